@@ -0,0 +1,388 @@
+use {
+  crate::{
+    EmoteEvent,
+    chat::{
+      ChatSender, IrcTags,
+    },
+    font_studio::*,
+    text_animation::*,
+  },
+  std::collections::{
+    HashMap, VecDeque,
+  },
+};
+
+/// Alerts waiting behind the one on screen. A big gift bomb can't back them up for minutes.
+const MAX_QUEUED_ALERTS: usize = 20;
+/// Different emotes loaded for an alert's burst. The burst repeats them to make up its size.
+pub const ALERT_BURST_VARIETY: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+  Sub,
+  Resub,
+  SubGift,
+  MysteryGift,
+  Raid,
+  Cheer,
+}
+
+impl AlertKind {
+  pub const ALL: [AlertKind; 6] = [AlertKind::Sub, AlertKind::Resub, AlertKind::SubGift, AlertKind::MysteryGift, AlertKind::Raid, AlertKind::Cheer];
+  /// Twitch's `msg-id` for the notice, also used as the template's name in config.kdl.
+  pub fn kdl_name(&self) -> &'static str {
+    match self {
+      AlertKind::Sub => { "sub" }
+      AlertKind::Resub => { "resub" }
+      AlertKind::SubGift => { "subgift" }
+      AlertKind::MysteryGift => { "submysterygift" }
+      AlertKind::Raid => { "raid" }
+      AlertKind::Cheer => { "cheer" }
+    }
+  }
+  pub fn from_kdl_name(name: &str) -> Option<Self> {
+    AlertKind::ALL.into_iter().find(|kind| kind.kdl_name() == name )
+  }
+  pub fn default_template(&self) -> &'static str {
+    match self {
+      AlertKind::Sub => { "{user} just subscribed!" }
+      AlertKind::Resub => { "{user} subscribed for {months} months!" }
+      AlertKind::SubGift => { "{user} gifted a sub to {recipient}!" }
+      AlertKind::MysteryGift => { "{user} gifted {count} subs!" }
+      AlertKind::Raid => { "{user} is raiding with {viewers} viewers!" }
+      AlertKind::Cheer => { "{user} cheered {bits} bits!" }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubPlan {
+  Prime,
+  Tier1,
+  Tier2,
+  Tier3,
+}
+
+impl SubPlan {
+  /// `msg-param-sub-plan` is `Prime`, `1000`, `2000` or `3000`.
+  fn from_tag(value: Option<&str>) -> Self {
+    match value {
+      Some("Prime") => { SubPlan::Prime }
+      Some("2000") => { SubPlan::Tier2 }
+      Some("3000") => { SubPlan::Tier3 }
+      _ => { SubPlan::Tier1 }
+    }
+  }
+  pub fn name(&self) -> &'static str {
+    match self {
+      SubPlan::Prime => { "Prime" }
+      SubPlan::Tier1 => { "Tier 1" }
+      SubPlan::Tier2 => { "Tier 2" }
+      SubPlan::Tier3 => { "Tier 3" }
+    }
+  }
+}
+
+/// A subscription, raid or cheer, from a `USERNOTICE` or a `PRIVMSG` with bits.
+/// `user` is always the display name of whoever did it.
+#[derive(Debug, Clone)]
+pub enum AlertEvent {
+  Sub { user: String, plan: SubPlan },
+  Resub { user: String, plan: SubPlan, months: u32, streak: Option<u32> },
+  /// `months` is how many months were gifted at once.
+  SubGift { user: String, recipient: String, plan: SubPlan, months: u32 },
+  MysteryGift { user: String, plan: SubPlan, count: u32 },
+  Raid { user: String, viewers: u32 },
+  Cheer { user: String, bits: u32 },
+}
+
+impl AlertEvent {
+  /// None for notices that aren't alerts, like announcements and gift upgrades. Subs given
+  /// out by a mystery gift are skipped too, the gift's own notice covers them.
+  pub fn from_usernotice(tags: &IrcTags) -> Option<Self> {
+    let number = |key: &str| tags.get(key).and_then(|value| value.parse::<u32>().ok() );
+    let user = tags.get("display-name").or(tags.get("login")).unwrap_or("Someone").to_owned();
+    let plan = SubPlan::from_tag(tags.get("msg-param-sub-plan"));
+    match tags.get("msg-id")? {
+      "sub" => { Some(AlertEvent::Sub { user, plan }) }
+      "resub" => {
+        let months = number("msg-param-cumulative-months").unwrap_or(1);
+        let streak = number("msg-param-streak-months").filter(|_| tags.flag("msg-param-should-share-streak") );
+        Some(AlertEvent::Resub { user, plan, months, streak })
+      }
+      "subgift" | "anonsubgift" => {
+        if tags.get("msg-param-community-gift-id").is_some() { return None; }
+        let recipient = tags.get("msg-param-recipient-display-name").or(tags.get("msg-param-recipient-user-name"))?.to_owned();
+        let months = number("msg-param-gift-months").unwrap_or(1);
+        Some(AlertEvent::SubGift { user, recipient, plan, months })
+      }
+      "submysterygift" | "anonsubmysterygift" => {
+        let count = number("msg-param-mass-gift-count").unwrap_or(1);
+        Some(AlertEvent::MysteryGift { user, plan, count })
+      }
+      "raid" => {
+        let user = tags.get("msg-param-displayName").map(str::to_owned).unwrap_or(user);
+        let viewers = number("msg-param-viewerCount").unwrap_or(0);
+        Some(AlertEvent::Raid { user, viewers })
+      }
+      _ => { None }
+    }
+  }
+  /// None for messages without bits.
+  pub fn from_cheer(sender: &ChatSender, bits: u32) -> Option<Self> {
+    (bits > 0).then(|| AlertEvent::Cheer { user: sender.display_name.to_owned(), bits })
+  }
+  pub fn kind(&self) -> AlertKind {
+    match self {
+      AlertEvent::Sub { .. } => { AlertKind::Sub }
+      AlertEvent::Resub { .. } => { AlertKind::Resub }
+      AlertEvent::SubGift { .. } => { AlertKind::SubGift }
+      AlertEvent::MysteryGift { .. } => { AlertKind::MysteryGift }
+      AlertEvent::Raid { .. } => { AlertKind::Raid }
+      AlertEvent::Cheer { .. } => { AlertKind::Cheer }
+    }
+  }
+  /// Values for the `{name}` placeholders in this kind's template.
+  fn fields(&self) -> Vec<(&'static str, String)> {
+    match self {
+      AlertEvent::Sub { user, plan } => {
+        vec![("user", user.to_owned()), ("tier", plan.name().to_owned())]
+      }
+      AlertEvent::Resub { user, plan, months, streak } => {
+        vec![
+          ("user", user.to_owned()), ("tier", plan.name().to_owned()),
+          ("months", months.to_string()), ("streak", streak.unwrap_or(0).to_string()),
+        ]
+      }
+      AlertEvent::SubGift { user, recipient, plan, months } => {
+        vec![
+          ("user", user.to_owned()), ("recipient", recipient.to_owned()),
+          ("tier", plan.name().to_owned()), ("months", months.to_string()),
+        ]
+      }
+      AlertEvent::MysteryGift { user, plan, count } => {
+        vec![("user", user.to_owned()), ("tier", plan.name().to_owned()), ("count", count.to_string())]
+      }
+      AlertEvent::Raid { user, viewers } => {
+        vec![("user", user.to_owned()), ("viewers", viewers.to_string())]
+      }
+      AlertEvent::Cheer { user, bits } => {
+        vec![("user", user.to_owned()), ("bits", bits.to_string())]
+      }
+    }
+  }
+}
+
+/// An alert ready to show, with the emotes for its burst already decoded.
+#[derive(Clone)]
+pub struct Alert {
+  /// Lowercase channel name without the `#`.
+  pub channel: String,
+  pub event: AlertEvent,
+  /// Whoever the notice or cheer came from, with their badges in the channel.
+  pub sender: ChatSender,
+  /// What the chatter wrote along with it, if anything.
+  pub message: String,
+  pub burst: Vec<EmoteEvent>,
+}
+
+impl Alert {
+  /// Fills `{user}`, `{channel}`, `{message}` and the event's own placeholders into `template`.
+  /// Events without `{months}` or `{tier}` take them from the sender's subscriber badge, so a
+  /// cheer can say how long they've subscribed. Placeholders left without a value stay as they are.
+  pub fn text(&self, template: &str) -> String {
+    let mut fields = self.event.fields();
+    if !fields.iter().any(|(name, _)| *name == "months" )
+      && let Some(months) = self.sender.subscriber_months()
+    {
+      fields.push(("months", months.to_string()));
+    }
+    if !fields.iter().any(|(name, _)| *name == "tier" )
+      && let Some(tier) = self.sender.subscriber_tier()
+    {
+      fields.push(("tier", format!("Tier {}", tier)));
+    }
+    fields.push(("channel", self.channel.to_owned()));
+    fields.push(("message", self.message.to_owned()));
+    fields.into_iter().fold(template.to_owned(), |text, (name, value)| text.replace(&format!("{{{}}}", name), &value) )
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertConfig {
+  pub enabled: bool,
+  /// Left edge of the text. None centers alerts on screen.
+  pub x: Option<i32>,
+  pub y: i32,
+  pub width: u32,
+  /// None uses the text style's size.
+  pub font_size: Option<f32>,
+  /// Seconds each alert stays up, including its exit animation.
+  pub duration: f32,
+  pub transition: TextTransition,
+  /// Emotes thrown onto the wall with each alert. 0 shows the text alone.
+  pub burst: u32,
+  /// Cheers with fewer bits don't get an alert.
+  pub min_bits: u32,
+  /// Kinds missing here don't get an alert.
+  pub templates: HashMap<AlertKind, String>,
+}
+
+impl Default for AlertConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      x: None,
+      y: 120,
+      width: 1200,
+      font_size: Some(48.),
+      duration: 6.,
+      transition: TextTransition {
+        enter: TextAnimation::ScalePop,
+        enter_time: 0.4,
+        exit: TextAnimation::Fade,
+        exit_time: 1.,
+      },
+      burst: 20,
+      min_bits: 1,
+      templates: AlertKind::ALL.into_iter().map(|kind| (kind, kind.default_template().to_owned()) ).collect(),
+    }
+  }
+}
+
+/// Shows alerts one at a time, in the order they came in.
+pub struct AlertOverlay {
+  config: AlertConfig,
+  queue: VecDeque<Alert>,
+  showing: Option<TextBlock>,
+}
+
+impl AlertOverlay {
+  pub fn new(config: AlertConfig) -> Self {
+    Self { config, queue: VecDeque::new(), showing: None }
+  }
+  pub fn config(&self) -> &AlertConfig {
+    &self.config
+  }
+  /// The alert on screen was rendered for the old settings, so a change clears it.
+  pub fn set_config(&mut self, config: AlertConfig) {
+    if config == self.config { return; }
+    self.showing = None;
+    self.config = config;
+  }
+  /// Alerts without a template, and cheers under `min_bits`, are dropped.
+  pub fn push(&mut self, alert: Alert) {
+    if !self.config.enabled { return; }
+    if !self.config.templates.contains_key(&alert.event.kind()) { return; }
+    if let AlertEvent::Cheer { bits, .. } = &alert.event
+      && *bits < self.config.min_bits
+    {
+      return;
+    }
+    if self.queue.len() >= MAX_QUEUED_ALERTS {
+      log::warn!("Too many alerts waiting. Skipping {} alert in #{}", alert.event.kind().kdl_name(), alert.channel);
+      return;
+    }
+    self.queue.push_back(alert);
+  }
+  /// Starts the next alert once the one on screen is gone. Returns the emotes to throw onto
+  /// the wall for an alert that just started.
+  pub fn update(&mut self, font_studio: &mut FontStudio, seconds: f32, screen_w: u32) -> Vec<EmoteEvent> {
+    if let Some(block) = self.showing.as_mut() {
+      block.update(seconds);
+      if block.is_alive() { return vec![]; }
+      self.showing = None;
+    }
+    let Some(alert) = self.queue.pop_front() else { return vec![]; };
+    let Some(template) = self.config.templates.get(&alert.event.kind()) else { return vec![]; };
+    let style = font_studio.style().resized(self.config.font_size).centered();
+    let text = alert.text(template);
+    let mut block = font_studio.render_spans(self.config.width, (0, 0), &style, Some(self.config.duration), &[TextSpan::plain(&text)])
+      .with_transition(self.config.transition);
+    let padding = block.padding() as i32;
+    let x = match self.config.x {
+      Some(x) => { x - padding }
+      None => { (screen_w as i32 - block.width() as i32) / 2 }
+    };
+    block.set_position(x, self.config.y - padding);
+    self.showing = Some(block);
+    let count = match alert.burst.is_empty() {
+      true => { 0 }
+      false => { self.config.burst as usize }
+    };
+    alert.burst.into_iter().cycle().take(count).collect()
+  }
+  pub fn draw(&self) {
+    if let Some(block) = self.showing.as_ref() {
+      block.draw();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn usernotice(tags: &str) -> Option<AlertEvent> {
+    let message: irc::proto::Message = format!("@{} :tmi.twitch.tv USERNOTICE #test :message", tags).parse().unwrap();
+    AlertEvent::from_usernotice(&IrcTags::from_message(&message))
+  }
+
+  #[test]
+  fn reads_sub_and_resub_notices() {
+    let Some(AlertEvent::Sub { user, plan }) = usernotice("msg-id=sub;display-name=Alice;login=alice;msg-param-sub-plan=Prime") else { panic!(); };
+    assert_eq!((user.as_str(), plan), ("Alice", SubPlan::Prime));
+    let resub = usernotice("msg-id=resub;login=bob;msg-param-sub-plan=2000;msg-param-cumulative-months=14;msg-param-streak-months=3;msg-param-should-share-streak=1");
+    let Some(AlertEvent::Resub { user, plan, months, streak }) = resub else { panic!(); };
+    assert_eq!((user.as_str(), plan, months, streak), ("bob", SubPlan::Tier2, 14, Some(3)));
+    let unshared = usernotice("msg-id=resub;login=bob;msg-param-cumulative-months=14;msg-param-streak-months=3;msg-param-should-share-streak=0");
+    assert!(matches!(unshared, Some(AlertEvent::Resub { plan: SubPlan::Tier1, streak: None, .. })));
+  }
+
+  #[test]
+  fn reads_gift_and_raid_notices() {
+    let gift = usernotice("msg-id=subgift;display-name=Carol;msg-param-recipient-display-name=Dave;msg-param-sub-plan=3000;msg-param-gift-months=6");
+    let Some(AlertEvent::SubGift { user, recipient, plan, months }) = gift else { panic!(); };
+    assert_eq!((user.as_str(), recipient.as_str(), plan, months), ("Carol", "Dave", SubPlan::Tier3, 6));
+    // Subs handed out by a mystery gift are covered by the gift's own notice.
+    assert!(usernotice("msg-id=subgift;display-name=Carol;msg-param-recipient-display-name=Dave;msg-param-community-gift-id=123").is_none());
+    assert!(matches!(usernotice("msg-id=anonsubmysterygift;msg-param-mass-gift-count=5"), Some(AlertEvent::MysteryGift { count: 5, .. })));
+    let Some(AlertEvent::Raid { user, viewers }) = usernotice("msg-id=raid;display-name=eve;msg-param-displayName=Eve;msg-param-viewerCount=42") else { panic!(); };
+    assert_eq!((user.as_str(), viewers), ("Eve", 42));
+  }
+
+  #[test]
+  fn skips_notices_that_are_not_alerts() {
+    assert!(usernotice("msg-id=announcement;display-name=Alice").is_none());
+    assert!(usernotice("display-name=Alice").is_none());
+  }
+
+  #[test]
+  fn fills_templates() {
+    let alert = Alert {
+      channel: "test".to_owned(),
+      event: AlertEvent::Cheer { user: "Alice".to_owned(), bits: 100 },
+      sender: ChatSender::default(),
+      message: "hype".to_owned(),
+      burst: vec![],
+    };
+    assert_eq!(alert.text("{user} cheered {bits} in #{channel}: {message} {months}"), "Alice cheered 100 in #test: hype {months}");
+  }
+
+  #[test]
+  fn sender_badge_fills_missing_months_and_tier() {
+    let message: irc::proto::Message = "@badges=subscriber/2012;badge-info=subscriber/14 :alice!alice@alice.tmi.twitch.tv PRIVMSG #test :Cheer100".parse().unwrap();
+    let sender = ChatSender::from_tags("alice", &IrcTags::from_message(&message));
+    let cheer = Alert {
+      channel: "test".to_owned(),
+      event: AlertEvent::Cheer { user: "Alice".to_owned(), bits: 100 },
+      sender: sender.clone(),
+      message: String::new(),
+      burst: vec![],
+    };
+    assert_eq!(cheer.text("{user}, {tier} sub for {months} months"), "Alice, Tier 2 sub for 14 months");
+    // The event's own values win over the badge.
+    let resub = Alert { event: AlertEvent::Resub { user: "Alice".to_owned(), plan: SubPlan::Tier1, months: 15, streak: None }, ..cheer };
+    assert_eq!(resub.text("{tier}, {months}"), "Tier 1, 15");
+  }
+}
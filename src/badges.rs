@@ -0,0 +1,226 @@
+use {
+  crate::{
+    EmoteSource,
+    config_kdl::EkbTwitchConfig,
+    providers::EmoteProvider,
+  },
+  std::{
+    collections::{
+      HashMap, HashSet,
+    },
+    path::Path,
+    sync::Arc,
+    time::Duration,
+  },
+  tokio::{
+    sync::mpsc::{
+      UnboundedReceiver, UnboundedSender,
+    },
+    task::JoinSet,
+  },
+  twitch_api::{
+    helix::{
+      HelixClient,
+      chat::{
+        BadgeSet, GetChannelChatBadgesRequest, GetGlobalChatBadgesRequest,
+      },
+    },
+    twitch_oauth2::{
+      AccessToken, UserToken,
+    },
+  },
+};
+
+/// Badge sets a channel can replace with its own images, so they're cached per channel.
+const CHANNEL_BADGE_SETS: [&str; 2] = ["subscriber", "bits"];
+/// Helix taking longer than this leaves the overlay without those badges, or alerts without
+/// the channel's Twitch emotes, until the monitor restarts.
+pub(crate) const HELIX_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct BadgeConfig {
+  /// False leaves badges out of the chat overlay.
+  pub enabled: bool,
+  /// Folder or base URL with images at `<set>/<version>.png`, and optionally
+  /// `<channel>/<set>/<version>.png` for a channel's own subscriber and bits badges.
+  /// Used instead of Helix, which needs an authenticated account.
+  pub mirror: Option<String>,
+}
+
+impl Default for BadgeConfig {
+  fn default() -> Self {
+    Self { enabled: true, mirror: None }
+  }
+}
+
+type BadgeKey = (String, String);
+/// Badges fetched in the background, with the channel they're for. None is the global set.
+type LoadedBadges = (Option<String>, HashMap<BadgeKey, String>);
+
+/// Where each badge's image comes from. Badge images are only listed by Helix, so without
+/// a token or a mirror the overlay goes without them. Offline, cached badges are still used.
+pub struct BadgeUrls {
+  config: BadgeConfig,
+  offline: bool,
+  helix: Option<Arc<BadgeHelix>>,
+  global: HashMap<BadgeKey, String>,
+  /// Keyed by lowercase channel name.
+  channels: HashMap<String, HashMap<BadgeKey, String>>,
+  /// Channels with a spawned load that hasn't arrived yet.
+  loading: HashSet<String>,
+  loaded_tx: UnboundedSender<LoadedBadges>,
+  loaded_rx: UnboundedReceiver<LoadedBadges>,
+}
+
+impl BadgeUrls {
+  /// Validates the token for Helix unless badges are off, come from a mirror, or we're offline.
+  pub async fn new(config: BadgeConfig, conf: &EkbTwitchConfig) -> Self {
+    let offline = conf.offline();
+    let helix = match config.enabled && config.mirror.is_none() && !offline {
+      true => { BadgeHelix::connect(conf).await.map(Arc::new) }
+      false => { None }
+    };
+    let (loaded_tx, loaded_rx) = tokio::sync::mpsc::unbounded_channel();
+    Self { config, offline, helix, global: HashMap::new(), channels: HashMap::new(), loading: HashSet::new(), loaded_tx, loaded_rx }
+  }
+  pub fn enabled(&self) -> bool {
+    self.config.enabled
+  }
+  /// Fetched on `tasks` so chat isn't held up. Used once `receive_loaded` picks it up.
+  pub fn spawn_global_load(&mut self, tasks: &mut JoinSet<()>) {
+    let Some(helix) = self.helix.clone() else { return; };
+    let loaded_tx = self.loaded_tx.clone();
+    tasks.spawn(async move {
+      _ = loaded_tx.send((None, helix.global_badges().await));
+    });
+  }
+  /// True once a load for `channel` was started, or when there's no Helix to load from.
+  pub fn has_channel(&self, channel: &str) -> bool {
+    self.helix.is_none() || self.channels.contains_key(channel) || self.loading.contains(channel)
+  }
+  /// Subscriber and bits badges the channel uploaded itself, fetched on `tasks`.
+  pub fn spawn_channel_load(&mut self, tasks: &mut JoinSet<()>, channel: &str, room_id: &str) {
+    if self.has_channel(channel) { return; }
+    let Some(helix) = self.helix.clone() else { return; };
+    self.loading.insert(channel.to_owned());
+    let loaded_tx = self.loaded_tx.clone();
+    let (channel, room_id) = (channel.to_owned(), room_id.to_owned());
+    tasks.spawn(async move {
+      let urls = helix.channel_badges(&channel, &room_id).await;
+      _ = loaded_tx.send((Some(channel), urls));
+    });
+  }
+  /// Puts badges from spawned loads that finished since the last call to use.
+  pub fn receive_loaded(&mut self) {
+    while let Ok((channel, urls)) = self.loaded_rx.try_recv() {
+      match channel {
+        Some(channel) => {
+          self.loading.remove(&channel);
+          self.channels.insert(channel, urls);
+        }
+        None => { self.global = urls; }
+      }
+    }
+  }
+  /// None when nothing says where the badge's image is. Offline, the cache is tried anyway.
+  pub(crate) fn source(&self, channel: &str, set: &str, version: &str) -> Option<EmoteSource> {
+    let per_channel = CHANNEL_BADGE_SETS.contains(&set);
+    let key = (set.to_owned(), version.to_owned());
+    let urls = match self.config.mirror.as_deref() {
+      Some(mirror) => {
+        let mut urls = vec![];
+        if per_channel {
+          urls.push(mirror_url(mirror, &format!("{}/{}/{}.png", channel, set, version)));
+        }
+        urls.push(mirror_url(mirror, &format!("{}/{}.png", set, version)));
+        urls
+      }
+      None => {
+        self.channels.get(channel).and_then(|urls| urls.get(&key) )
+          .filter(|_| per_channel )
+          .or_else(|| self.global.get(&key) )
+          .cloned().into_iter().collect()
+      }
+    };
+    if urls.is_empty() && !self.offline { return None; }
+    let id = match per_channel {
+      true => { format!("{}/{}/{}", channel, set, version) }
+      false => { format!("{}/{}", set, version) }
+    };
+    Some(EmoteSource {
+      provider: EmoteProvider::Badge,
+      id,
+      name: set.to_owned(),
+      urls,
+      image: None,
+    })
+  }
+}
+
+struct BadgeHelix {
+  client: HelixClient<'static, reqwest::Client>,
+  token: UserToken,
+}
+
+impl BadgeHelix {
+  async fn connect(conf: &EkbTwitchConfig) -> Option<Self> {
+    if conf.is_anonymous() {
+      log::info!("Chat badges need an authenticated account or a badge mirror. Only cached badges will be shown.");
+      return None;
+    }
+    let http = reqwest::Client::builder().timeout(HELIX_TIMEOUT).build().unwrap_or_else(|e| {
+      log::error!("Failed to set up the badge client, continuing without a timeout: {}", e);
+      reqwest::Client::new()
+    });
+    let client: HelixClient<reqwest::Client> = HelixClient::with_client(http);
+    match UserToken::from_token(&client, AccessToken::new(conf.oauth())).await {
+      Ok(token) => { Some(Self { client, token }) }
+      Err(e) => {
+        log::error!("Failed to validate oauth token for chat badges: {}", e);
+        None
+      }
+    }
+  }
+  async fn global_badges(&self) -> HashMap<BadgeKey, String> {
+    match self.client.req_get(GetGlobalChatBadgesRequest::new(), &self.token).await {
+      Ok(response) => {
+        let urls = badge_urls(response.data);
+        log::info!("Loaded {} global Twitch badges", urls.len());
+        urls
+      }
+      Err(e) => {
+        log::error!("Failed to fetch global Twitch badges: {}", e);
+        HashMap::new()
+      }
+    }
+  }
+  async fn channel_badges(&self, channel: &str, room_id: &str) -> HashMap<BadgeKey, String> {
+    let request = GetChannelChatBadgesRequest::broadcaster_id(room_id);
+    let urls = match self.client.req_get(request, &self.token).await {
+      Ok(response) => { badge_urls(response.data) }
+      Err(e) => {
+        log::error!("Failed to fetch Twitch badges for #{}: {}", channel, e);
+        HashMap::new()
+      }
+    };
+    log::info!("Loaded {} channel badges for #{}", urls.len(), channel);
+    urls
+  }
+}
+
+fn badge_urls(sets: Vec<BadgeSet>) -> HashMap<BadgeKey, String> {
+  sets.into_iter()
+    .flat_map(|set| {
+      let set_id = set.set_id.to_string();
+      set.versions.into_iter().map(move |version| ((set_id.clone(), version.id.to_string()), version.image_url_4x) )
+    })
+    .collect()
+}
+
+/// Mirrors that aren't http(s) are local folders, read through `file://` URLs.
+fn mirror_url(mirror: &str, path: &str) -> String {
+  match mirror.starts_with("http://") || mirror.starts_with("https://") {
+    true => { format!("{}/{}", mirror.trim_end_matches('/'), path) }
+    false => { format!("file://{}", Path::new(mirror).join(path).display()) }
+  }
+}
@@ -0,0 +1,236 @@
+use {
+  crate::emote_image::DecodedEmote,
+  std::{
+    collections::HashMap,
+    ops::Range,
+    sync::Arc,
+  },
+};
+
+/// IRCv3 tags of a Twitch message, read straight from the `irc` crate's message
+/// so we aren't limited to what `twitch_message` exposes.
+#[derive(Debug, Clone, Default)]
+pub struct IrcTags(HashMap<String, String>);
+
+impl IrcTags {
+  pub fn from_message(msg: &irc::proto::Message) -> Self {
+    let mut tags = HashMap::new();
+    if let Some(msg_tags) = &msg.tags {
+      for irc::proto::message::Tag(key, value) in msg_tags {
+        tags.insert(key.to_owned(), value.to_owned().unwrap_or_default());
+      }
+    }
+    Self(tags)
+  }
+  /// Empty tag values are treated as missing.
+  pub fn get(&self, key: &str) -> Option<&str> {
+    self.0.get(key).map(|val| val.as_str() ).filter(|val| !val.is_empty() )
+  }
+  pub fn flag(&self, key: &str) -> bool {
+    self.get(key) == Some("1")
+  }
+}
+
+/// `badges` and `badge-info` are both `name/version` pairs separated by commas.
+pub fn parse_badges(value: &str) -> Vec<(String, String)> {
+  value.split(',')
+    .filter_map(|badge| badge.split_once('/') )
+    .map(|(name, version)| (name.to_owned(), version.to_owned()) )
+    .collect()
+}
+
+/// The `color` tag, `#RRGGBB`. Missing for chatters who never picked a color.
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+  let hex = value.strip_prefix('#')?;
+  if hex.len() != 6 { return None; }
+  let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+  Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Twitch's defaults for chatters without a `color`, picked from the login so it stays the same.
+const DEFAULT_NAME_COLORS: [[u8; 3]; 15] = [
+  [0xFF, 0x00, 0x00], [0x00, 0x00, 0xFF], [0x00, 0x80, 0x00], [0xB2, 0x22, 0x22], [0xFF, 0x7F, 0x50],
+  [0x9A, 0xCD, 0x32], [0xFF, 0x45, 0x00], [0x2E, 0x8B, 0x57], [0xDA, 0xA5, 0x20], [0xD2, 0x69, 0x1E],
+  [0x5F, 0x9E, 0xA0], [0x1E, 0x90, 0xFF], [0xFF, 0x69, 0xB4], [0x8A, 0x2B, 0xE2], [0x00, 0xFF, 0x7F],
+];
+
+/// `emotes` is `id:start-end,start-end/id:start-end`, with inclusive character positions.
+/// Sorted by position.
+pub fn parse_emote_positions(value: &str) -> Vec<(String, Range<usize>)> {
+  let mut positions: Vec<(String, Range<usize>)> = value.split('/')
+    .filter_map(|emote| emote.split_once(':') )
+    .flat_map(|(id, ranges)| ranges.split(',').filter_map(move |range| {
+      let (start, end) = range.split_once('-')?;
+      let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+      (start <= end).then(|| (id.to_owned(), start..end + 1) )
+    }))
+    .collect();
+  positions.sort_by_key(|(_, range)| range.start );
+  positions
+}
+
+/// A chat message cut into text and the Twitch emotes between it.
+#[derive(Debug, Clone)]
+pub enum MessagePart {
+  Text(String),
+  Emote { id: String, name: String },
+}
+
+/// Splits `text` at the `emotes` tag positions. Positions that overlap or run past the
+/// end of the text are left as text.
+pub fn split_message(text: &str, positions: &[(String, Range<usize>)]) -> Vec<MessagePart> {
+  let chars: Vec<char> = text.chars().collect();
+  let mut parts = vec![];
+  let mut next = 0;
+  for (id, range) in positions {
+    if range.start < next || range.end > chars.len() { continue; }
+    if range.start > next {
+      parts.push(MessagePart::Text(chars[next..range.start].iter().collect()));
+    }
+    parts.push(MessagePart::Emote { id: id.to_owned(), name: chars[range.clone()].iter().collect() });
+    next = range.end;
+  }
+  if next < chars.len() {
+    parts.push(MessagePart::Text(chars[next..].iter().collect()));
+  }
+  parts
+}
+
+/// A piece of a chat line as the overlay draws it.
+#[derive(Clone)]
+pub enum ChatPart {
+  Text(String),
+  Emote { name: String, image: Arc<DecodedEmote> },
+}
+
+/// A chat message ready for the on-screen chat, with its emotes already decoded.
+#[derive(Clone)]
+pub struct ChatLine {
+  pub channel: String,
+  pub sender: ChatSender,
+  pub color: [u8; 3],
+  /// Sent with /me, which Twitch shows all in the chatter's color.
+  pub action: bool,
+  /// Drawn before the name, in the order Twitch sent them.
+  pub badges: Vec<Arc<DecodedEmote>>,
+  pub parts: Vec<ChatPart>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChatSender {
+  pub login: String,
+  pub user_id: String,
+  pub display_name: String,
+  pub badges: Vec<(String, String)>,
+  /// `badge-info`, which has the exact months behind a subscriber badge.
+  pub badge_info: Vec<(String, String)>,
+}
+
+impl ChatSender {
+  pub fn from_tags(login: &str, tags: &IrcTags) -> Self {
+    let login = login.to_ascii_lowercase();
+    Self {
+      user_id: tags.get("user-id").unwrap_or(&login).to_owned(),
+      display_name: tags.get("display-name").unwrap_or(&login).to_owned(),
+      badges: tags.get("badges").map(parse_badges).unwrap_or_default(),
+      badge_info: tags.get("badge-info").map(parse_badges).unwrap_or_default(),
+      login,
+    }
+  }
+  pub fn name_color(&self, tags: &IrcTags) -> [u8; 3] {
+    tags.get("color").and_then(parse_color).unwrap_or_else(|| {
+      let hash = self.login.bytes().fold(0usize, |hash, byte| hash.wrapping_mul(31).wrapping_add(byte as usize) );
+      DEFAULT_NAME_COLORS[hash % DEFAULT_NAME_COLORS.len()]
+    })
+  }
+  pub fn badge(&self, name: &str) -> Option<&str> {
+    self.badges.iter().find(|(badge, _)| badge == name).map(|(_, version)| version.as_str() )
+  }
+  pub fn has_badge(&self, name: &str) -> bool {
+    self.badge(name).is_some()
+  }
+  pub fn is_broadcaster(&self) -> bool {
+    self.has_badge("broadcaster")
+  }
+  pub fn is_moderator(&self) -> bool {
+    self.has_badge("moderator")
+  }
+  pub fn is_vip(&self) -> bool {
+    self.has_badge("vip")
+  }
+  /// Founders keep their own badge instead of the subscriber badge.
+  pub fn is_subscriber(&self) -> bool {
+    self.has_badge("subscriber") || self.has_badge("founder")
+  }
+  /// Subscriber badge versions are the months shown on the badge, plus 2000 for tier 2 and 3000 for tier 3.
+  pub fn subscriber_tier(&self) -> Option<u8> {
+    let version: u32 = self.badge("subscriber")?.parse().ok()?;
+    Some(match version {
+      3000.. => { 3 }
+      2000.. => { 2 }
+      _ => { 1 }
+    })
+  }
+  pub fn subscriber_months(&self) -> Option<u32> {
+    self.badge_info.iter()
+      .find(|(name, _)| name == "subscriber" || name == "founder" )
+      .and_then(|(_, months)| months.parse().ok() )
+  }
+}
+
+/// Parts of a message that can unlock gated effects.
+#[derive(Debug, Clone, Default)]
+pub struct MessageTrigger {
+  pub bits: u32,
+  pub custom_reward_id: Option<String>,
+}
+
+impl MessageTrigger {
+  pub fn from_tags(tags: &IrcTags) -> Self {
+    Self {
+      bits: tags.get("bits").and_then(|bits| bits.parse().ok() ).unwrap_or(0),
+      custom_reward_id: tags.get("custom-reward-id").map(str::to_owned),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn part_names(parts: &[MessagePart]) -> Vec<String> {
+    parts.iter().map(|part| match part {
+      MessagePart::Text(text) => { format!("text:{}", text) }
+      MessagePart::Emote { id, name } => { format!("{}:{}", id, name) }
+    }).collect()
+  }
+
+  #[test]
+  fn parses_and_sorts_emote_positions() {
+    let positions = parse_emote_positions("25:6-10,17-21/1902:0-4");
+    assert_eq!(positions, [("1902".to_owned(), 0..5), ("25".to_owned(), 6..11), ("25".to_owned(), 17..22)]);
+    assert!(parse_emote_positions("").is_empty());
+    assert_eq!(parse_emote_positions("25:5-2,x-3,4-4"), [("25".to_owned(), 4..5)]);
+  }
+
+  #[test]
+  fn splits_text_around_emotes() {
+    let text = "Keepo Kappa and Kappa";
+    let parts = split_message(text, &parse_emote_positions("25:6-10,16-20/1902:0-4"));
+    assert_eq!(part_names(&parts), ["1902:Keepo", "text: ", "25:Kappa", "text: and ", "25:Kappa"]);
+  }
+
+  #[test]
+  fn split_positions_count_characters_not_bytes() {
+    let parts = split_message("😀 Kappa!", &parse_emote_positions("25:2-6"));
+    assert_eq!(part_names(&parts), ["text:😀 ", "25:Kappa", "text:!"]);
+  }
+
+  #[test]
+  fn split_leaves_bad_positions_as_text() {
+    let parts = split_message("Kappa", &[("25".to_owned(), 0..5), ("1".to_owned(), 3..5), ("2".to_owned(), 4..9)]);
+    assert_eq!(part_names(&parts), ["25:Kappa"]);
+    let parts = split_message("hi", &[("25".to_owned(), 0..5)]);
+    assert_eq!(part_names(&parts), ["text:hi"]);
+  }
+}
@@ -0,0 +1,216 @@
+use {
+  crate::{
+    chat::{
+      ChatLine, ChatPart,
+    },
+    font_studio::*,
+    text_animation::*,
+    text_style::TextStyle,
+  },
+  std::{
+    collections::VecDeque,
+    sync::{
+      Arc, Mutex,
+      mpsc::{
+        Receiver, Sender,
+      },
+    },
+  },
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatOverlayConfig {
+  pub enabled: bool,
+  /// Top left corner of the area chat is drawn in. Lines stack up from its bottom edge.
+  pub x: i32,
+  pub y: i32,
+  pub width: u32,
+  pub height: u32,
+  /// Most lines on screen at once. 0 only limits by height.
+  pub max_lines: u32,
+  /// Seconds a line stays up. 0 keeps lines until newer ones push them out.
+  pub lifetime: f32,
+  /// How lines come in, and how they leave at the end of `lifetime`.
+  pub transition: TextTransition,
+  /// None uses the text style's size.
+  pub font_size: Option<f32>,
+}
+
+impl Default for ChatOverlayConfig {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      x: 20,
+      y: 20,
+      width: 480,
+      height: 600,
+      max_lines: 12,
+      lifetime: 30.,
+      transition: TextTransition {
+        enter: TextAnimation::Fade,
+        enter_time: 0.3,
+        exit: TextAnimation::Fade,
+        exit_time: 2.,
+      },
+      font_size: None,
+    }
+  }
+}
+
+/// A chat line waiting for the rasterizer thread.
+struct ChatJob {
+  /// Lines rasterized for settings that have since changed are thrown away.
+  generation: u64,
+  width: u32,
+  style: TextStyle,
+  spans: Vec<TextSpan>,
+}
+
+/// Rasterizes chat lines on its own thread, so shaping text and scaling emotes doesn't hold
+/// up `video_tick`. Lines come back in the order they were sent. The thread stops when this
+/// is dropped.
+struct ChatRasterizer {
+  jobs: Sender<ChatJob>,
+  rendered: Receiver<(u64, RenderedText)>,
+}
+
+impl ChatRasterizer {
+  fn spawn() -> Option<Self> {
+    let (jobs, job_rx) = std::sync::mpsc::channel::<ChatJob>();
+    let (rendered_tx, rendered) = std::sync::mpsc::channel();
+    let spawned = std::thread::Builder::new().name("ekb-chat-text".to_owned()).spawn(move || {
+      let mut rasterizer = TextRasterizer::new();
+      while let Ok(job) = job_rx.recv() {
+        let text = rasterizer.rasterize(job.width, &job.style, &job.spans);
+        if rendered_tx.send((job.generation, text)).is_err() { break; }
+      }
+    });
+    match spawned {
+      Ok(_) => { Some(Self { jobs, rendered }) }
+      Err(e) => {
+        log::error!("Failed to start the chat text thread. Chat won't be shown: {}", e);
+        None
+      }
+    }
+  }
+}
+
+/// On-screen chat, newest line at the bottom.
+pub struct ChatOverlay {
+  config: ChatOverlayConfig,
+  lines: VecDeque<TextBlock>,
+  /// `config.enabled`, shared with the Twitch monitor so it only loads chat lines while they're shown.
+  enabled: Arc<Mutex<bool>>,
+  /// Started with the first line, since it loads its own copy of the system fonts.
+  rasterizer: Option<ChatRasterizer>,
+  generation: u64,
+}
+
+impl ChatOverlay {
+  pub fn new(config: ChatOverlayConfig) -> Self {
+    let enabled = Arc::new(Mutex::new(config.enabled));
+    Self { config, lines: VecDeque::new(), enabled, rasterizer: None, generation: 0 }
+  }
+  pub fn config(&self) -> &ChatOverlayConfig {
+    &self.config
+  }
+  /// Handed to the Twitch monitor. Kept up to date as the settings change.
+  pub fn shared_enabled(&self) -> Arc<Mutex<bool>> {
+    self.enabled.clone()
+  }
+  /// Lines already on screen were rendered for the old settings, so a change clears them.
+  pub fn set_config(&mut self, config: ChatOverlayConfig) {
+    if config == self.config { return; }
+    if let Ok(mut enabled) = self.enabled.lock() {
+      *enabled = config.enabled;
+    }
+    self.lines.clear();
+    self.generation += 1;
+    self.config = config;
+  }
+  /// Sends the line off to be rasterized. It shows up in a later `update`.
+  pub fn push(&mut self, font_studio: &FontStudio, line: &ChatLine) {
+    if !self.config.enabled { return; }
+    let mut spans: Vec<TextSpan> = line.badges.iter().map(|badge| TextSpan::Image(badge.clone()) ).collect();
+    if !spans.is_empty() {
+      spans.push(TextSpan::plain(" "));
+    }
+    spans.push(TextSpan::Text { text: line.sender.display_name.to_owned(), color: Some(line.color), bold: true });
+    // /me lines are all in the chatter's color, like on Twitch.
+    let color = line.action.then_some(line.color);
+    spans.push(TextSpan::Text { text: if line.action { " " } else { ": " }.to_owned(), color, bold: false });
+    spans.extend(line.parts.iter().map(|part| match part {
+      ChatPart::Text(text) => { TextSpan::Text { text: text.to_owned(), color, bold: false } }
+      ChatPart::Emote { image, .. } => { TextSpan::Image(image.clone()) }
+    }));
+    if self.rasterizer.is_none() {
+      self.rasterizer = ChatRasterizer::spawn();
+    }
+    let Some(rasterizer) = self.rasterizer.as_ref() else { return; };
+    let job = ChatJob {
+      generation: self.generation,
+      width: self.config.width,
+      style: font_studio.style().resized(self.config.font_size),
+      spans,
+    };
+    if rasterizer.jobs.send(job).is_err() {
+      log::error!("The chat text thread stopped. Restarting it with the next line.");
+      self.rasterizer = None;
+    }
+  }
+  /// Uploads lines the rasterizer finished, then ages every line.
+  pub fn update(&mut self, font_studio: &FontStudio, seconds: f32) {
+    let finished: Vec<RenderedText> = match self.rasterizer.as_ref() {
+      Some(rasterizer) => {
+        rasterizer.rendered.try_iter()
+          .filter(|(generation, _)| *generation == self.generation )
+          .map(|(_, text)| text )
+          .collect()
+      }
+      None => { vec![] }
+    };
+    if !finished.is_empty() {
+      let life = (self.config.lifetime > 0.).then_some(self.config.lifetime);
+      for text in finished {
+        let block = font_studio.upload(text, (self.config.x, self.config.y), life)
+          .with_transition(self.config.transition);
+        self.lines.push_back(block);
+      }
+      self.layout();
+    }
+    for line in self.lines.iter_mut() {
+      line.update(seconds);
+    }
+    self.lines.retain(|line| line.is_alive() );
+  }
+  /// Stacks lines up from the bottom of the area, dropping the oldest ones that don't fit.
+  fn layout(&mut self) {
+    if self.config.max_lines > 0 {
+      while self.lines.len() > self.config.max_lines as usize {
+        self.lines.pop_front();
+      }
+    }
+    let mut bottom = self.config.y + self.config.height as i32;
+    let count = self.lines.len();
+    let mut fits = count;
+    for (index, line) in self.lines.iter_mut().enumerate().rev() {
+      // Positions are of the text, so neighbouring lines overlap their padding.
+      let padding = line.padding() as i32;
+      let y = bottom - line.height() as i32 + padding;
+      if y + padding < self.config.y {
+        fits = count - index - 1;
+        break;
+      }
+      line.set_position(self.config.x - padding, y);
+      bottom = y + padding;
+    }
+    while self.lines.len() > fits {
+      self.lines.pop_front();
+    }
+  }
+  pub fn draw(&self) {
+    for line in self.lines.iter() {
+      line.draw();
+    }
+  }
+}
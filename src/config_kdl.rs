@@ -1,11 +1,37 @@
 use {
+  crate::{
+    alerts::{
+      AlertConfig, AlertKind,
+    },
+    badges::BadgeConfig,
+    chat_overlay::ChatOverlayConfig,
+    connection::ConnectionConfig,
+    cooldown::{
+      CooldownConfig, UserLimitConfig,
+    },
+    effects::{
+      ChannelProfile, EffectGates, EffectKind, EffectRequirement, EffectWeights,
+    },
+    emote_db::CacheConfig,
+    providers::{
+      EmoteProvider, ProviderConfig,
+    },
+    text_animation::{
+      TextAnimation, TextTransition,
+    },
+    text_style::{
+      MAX_TEXT_EFFECT, TextStyle, parse_hex_color,
+    },
+  },
   kdl::{
     KdlDocument,
     // KdlEntry,
+    KdlNode,
     KdlValue,
   },
   std::{
     borrow::Cow,
+    collections::HashMap,
     path::PathBuf,
     io::{BufRead, BufReader, Read, Write, },
     net::{TcpListener, TcpStream, },
@@ -25,21 +51,71 @@ pub struct EkbConfigDirs {
   pub data:   PathBuf,
 }
 
+/// `mode` in config.kdl. Anonymous logins read chat as `justinfan<number>` without OAuth,
+/// which is all the emote wall needs. OAuth is only required for writing to chat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TwitchAuthMode {
+  #[default]
+  Authenticated,
+  Anonymous,
+}
+
+impl TwitchAuthMode {
+  pub fn kdl_name(&self) -> &'static str {
+    match self {
+      TwitchAuthMode::Authenticated => { "authenticated" }
+      TwitchAuthMode::Anonymous => { "anonymous" }
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct EkbTwitchConfig {
+  mode:        TwitchAuthMode,
   bot_account: String,
-  channel:     String,
+  /// Every channel to join. The first one is the one shown and edited in Properties.
+  channels:    Vec<String>,
   oauth:       String,
+  user_limits: UserLimitConfig,
+  connection:  ConnectionConfig,
+  providers:   ProviderConfig,
+  cache:       CacheConfig,
+  /// Only cached emotes are drawn, and emote CDNs and provider APIs are never contacted.
+  offline:     bool,
+  badges:      BadgeConfig,
 }
 impl EkbTwitchConfig {
+  pub fn mode(&self) -> TwitchAuthMode { self.mode }
+  pub fn is_anonymous(&self) -> bool { self.mode == TwitchAuthMode::Anonymous }
   pub fn bot_account(&self) -> String { self.bot_account.to_owned() }
-  pub fn channel(&self) -> String { self.channel.to_owned() }
+  pub fn channel(&self) -> String { self.channels[0].to_owned() }
+  pub fn channels(&self) -> Vec<String> { self.channels.clone() }
   pub fn oauth(&self) -> String { self.oauth.to_owned() }
+  pub fn user_limits(&self) -> UserLimitConfig { self.user_limits.clone() }
+  pub fn connection(&self) -> ConnectionConfig { self.connection.clone() }
+  pub fn providers(&self) -> ProviderConfig { self.providers.clone() }
+  pub fn cache(&self) -> CacheConfig { self.cache.clone() }
+  pub fn offline(&self) -> bool { self.offline }
+  pub fn badges(&self) -> BadgeConfig { self.badges.clone() }
 }
 
 impl TryFrom<KdlDocument> for EkbTwitchConfig {
   type Error = String;
   fn try_from(conf: KdlDocument) -> Result<Self, Self::Error> {
+    let mode = conf.mode()?;
+    let user_limits = conf.user_limits()?;
+    let connection = conf.connection()?;
+    let providers = conf.providers()?;
+    let cache = conf.cache()?;
+    let offline = conf.offline()?;
+    let badges = conf.badges()?;
+    if mode == TwitchAuthMode::Anonymous {
+      // bot-account and oauth may be left over from an authenticated setup, but aren't needed.
+      let bot_account = conf.bot_account().unwrap_or_default();
+      let oauth = conf.oauth().unwrap_or_default();
+      let channels = conf.channels().map_err(|e| format!("mode anonymous needs a channel to read. {}", e) )?;
+      return Ok(Self { mode, bot_account, channels, oauth, user_limits, connection, providers, cache, offline, badges });
+    }
     let oauth = match conf.oauth() {
       Err(e) => { return Err(e); }
       Ok(val) => { val }
@@ -48,15 +124,637 @@ impl TryFrom<KdlDocument> for EkbTwitchConfig {
       Err(e) => { return Err(e); }
       Ok(val) => { val }
     };
-    let channel = conf.channel();
-    let channel = channel.unwrap_or(bot_account.clone());
-    Ok(Self { bot_account, channel, oauth })
+    let channels = conf.channels();
+    let channels = channels.unwrap_or(vec![bot_account.to_ascii_lowercase()]);
+    Ok(Self { mode, bot_account, channels, oauth, user_limits, connection, providers, cache, offline, badges })
+  }
+}
+
+/// Settings used by the Twitch monitor while reading chat.
+pub trait EkbMonitorValues {
+  fn user_limits(&self) -> Result<UserLimitConfig, String>;
+  fn connection(&self) -> Result<ConnectionConfig, String>;
+  fn providers(&self) -> Result<ProviderConfig, String>;
+  fn cache(&self) -> Result<CacheConfig, String>;
+  fn offline(&self) -> Result<bool, String>;
+  fn badges(&self) -> Result<BadgeConfig, String>;
+}
+
+impl EkbMonitorValues for KdlDocument {
+  fn user_limits(&self) -> Result<UserLimitConfig, String> {
+    let mut limits = UserLimitConfig::default();
+    let Some(user_limits) = self.get("user-limits").and_then(|node| node.children() ) else {
+      return Ok(limits);
+    };
+    for node in user_limits.nodes() {
+      match node.name().value() {
+        "cooldown" => {
+          if let Some(cooldown) = kdl_arg_f32(node)? {
+            limits.user_cooldown = cooldown;
+          }
+        }
+        "max-per-message" => {
+          if let Some(KdlValue::Integer(max)) = node.entry(0).map(|entry| entry.value() ) {
+            limits.max_per_message = u32::try_from(*max).map_err(|_| format!("max-per-message should be a positive whole number. Found {}", max) )?;
+          }
+        }
+        "exempt" => {
+          (limits.exempt_broadcaster, limits.exempt_moderators, limits.exempt_vips) = (false, false, false);
+          for entry in node.entries().iter().filter(|entry| entry.name().is_none() ) {
+            match entry.value() {
+              KdlValue::String(role) if role == "broadcaster" => { limits.exempt_broadcaster = true; }
+              KdlValue::String(role) if role == "moderator" => { limits.exempt_moderators = true; }
+              KdlValue::String(role) if role == "vip" => { limits.exempt_vips = true; }
+              e => { return Err(format!("user-limits exempt accepts broadcaster, moderator and vip. Found {:?}", e)); }
+            }
+          }
+        }
+        e => { return Err(format!("user-limits block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(limits)
+  }
+  fn connection(&self) -> Result<ConnectionConfig, String> {
+    let mut connection = ConnectionConfig::default();
+    let Some(settings) = self.get("connection").and_then(|node| node.children() ) else {
+      return Ok(connection);
+    };
+    for node in settings.nodes() {
+      match node.name().value() {
+        "server" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            Some(KdlValue::String(server)) => { connection.server = server.to_owned(); }
+            e => { return Err(format!("connection server should be a host name as a string. Found {:?}", e)); }
+          }
+        }
+        "port" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            Some(KdlValue::Integer(port)) if u16::try_from(*port).is_ok() => { connection.port = *port as u16; }
+            e => { return Err(format!("connection port should be a port number. Found {:?}", e)); }
+          }
+        }
+        "tls" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            Some(KdlValue::Bool(tls)) => { connection.use_tls = *tls; }
+            e => { return Err(format!("connection tls should be #true or #false. Found {:?}", e)); }
+          }
+        }
+        "max-retries" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            Some(KdlValue::Integer(max)) if u32::try_from(*max).is_ok() => { connection.max_retries = *max as u32; }
+            e => { return Err(format!("connection max-retries should be a positive whole number. Found {:?}", e)); }
+          }
+        }
+        "backoff" => {
+          if let Some(initial) = kdl_prop_f32(node, "initial")? {
+            connection.backoff_initial = initial;
+          }
+          if let Some(max) = kdl_prop_f32(node, "max")? {
+            connection.backoff_max = max;
+          }
+        }
+        "ping" => {
+          if let Some(interval) = kdl_prop_u32(node, "interval")? {
+            connection.ping_time = interval;
+          }
+          if let Some(timeout) = kdl_prop_u32(node, "timeout")? {
+            connection.ping_timeout = timeout;
+          }
+        }
+        e => { return Err(format!("connection block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(connection)
+  }
+  /// Without a `providers` block every provider is used. With one, only the listed providers are.
+  fn providers(&self) -> Result<ProviderConfig, String> {
+    let Some(settings) = self.get("providers").and_then(|node| node.children() ) else {
+      return Ok(ProviderConfig::default());
+    };
+    let mut providers = ProviderConfig::none();
+    for node in settings.nodes() {
+      let name = node.name().value();
+      let provider = match EmoteProvider::from_name(name) {
+        Some(EmoteProvider::Twitch | EmoteProvider::Badge) | None => { return Err(format!("providers block has unknown provider '{}'. Use seventv, bttv, ffz, emoji or custom", name)); }
+        Some(provider) => { provider }
+      };
+      let url = match node.entry("url").map(|entry| entry.value() ) {
+        None => { None }
+        Some(KdlValue::String(url)) => { Some(url.trim_end_matches('/').to_owned()) }
+        Some(e) => { return Err(format!("{} url should be the API base URL as a string. Found {:?}", name, e)); }
+      };
+      providers.enable(provider, url);
+      match node.entry("cdn").map(|entry| entry.value() ) {
+        None => {}
+        Some(KdlValue::String(cdn)) if provider == EmoteProvider::Bttv => { providers.bttv_cdn = cdn.trim_end_matches('/').to_owned(); }
+        Some(e) => { return Err(format!("{} cdn is only used by bttv, as a URL string. Found {:?}", name, e)); }
+      }
+    }
+    Ok(providers)
+  }
+  fn cache(&self) -> Result<CacheConfig, String> {
+    let mut cache = CacheConfig::default();
+    let Some(settings) = self.get("cache").and_then(|node| node.children() ) else {
+      return Ok(cache);
+    };
+    for node in settings.nodes() {
+      let Some(value) = kdl_arg_f32(node)? else {
+        return Err(format!("cache {} needs a value", node.name().value()));
+      };
+      match node.name().value() {
+        "max-entries" => { cache.max_entries = value as u32; }
+        "max-size-mb" => { cache.max_bytes = (value as f64 * 1024. * 1024.) as u64; }
+        "ttl-days" => { cache.ttl = (value as f64 * 24. * 60. * 60.) as u64; }
+        e => { return Err(format!("cache block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(cache)
+  }
+  /// `offline` on its own means `offline #true`.
+  fn offline(&self) -> Result<bool, String> {
+    let Some(node) = self.get("offline") else { return Ok(false); };
+    match node.entry(0).map(|entry| entry.value() ) {
+      None => { Ok(true) }
+      Some(KdlValue::Bool(offline)) => { Ok(*offline) }
+      Some(e) => { Err(format!("offline should be #true or #false. Found {:?}", e)) }
+    }
+  }
+  /// `badges { enabled #false }` hides chat badges, `badges { mirror "<folder or URL>" }` loads them from a mirror.
+  fn badges(&self) -> Result<BadgeConfig, String> {
+    let mut badges = BadgeConfig::default();
+    let Some(settings) = self.get("badges").and_then(|node| node.children() ) else {
+      return Ok(badges);
+    };
+    for node in settings.nodes() {
+      match (node.name().value(), node.entry(0).map(|entry| entry.value() )) {
+        ("enabled", None) => { badges.enabled = true; }
+        ("enabled", Some(KdlValue::Bool(enabled))) => { badges.enabled = *enabled; }
+        ("enabled", Some(e)) => { return Err(format!("badges enabled should be #true or #false. Found {:?}", e)); }
+        ("mirror", Some(KdlValue::String(mirror))) => { badges.mirror = Some(mirror.to_owned()); }
+        ("mirror", e) => { return Err(format!("badges mirror should be a folder or base URL as a string. Found {:?}", e)); }
+        (e, _) => { return Err(format!("badges block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(badges)
+  }
+}
+
+/// Settings for the emote wall itself, as opposed to the Twitch connection.
+#[derive(Debug, Clone, Default)]
+pub struct EkbWallConfig {
+  pub effects: EffectWeights,
+  pub gates: EffectGates,
+  /// None when config.kdl has no cooldown settings, leaving the OBS properties in charge.
+  pub cooldowns: Option<CooldownConfig>,
+  /// None when config.kdl has no chat block, leaving the OBS properties in charge.
+  pub chat: Option<ChatOverlayConfig>,
+  /// None when config.kdl has no text-style block, leaving the OBS properties in charge.
+  pub text_style: Option<TextStyle>,
+  /// None when config.kdl has no alerts block, leaving the OBS properties in charge.
+  pub alerts: Option<AlertConfig>,
+  /// Keyed by lowercase channel name, only for channels with settings of their own.
+  pub channels: HashMap<String, ChannelProfile>,
+}
+
+impl TryFrom<KdlDocument> for EkbWallConfig {
+  type Error = String;
+  fn try_from(conf: KdlDocument) -> Result<Self, Self::Error> {
+    let effects = conf.effect_weights()?;
+    let gates = conf.effect_gates()?;
+    let cooldowns = conf.cooldowns()?;
+    let chat = conf.chat_overlay()?;
+    let text_style = conf.text_style()?;
+    let alerts = conf.alerts()?;
+    let channels = conf.channel_profiles()?;
+    Ok(Self { effects, gates, cooldowns, chat, text_style, alerts, channels })
+  }
+}
+
+pub trait EkbEffectValues {
+  fn effect_weights(&self) -> Result<EffectWeights, String>;
+  fn effect_gates(&self) -> Result<EffectGates, String>;
+  fn cooldowns(&self) -> Result<Option<CooldownConfig>, String>;
+  fn chat_overlay(&self) -> Result<Option<ChatOverlayConfig>, String>;
+  fn text_style(&self) -> Result<Option<TextStyle>, String>;
+  fn alerts(&self) -> Result<Option<AlertConfig>, String>;
+  fn channel_profiles(&self) -> Result<HashMap<String, ChannelProfile>, String>;
+}
+
+impl EkbEffectValues for KdlDocument {
+  fn effect_weights(&self) -> Result<EffectWeights, String> {
+    let mut weights = EffectWeights::default();
+    let Some(effects) = self.get("effects").and_then(|node| node.children() ) else {
+      return Ok(weights);
+    };
+    for kind in EffectKind::ALL.into_iter().filter(EffectKind::is_opt_in) {
+      weights.set_weight(kind, 0);
+    }
+    for node in effects.nodes() {
+      let name = node.name().value();
+      let Some(kind) = EffectKind::from_kdl_name(name) else {
+        return Err(format!("effects block has unknown effect '{}'", name));
+      };
+      // Naming an opt-in effect without a weight turns it on at its default odds.
+      let weight = kdl_prop_u32(node, "weight")?.or(kind.is_opt_in().then(|| kind.default_weight() ));
+      if let Some(weight) = weight {
+        weights.set_weight(kind, weight);
+      }
+    }
+    Ok(weights)
+  }
+  fn effect_gates(&self) -> Result<EffectGates, String> {
+    let mut gates = EffectGates::default();
+    let Some(effects) = self.get("effects") else {
+      return Ok(gates);
+    };
+    match effects.entry("unqualified").map(|entry| entry.value() ) {
+      None => {}
+      Some(KdlValue::String(val)) if val == "fallback" => {}
+      Some(KdlValue::String(val)) if val == "ignore" => { gates.ignore_unqualified = true; }
+      Some(e) => { return Err(format!("effects unqualified should be \"fallback\" or \"ignore\". Found {:?}", e)); }
+    }
+    let Some(effects) = effects.children() else {
+      return Ok(gates);
+    };
+    for node in effects.nodes() {
+      let Some(kind) = EffectKind::from_kdl_name(node.name().value()) else { continue; };
+      let mut req = EffectRequirement::default();
+      match node.entry("require").map(|entry| entry.value() ) {
+        None => {}
+        Some(KdlValue::String(val)) if val == "sub" => { req.subscriber = true; }
+        Some(e) => { return Err(format!("{} require only supports \"sub\". Found {:?}", kind.kdl_name(), e)); }
+      }
+      if let Some(bits) = kdl_prop_u32(node, "bits")? {
+        req.min_bits = bits;
+      }
+      match node.entry("reward").map(|entry| entry.value() ) {
+        None => {}
+        Some(KdlValue::String(val)) => { req.reward_id = Some(val.to_owned()); }
+        Some(e) => { return Err(format!("{} reward should be a channel point reward id as a string. Found {:?}", kind.kdl_name(), e)); }
+      }
+      if !req.is_open() {
+        gates.requirements.insert(kind, req);
+      }
+    }
+    Ok(gates)
+  }
+  fn cooldowns(&self) -> Result<Option<CooldownConfig>, String> {
+    let mut found = false;
+    let mut cooldowns = CooldownConfig::default();
+    if let Some(effects) = self.get("effects").and_then(|node| node.children() ) {
+      for node in effects.nodes() {
+        let Some(kind) = EffectKind::from_kdl_name(node.name().value()) else { continue; };
+        if let Some(interval) = kdl_prop_f32(node, "cooldown")? {
+          cooldowns.effect_intervals.insert(kind, interval);
+          found = true;
+        }
+      }
+    }
+    if let Some(cooldown) = self.get("cooldown").and_then(|node| node.children() ) {
+      for node in cooldown.nodes() {
+        match node.name().value() {
+          "global" => {
+            if let Some(interval) = kdl_arg_f32(node)? {
+              cooldowns.global_interval = interval;
+              found = true;
+            }
+          }
+          "burst" => {
+            if let Some(capacity) = kdl_prop_u32(node, "capacity")? {
+              cooldowns.burst_capacity = capacity;
+              found = true;
+            }
+            if let Some(refill) = kdl_prop_f32(node, "refill")? {
+              cooldowns.burst_refill = refill;
+              found = true;
+            }
+          }
+          e => { return Err(format!("cooldown block has unknown setting '{}'", e)); }
+        }
+      }
+    }
+    Ok(found.then_some(cooldowns))
+  }
+  /// `chat { position x=20 y=20; size width=480 height=600; max-lines 12; lifetime 30; font-size 24;
+  /// enter "slide-left" time=0.3; exit "fade" time=2 }`. `fade 2` is short for `exit "fade" time=2`.
+  /// `font-size 0` goes back to the text-style size.
+  /// Having the block turns the overlay on unless it says `enabled #false`.
+  fn chat_overlay(&self) -> Result<Option<ChatOverlayConfig>, String> {
+    let Some(chat) = self.get("chat") else { return Ok(None); };
+    let mut config = ChatOverlayConfig { enabled: true, ..Default::default() };
+    for node in chat.children().map(|children| children.nodes() ).unwrap_or_default() {
+      match node.name().value() {
+        "enabled" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            None => { config.enabled = true; }
+            Some(KdlValue::Bool(enabled)) => { config.enabled = *enabled; }
+            Some(e) => { return Err(format!("chat enabled should be #true or #false. Found {:?}", e)); }
+          }
+        }
+        "position" => {
+          if let Some(x) = kdl_prop_u32(node, "x")? {
+            config.x = x as i32;
+          }
+          if let Some(y) = kdl_prop_u32(node, "y")? {
+            config.y = y as i32;
+          }
+        }
+        "size" => {
+          if let Some(width) = kdl_prop_u32(node, "width")? {
+            config.width = width;
+          }
+          if let Some(height) = kdl_prop_u32(node, "height")? {
+            config.height = height;
+          }
+        }
+        "max-lines" => {
+          if let Some(max_lines) = kdl_arg_f32(node)? {
+            config.max_lines = max_lines as u32;
+          }
+        }
+        "lifetime" => {
+          if let Some(lifetime) = kdl_arg_f32(node)? {
+            config.lifetime = lifetime;
+          }
+        }
+        "fade" => {
+          if let Some(fade) = kdl_arg_f32(node)? {
+            config.transition.exit = TextAnimation::Fade;
+            config.transition.exit_time = fade;
+          }
+        }
+        "enter" | "exit" => { kdl_transition(node, &mut config.transition)?; }
+        "font-size" => {
+          match kdl_arg_f32(node)? {
+            None => {}
+            Some(0.) => { config.font_size = None; }
+            Some(size) if size >= 6. => { config.font_size = Some(size); }
+            Some(e) => { return Err(format!("chat font-size should be at least 6, or 0 for the text-style size. Found {}", e)); }
+          }
+        }
+        e => { return Err(format!("chat block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(Some(config))
+  }
+  /// `text-style { font "Noto Sans" weight=700 italic=#true; size 24 line-height=1.2; color "#FFFFFF";
+  /// outline width=2 color="#000000"; shadow x=2 y=2 blur=2 color="#00000099"; background color="#00000080" radius=8 }`.
+  /// Settings left out keep their defaults.
+  fn text_style(&self) -> Result<Option<TextStyle>, String> {
+    let Some(settings) = self.get("text-style").and_then(|node| node.children() ) else {
+      return Ok(None);
+    };
+    let mut style = TextStyle::default();
+    for node in settings.nodes() {
+      match node.name().value() {
+        "font" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            None => {}
+            Some(KdlValue::String(family)) => { style.family = Some(family.to_owned()); }
+            Some(e) => { return Err(format!("text-style font should be a font family name as a string. Found {:?}", e)); }
+          }
+          match kdl_prop_u32(node, "weight")? {
+            None => {}
+            Some(weight @ 1..=1000) => { style.weight = weight as u16; }
+            Some(e) => { return Err(format!("text-style font weight should be from 1 to 1000. Found {}", e)); }
+          }
+          match node.entry("italic").map(|entry| entry.value() ) {
+            None => {}
+            Some(KdlValue::Bool(italic)) => { style.italic = *italic; }
+            Some(e) => { return Err(format!("text-style font italic should be #true or #false. Found {:?}", e)); }
+          }
+        }
+        "size" => {
+          match kdl_arg_f32(node)? {
+            None => {}
+            Some(size) if size >= 6. => { style.size = size; }
+            Some(e) => { return Err(format!("text-style size should be at least 6. Found {}", e)); }
+          }
+          if let Some(line_height) = kdl_prop_f32(node, "line-height")? {
+            style.line_height = line_height;
+          }
+        }
+        "color" => {
+          if let Some(color) = kdl_value_color(node.entry(0).map(|entry| entry.value() ), node, "value")? {
+            style.color = color;
+          }
+        }
+        "outline" => {
+          match kdl_prop_f32(node, "width")? {
+            None => {}
+            Some(width) if width <= MAX_TEXT_EFFECT => { style.outline_width = width; }
+            Some(e) => { return Err(format!("text-style outline width should be at most {}. Found {}", MAX_TEXT_EFFECT, e)); }
+          }
+          if let Some(color) = kdl_prop_color(node, "color")? {
+            style.outline_color = color;
+          }
+        }
+        "shadow" => {
+          if let Some(x) = kdl_prop_signed_f32(node, "x")? {
+            style.shadow_offset.0 = x;
+          }
+          if let Some(y) = kdl_prop_signed_f32(node, "y")? {
+            style.shadow_offset.1 = y;
+          }
+          match kdl_prop_f32(node, "blur")? {
+            None => {}
+            Some(blur) if blur <= MAX_TEXT_EFFECT => { style.shadow_blur = blur; }
+            Some(e) => { return Err(format!("text-style shadow blur should be at most {}. Found {}", MAX_TEXT_EFFECT, e)); }
+          }
+          // A shadow node on its own turns on the default shadow color.
+          style.shadow_color = kdl_prop_color(node, "color")?.unwrap_or([0x00, 0x00, 0x00, 0x99]);
+        }
+        "background" => {
+          style.background = Some(kdl_prop_color(node, "color")?.unwrap_or([0x00, 0x00, 0x00, 0x80]));
+          if let Some(radius) = kdl_prop_f32(node, "radius")? {
+            style.background_radius = radius;
+          }
+        }
+        e => { return Err(format!("text-style block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(Some(style))
+  }
+  /// `alerts { position x=360 y=120; width 1200; font-size 48; duration 6; enter "pop" time=0.4;
+  /// exit "fade" time=1; burst 20; min-bits 100; resub "{user} subscribed for {months} months!"; raid #false }`.
+  /// Each kind of alert is named after Twitch's `msg-id` and takes a template, or `#false` to
+  /// turn it off. Having the block turns alerts on unless it says `enabled #false`.
+  fn alerts(&self) -> Result<Option<AlertConfig>, String> {
+    let Some(alerts) = self.get("alerts") else { return Ok(None); };
+    let mut config = AlertConfig { enabled: true, ..Default::default() };
+    for node in alerts.children().map(|children| children.nodes() ).unwrap_or_default() {
+      let name = node.name().value();
+      if let Some(kind) = AlertKind::from_kdl_name(name) {
+        match node.entry(0).map(|entry| entry.value() ) {
+          None => {}
+          Some(KdlValue::String(template)) => { config.templates.insert(kind, template.to_owned()); }
+          Some(KdlValue::Bool(false)) => { config.templates.remove(&kind); }
+          Some(e) => { return Err(format!("alerts {} should be a template string or #false. Found {:?}", name, e)); }
+        }
+        continue;
+      }
+      match name {
+        "enabled" => {
+          match node.entry(0).map(|entry| entry.value() ) {
+            None => { config.enabled = true; }
+            Some(KdlValue::Bool(enabled)) => { config.enabled = *enabled; }
+            Some(e) => { return Err(format!("alerts enabled should be #true or #false. Found {:?}", e)); }
+          }
+        }
+        "position" => {
+          if let Some(x) = kdl_prop_u32(node, "x")? {
+            config.x = Some(x as i32);
+          }
+          if let Some(y) = kdl_prop_u32(node, "y")? {
+            config.y = y as i32;
+          }
+        }
+        "width" => {
+          if let Some(width) = kdl_arg_f32(node)? {
+            config.width = width as u32;
+          }
+        }
+        "font-size" => {
+          match kdl_arg_f32(node)? {
+            None => {}
+            Some(0.) => { config.font_size = None; }
+            Some(size) if size >= 6. => { config.font_size = Some(size); }
+            Some(e) => { return Err(format!("alerts font-size should be at least 6, or 0 for the text-style size. Found {}", e)); }
+          }
+        }
+        "duration" => {
+          if let Some(duration) = kdl_arg_f32(node)? {
+            config.duration = duration;
+          }
+        }
+        "enter" | "exit" => { kdl_transition(node, &mut config.transition)?; }
+        "burst" => {
+          if let Some(burst) = kdl_arg_f32(node)? {
+            config.burst = burst as u32;
+          }
+        }
+        "min-bits" => {
+          if let Some(min_bits) = kdl_arg_f32(node)? {
+            config.min_bits = min_bits as u32;
+          }
+        }
+        e => { return Err(format!("alerts block has unknown setting '{}'", e)); }
+      }
+    }
+    Ok(Some(config))
+  }
+  /// `channel` nodes with `weight=` or an `effects` block of their own, e.g.
+  /// `channel collab-partner weight=0.5 { effects { gravity weight=100 } }`.
+  fn channel_profiles(&self) -> Result<HashMap<String, ChannelProfile>, String> {
+    let mut profiles = HashMap::new();
+    for node in self.nodes().iter().filter(|node| node.name().value() == "channel" ) {
+      let mut profile = ChannelProfile::default();
+      match kdl_prop_f32(node, "weight")? {
+        None => {}
+        Some(weight) if weight <= 1. => { profile.weight = weight; }
+        Some(e) => { return Err(format!("channel weight is the chance an emote is used, from 0 to 1. Found {}", e)); }
+      }
+      if let Some(children) = node.children() {
+        profile.effects = Some((children.effect_weights()?, children.effect_gates()?));
+      }
+      if profile.weight == 1. && profile.effects.is_none() {
+        continue;
+      }
+      for entry in node.entries().iter().filter(|entry| entry.name().is_none() ) {
+        if let KdlValue::String(channel) = entry.value() {
+          profiles.insert(channel.to_ascii_lowercase(), profile.clone());
+        }
+      }
+    }
+    Ok(profiles)
+  }
+}
+
+pub fn kdl_prop_u32(node: &KdlNode, key: &str) -> Result<Option<u32>, String> {
+  match node.entry(key).map(|entry| entry.value() ) {
+    None => { Ok(None) }
+    Some(KdlValue::Integer(val)) => {
+      u32::try_from(*val).map(Some).map_err(|_| format!("{} {} should be a positive whole number. Found {}", node.name().value(), key, val) )
+    }
+    Some(e) => { Err(format!("{} {} should be a positive whole number. Found {:?}", node.name().value(), key, e)) }
+  }
+}
+
+pub fn kdl_prop_f32(node: &KdlNode, key: &str) -> Result<Option<f32>, String> {
+  kdl_value_f32(node.entry(key).map(|entry| entry.value() ), node, key)
+}
+
+/// `enter "<animation>" time=<seconds>` or `exit ...`, applied to that half of `transition`.
+pub fn kdl_transition(node: &KdlNode, transition: &mut TextTransition) -> Result<(), String> {
+  let name = node.name().value();
+  let animation = match node.entry(0).map(|entry| entry.value() ) {
+    None => { None }
+    Some(KdlValue::String(animation)) => {
+      let Some(animation) = TextAnimation::from_name(animation) else {
+        return Err(format!("{} animation '{}' isn't known. Use one of {}", name, animation, TextAnimation::NAMES.join(", ")));
+      };
+      Some(animation)
+    }
+    Some(e) => { return Err(format!("{} should name an animation as a string. Found {:?}", name, e)); }
+  };
+  let time = kdl_prop_f32(node, "time")?;
+  let (current, current_time) = match name {
+    "enter" => { (&mut transition.enter, &mut transition.enter_time) }
+    _ => { (&mut transition.exit, &mut transition.exit_time) }
+  };
+  if let Some(animation) = animation {
+    *current = animation;
+  }
+  if let Some(time) = time {
+    *current_time = time;
+  }
+  Ok(())
+}
+
+/// Like `kdl_prop_f32`, but negative numbers are allowed, e.g. for offsets.
+pub fn kdl_prop_signed_f32(node: &KdlNode, key: &str) -> Result<Option<f32>, String> {
+  match node.entry(key).map(|entry| entry.value() ) {
+    None => { Ok(None) }
+    Some(KdlValue::Integer(val)) => { Ok(Some(*val as f32)) }
+    Some(KdlValue::Float(val)) => { Ok(Some(*val as f32)) }
+    Some(e) => { Err(format!("{} {} should be a number. Found {:?}", node.name().value(), key, e)) }
+  }
+}
+
+pub fn kdl_prop_color(node: &KdlNode, key: &str) -> Result<Option<[u8; 4]>, String> {
+  kdl_value_color(node.entry(key).map(|entry| entry.value() ), node, key)
+}
+
+fn kdl_value_color(value: Option<&KdlValue>, node: &KdlNode, key: &str) -> Result<Option<[u8; 4]>, String> {
+  match value {
+    None => { Ok(None) }
+    Some(KdlValue::String(val)) => {
+      parse_hex_color(val).map(Some).ok_or_else(|| format!("{} {} should be a color as \"#RRGGBB\" or \"#RRGGBBAA\". Found {:?}", node.name().value(), key, val) )
+    }
+    Some(e) => { Err(format!("{} {} should be a color as \"#RRGGBB\" or \"#RRGGBBAA\". Found {:?}", node.name().value(), key, e)) }
+  }
+}
+
+/// First positional argument of the node as seconds, counts, etc.
+pub fn kdl_arg_f32(node: &KdlNode) -> Result<Option<f32>, String> {
+  kdl_value_f32(node.entry(0).map(|entry| entry.value() ), node, "value")
+}
+
+fn kdl_value_f32(value: Option<&KdlValue>, node: &KdlNode, key: &str) -> Result<Option<f32>, String> {
+  match value {
+    None => { Ok(None) }
+    Some(KdlValue::Integer(val)) if *val >= 0 => { Ok(Some(*val as f32)) }
+    Some(KdlValue::Float(val)) if *val >= 0. => { Ok(Some(*val as f32)) }
+    Some(e) => { Err(format!("{} {} should be a positive number. Found {:?}", node.name().value(), key, e)) }
   }
 }
 
 pub trait EkbTwitchValues {
+  fn mode(&self) -> Result<TwitchAuthMode, String>;
+  fn mode_update(&mut self, new_mode: TwitchAuthMode) -> Result<(),String>;
   fn bot_account(&self) -> Result<String, String>;
   fn channel(&self) -> Result<String, String>;
+  fn channels(&self) -> Result<Vec<String>, String>;
   fn oauth(&self) -> Result<String, String>;
   fn bot_account_update(&mut self, new_oauth: &str) -> Result<(),String>;
   fn channel_update(&mut self, new_oauth: &str) -> Result<(),String>;
@@ -65,6 +763,29 @@ pub trait EkbTwitchValues {
 
 #[allow(clippy::needless_return)]
 impl EkbTwitchValues for KdlDocument {
+  /// Missing `mode` means authenticated, which is how config files were written before it existed.
+  fn mode(&self) -> Result<TwitchAuthMode, String> {
+    let Some(node) = self.get("mode") else { return Ok(TwitchAuthMode::Authenticated); };
+    match node.entry(0).map(|entry| entry.value() ) {
+      Some(KdlValue::String(mode)) if mode == "authenticated" => { Ok(TwitchAuthMode::Authenticated) }
+      Some(KdlValue::String(mode)) if mode == "anonymous" => { Ok(TwitchAuthMode::Anonymous) }
+      e => { Err(format!("mode should be anonymous or authenticated. Found {:?}", e)) }
+    }
+  }
+  fn mode_update(&mut self, new_mode: TwitchAuthMode) -> Result<(),String> {
+    if let Some(node) = self.get_mut("mode") {
+      if let Some(entry) = node.entry_mut(0) {
+        entry.set_value(new_mode.kdl_name());
+        let value_repr = entry.value().to_string();
+        if let Some(format) = entry.format_mut() {
+          format.value_repr = value_repr;
+        }
+        Ok(())
+      } else { return Err("mode node has no fields".to_owned()); }
+    } else if new_mode == TwitchAuthMode::Authenticated {
+      Ok(()) // Already the default
+    } else { return Err("mode node not present".to_owned()); }
+  }
   fn bot_account(&self) -> Result<String, String> {
     if let Some(node) = self.get("bot-account") {
       if let Some(entry) = node.entry(0) {
@@ -91,6 +812,25 @@ impl EkbTwitchValues for KdlDocument {
       } else { return Err("channel node has no fields".to_owned()); }
     } else { return Err("channel node not present".to_owned()); }
   }
+  /// Every argument of every `channel` node, lowercased to match IRC channel names.
+  fn channels(&self) -> Result<Vec<String>, String> {
+    let mut channels: Vec<String> = vec![];
+    for node in self.nodes().iter().filter(|node| node.name().value() == "channel" ) {
+      for entry in node.entries().iter().filter(|entry| entry.name().is_none() ) {
+        match entry.value() {
+          KdlValue::String(val) => {
+            let val = val.to_ascii_lowercase();
+            if !channels.contains(&val) { channels.push(val); }
+          }
+          e => { return Err(format!("channel node entries should be channel names as strings. Found {:?}", e)); }
+        }
+      }
+    }
+    if channels.is_empty() {
+      return Err("channel node not present".to_owned());
+    }
+    Ok(channels)
+  }
   fn oauth(&self) -> Result<String, String> {
     if let Some(node) = self.get("oauth") {
       if let Some(entry) = node.entry(0) {
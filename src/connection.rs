@@ -0,0 +1,67 @@
+use {
+  rand::prelude::*,
+  std::time::Duration,
+};
+
+/// Where and how the Twitch monitor connects, and how hard it tries to stay connected.
+/// Pointing `server` at a local IRC server with `use_tls` off makes the monitor testable offline.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+  pub server: String,
+  pub port: u16,
+  pub use_tls: bool,
+  /// Failed attempts in a row before giving up. 0 retries forever.
+  pub max_retries: u32,
+  /// Seconds before the first retry. `Backoff` waits at least 0.1.
+  pub backoff_initial: f32,
+  pub backoff_max: f32,
+  /// Seconds between client PINGs, and seconds to wait for the PONG.
+  pub ping_time: u32,
+  pub ping_timeout: u32,
+}
+
+impl Default for ConnectionConfig {
+  fn default() -> Self {
+    Self {
+      server: "irc.chat.twitch.tv".to_owned(),
+      port: 6697,
+      use_tls: true,
+      max_retries: 10,
+      backoff_initial: 1.,
+      backoff_max: 120.,
+      ping_time: 180,
+      ping_timeout: 20,
+    }
+  }
+}
+
+/// Exponential backoff with jitter between reconnect attempts.
+pub struct Backoff {
+  initial: f32,
+  max: f32,
+  attempts: u32,
+}
+
+impl Backoff {
+  pub fn new(conf: &ConnectionConfig) -> Self {
+    Self {
+      initial: conf.backoff_initial.max(0.1),
+      max: conf.backoff_max.max(conf.backoff_initial),
+      attempts: 0,
+    }
+  }
+  pub fn attempts(&self) -> u32 {
+    self.attempts
+  }
+  pub fn reset(&mut self) {
+    self.attempts = 0;
+  }
+  /// Doubles the delay with each attempt up to `max`, then picks a random point in
+  /// the upper half so many clients dropped together don't all return at once.
+  pub fn next_delay(&mut self) -> Duration {
+    let delay = (self.initial * 2_f32.powi(self.attempts.min(16) as i32)).min(self.max);
+    self.attempts += 1;
+    let jittered = rand::rng().random_range((delay / 2.)..=delay);
+    Duration::from_secs_f32(jittered)
+  }
+}
@@ -0,0 +1,229 @@
+use {
+  crate::{
+    chat::ChatSender,
+    effects::EffectKind,
+  },
+  std::{
+    collections::HashMap,
+    time::{
+      Duration, Instant,
+    },
+  },
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct CooldownConfig {
+  /// Minimum seconds between any two emote spawns. 0 disables it.
+  pub global_interval: f32,
+  /// Minimum seconds between two spawns of the same effect.
+  pub effect_intervals: HashMap<EffectKind, f32>,
+  /// Size of the token bucket. 0 disables the bucket.
+  pub burst_capacity: u32,
+  /// Tokens returned to the bucket per second.
+  pub burst_refill: f32,
+}
+
+impl CooldownConfig {
+  pub fn effect_interval(&self, kind: EffectKind) -> f32 {
+    self.effect_intervals.get(&kind).copied().unwrap_or(0.)
+  }
+}
+
+/// Tracks spawn times against the `video_tick` clock so the wall can be rate limited.
+pub struct Cooldowns {
+  config: CooldownConfig,
+  clock: f32,
+  last_spawn: Option<f32>,
+  last_effect: HashMap<EffectKind, f32>,
+  tokens: f32,
+}
+
+impl Cooldowns {
+  pub fn new(config: CooldownConfig) -> Self {
+    let tokens = config.burst_capacity as f32;
+    Self {
+      config,
+      clock: 0.,
+      last_spawn: None,
+      last_effect: HashMap::new(),
+      tokens,
+    }
+  }
+  pub fn config(&self) -> &CooldownConfig {
+    &self.config
+  }
+  pub fn set_config(&mut self, config: CooldownConfig) {
+    self.tokens = self.tokens.min(config.burst_capacity as f32);
+    if self.config.burst_capacity == 0 {
+      self.tokens = config.burst_capacity as f32;
+    }
+    self.config = config;
+  }
+  pub fn update(&mut self, seconds: f32) {
+    self.clock += seconds;
+    let capacity = self.config.burst_capacity as f32;
+    self.tokens = (self.tokens + self.config.burst_refill * seconds).min(capacity);
+  }
+  /// Global interval and burst bucket both allow another spawn.
+  pub fn can_spawn(&self) -> bool {
+    let interval_ok = match self.last_spawn {
+      None => { true }
+      Some(last) => { self.clock - last >= self.config.global_interval }
+    };
+    let bucket_ok = self.config.burst_capacity == 0 || self.tokens >= 1.;
+    interval_ok && bucket_ok
+  }
+  pub fn effect_ready(&self, kind: EffectKind) -> bool {
+    match self.last_effect.get(&kind) {
+      None => { true }
+      Some(last) => { self.clock - last >= self.config.effect_interval(kind) }
+    }
+  }
+  pub fn record_spawn(&mut self, kind: EffectKind) {
+    self.last_spawn = Some(self.clock);
+    self.last_effect.insert(kind, self.clock);
+    if self.config.burst_capacity > 0 {
+      self.tokens = (self.tokens - 1.).max(0.);
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct UserLimitConfig {
+  /// Seconds before the same chatter can trigger the wall again. 0 disables it.
+  pub user_cooldown: f32,
+  /// Emotes taken from a single message. 0 means no cap.
+  pub max_per_message: u32,
+  pub exempt_broadcaster: bool,
+  pub exempt_moderators: bool,
+  pub exempt_vips: bool,
+}
+
+impl Default for UserLimitConfig {
+  fn default() -> Self {
+    Self {
+      user_cooldown: 0.,
+      max_per_message: 0,
+      exempt_broadcaster: true,
+      exempt_moderators: true,
+      exempt_vips: true,
+    }
+  }
+}
+
+impl UserLimitConfig {
+  pub fn is_exempt(&self, sender: &ChatSender) -> bool {
+    (self.exempt_broadcaster && sender.is_broadcaster())
+      || (self.exempt_moderators && sender.is_moderator())
+      || (self.exempt_vips && sender.is_vip())
+  }
+}
+
+/// Per-chatter limits, applied in the Twitch monitor before any emote is downloaded.
+pub struct UserLimiter {
+  config: UserLimitConfig,
+  last_trigger: HashMap<String, Instant>,
+}
+
+impl UserLimiter {
+  pub fn new(config: UserLimitConfig) -> Self {
+    Self { config, last_trigger: HashMap::new() }
+  }
+  /// Number of emotes the sender's message may spawn, or None while the sender is cooling down.
+  pub fn admit(&mut self, sender: &ChatSender) -> Option<usize> {
+    if self.config.is_exempt(sender) {
+      return Some(usize::MAX);
+    }
+    let cooldown = Duration::from_secs_f32(self.config.user_cooldown);
+    let now = Instant::now();
+    if let Some(last) = self.last_trigger.get(&sender.user_id)
+      && now.duration_since(*last) < cooldown
+    {
+      return None;
+    }
+    if self.last_trigger.len() > 1000 {
+      self.last_trigger.retain(|_, last| now.duration_since(*last) < cooldown );
+    }
+    if !cooldown.is_zero() {
+      self.last_trigger.insert(sender.user_id.to_owned(), now);
+    }
+    match self.config.max_per_message {
+      0 => { Some(usize::MAX) }
+      cap => { Some(cap as usize) }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    crate::chat::parse_badges,
+  };
+
+  fn sender(user_id: &str, badges: &str) -> ChatSender {
+    ChatSender { user_id: user_id.to_owned(), badges: parse_badges(badges), ..Default::default() }
+  }
+
+  #[test]
+  fn global_interval_and_effect_intervals() {
+    let mut cooldowns = Cooldowns::new(CooldownConfig {
+      global_interval: 1.,
+      effect_intervals: HashMap::from([(EffectKind::InchWorm, 5.)]),
+      ..Default::default()
+    });
+    assert!(cooldowns.can_spawn());
+    cooldowns.record_spawn(EffectKind::InchWorm);
+    assert!(!cooldowns.can_spawn());
+    cooldowns.update(1.);
+    assert!(cooldowns.can_spawn());
+    assert!(!cooldowns.effect_ready(EffectKind::InchWorm));
+    assert!(cooldowns.effect_ready(EffectKind::Gravity));
+    cooldowns.update(4.);
+    assert!(cooldowns.effect_ready(EffectKind::InchWorm));
+  }
+
+  #[test]
+  fn burst_bucket_empties_and_refills() {
+    let mut cooldowns = Cooldowns::new(CooldownConfig { burst_capacity: 2, burst_refill: 0.5, ..Default::default() });
+    cooldowns.record_spawn(EffectKind::Gravity);
+    cooldowns.record_spawn(EffectKind::Gravity);
+    assert!(!cooldowns.can_spawn());
+    cooldowns.update(1.);
+    assert!(!cooldowns.can_spawn());
+    cooldowns.update(1.);
+    assert!(cooldowns.can_spawn());
+    // The bucket never holds more than its capacity.
+    cooldowns.update(100.);
+    for _ in 0..2 {
+      assert!(cooldowns.can_spawn());
+      cooldowns.record_spawn(EffectKind::Gravity);
+    }
+    assert!(!cooldowns.can_spawn());
+    // Shrinking the bucket drops the extra tokens.
+    cooldowns.update(100.);
+    cooldowns.set_config(CooldownConfig { burst_capacity: 1, burst_refill: 0.5, ..Default::default() });
+    cooldowns.record_spawn(EffectKind::Gravity);
+    assert!(!cooldowns.can_spawn());
+  }
+
+  #[test]
+  fn user_limiter_cooldown_cap_and_exemptions() {
+    let mut limiter = UserLimiter::new(UserLimitConfig { user_cooldown: 60., max_per_message: 3, ..Default::default() });
+    let chatter = sender("1", "");
+    assert_eq!(limiter.admit(&chatter), Some(3));
+    assert_eq!(limiter.admit(&chatter), None);
+    assert_eq!(limiter.admit(&sender("2", "")), Some(3));
+    let moderator = sender("3", "moderator/1");
+    assert_eq!(limiter.admit(&moderator), Some(usize::MAX));
+    assert_eq!(limiter.admit(&moderator), Some(usize::MAX));
+
+    // VIPs are exempt by default, and held to the cooldown like anyone else when they aren't.
+    let vip = sender("4", "vip/1");
+    assert_eq!(limiter.admit(&vip), Some(usize::MAX));
+    assert_eq!(limiter.admit(&vip), Some(usize::MAX));
+    let mut limiter = UserLimiter::new(UserLimitConfig { user_cooldown: 60., exempt_vips: false, ..Default::default() });
+    assert_eq!(limiter.admit(&vip), Some(usize::MAX));
+    assert_eq!(limiter.admit(&vip), None);
+  }
+}
@@ -0,0 +1,146 @@
+use {
+  crate::emote_image::{
+    DecodedEmote, decode_emote,
+  },
+  std::{
+    collections::HashMap,
+    path::{
+      Path, PathBuf,
+    },
+    sync::{
+      Arc, RwLock,
+    },
+    time::{
+      Duration, SystemTime, UNIX_EPOCH,
+    },
+  },
+  tokio::task::JoinHandle,
+};
+
+/// How often the folder is polled for added, changed or removed files. There's no file system
+/// notifier, each poll lists the folder and compares modification times.
+const RESCAN_SECONDS: u64 = 5;
+const EXTENSIONS: [&str; 3] = ["png", "gif", "webp"];
+
+/// An image from the custom folder, ready to send like a downloaded emote.
+#[derive(Clone)]
+pub struct CustomEmote {
+  /// Name plus the file's modification time, so an edited file isn't served from the texture cache.
+  pub id: String,
+  pub name: String,
+  pub image: Arc<DecodedEmote>,
+  modified: SystemTime,
+}
+
+/// Emotes from `<data_dir>/custom/`, triggered by `:filename:` in chat.
+/// Cloning shares the same set, which the watcher task keeps up to date.
+#[derive(Clone)]
+pub struct CustomEmotes {
+  dir: PathBuf,
+  emotes: Arc<RwLock<HashMap<String, CustomEmote>>>,
+}
+
+/// Stops the folder watcher when the Twitch monitor ends or is aborted.
+pub struct CustomEmoteWatcher(JoinHandle<()>);
+
+impl Drop for CustomEmoteWatcher {
+  fn drop(&mut self) {
+    self.0.abort();
+  }
+}
+
+impl CustomEmotes {
+  pub fn new(dir: PathBuf) -> Self {
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+      log::error!("Failed to create custom emote folder: {}\nError: {}", dir.display(), e);
+    }
+    Self { dir, emotes: Arc::new(RwLock::new(HashMap::new())) }
+  }
+  /// Scans the folder now, then polls it every `RESCAN_SECONDS` until the returned watcher is dropped.
+  pub async fn watch(&self) -> CustomEmoteWatcher {
+    self.rescan().await;
+    let custom = self.clone();
+    CustomEmoteWatcher(tokio::spawn(async move {
+      let mut interval = tokio::time::interval(Duration::from_secs(RESCAN_SECONDS));
+      interval.tick().await; // The first tick is immediate and we just scanned
+      loop {
+        interval.tick().await;
+        custom.rescan().await;
+      }
+    }))
+  }
+  async fn rescan(&self) {
+    let dir = self.dir.clone();
+    let previous = match self.emotes.read() {
+      Ok(emotes) => { emotes.clone() }
+      Err(_) => { return; }
+    };
+    match tokio::task::spawn_blocking(move || scan_folder(&dir, &previous) ).await {
+      Ok(scanned) => {
+        if let Ok(mut emotes) = self.emotes.write() {
+          *emotes = scanned;
+        }
+      }
+      Err(e) => { log::error!("Custom emote scan task failed: {}", e); }
+    }
+  }
+  /// Words written as `:name:`, in message order. Names aren't case sensitive.
+  pub fn find_in_message(&self, text: &str) -> Vec<CustomEmote> {
+    let Ok(emotes) = self.emotes.read() else { return vec![]; };
+    if emotes.is_empty() { return vec![]; }
+    text.split_whitespace()
+      .filter_map(|word| word.strip_prefix(':').and_then(|word| word.strip_suffix(':') ) )
+      .filter_map(|name| emotes.get(&name.to_lowercase()) )
+      .cloned()
+      .collect()
+  }
+}
+
+/// Reuses entries whose file hasn't changed since `previous`, so only new or edited files are decoded.
+fn scan_folder(dir: &Path, previous: &HashMap<String, CustomEmote>) -> HashMap<String, CustomEmote> {
+  let mut scanned = HashMap::new();
+  let entries = match std::fs::read_dir(dir) {
+    Ok(entries) => { entries }
+    Err(e) => {
+      log::error!("Failed to read custom emote folder: {}\nError: {}", dir.display(), e);
+      return scanned;
+    }
+  };
+  for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path() ) ) {
+    let Some(extension) = path.extension().and_then(|ext| ext.to_str() ) else { continue; };
+    if !EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()) { continue; }
+    let Some(name) = path.file_stem().and_then(|stem| stem.to_str() ) else { continue; };
+    let key = name.to_lowercase();
+    let Ok(modified) = std::fs::metadata(&path).and_then(|meta| meta.modified() ) else { continue; };
+    if let Some(emote) = previous.get(&key)
+      && emote.modified == modified
+    {
+      scanned.insert(key, emote.clone());
+      continue;
+    }
+    match load_custom_emote(&path, name, modified) {
+      Ok(emote) => {
+        log::info!("Loaded custom emote :{}: from {}", key, path.display());
+        scanned.insert(key, emote);
+      }
+      Err(e) => { log::error!("Skipping custom emote {}: {}", path.display(), e); }
+    }
+  }
+  for key in previous.keys().filter(|key| !scanned.contains_key(*key) ) {
+    log::info!("Custom emote :{}: removed", key);
+  }
+  scanned
+}
+
+fn load_custom_emote(path: &Path, name: &str, modified: SystemTime) -> Result<CustomEmote, anyhow::Error> {
+  let img = std::fs::read(path)?;
+  // Decoding every frame rejects anything a downloaded emote would be rejected for.
+  let image = Arc::new(decode_emote(&img)?);
+  let stamp = modified.duration_since(UNIX_EPOCH).map(|d| d.as_millis() ).unwrap_or(0);
+  Ok(CustomEmote {
+    id: format!("{}@{}", name.to_lowercase(), stamp),
+    name: name.to_owned(),
+    image,
+    modified,
+  })
+}
@@ -0,0 +1,64 @@
+use {
+  crate::{
+    emote_image::DecodedEmote,
+    providers::EmoteProvider,
+  },
+  std::{
+    collections::HashMap,
+    sync::{
+      Arc, Mutex,
+    },
+    time::{
+      Duration, Instant,
+    },
+  },
+};
+
+/// Emotes kept decoded on the worker side.
+const DECODED_CAPACITY: usize = 256;
+/// How long a decoded emote is reused before it's looked up in sqlite again, so stale
+/// emotes still get revalidated while they're popular.
+const DECODED_TTL: Duration = Duration::from_secs(600);
+
+struct DecodedEntry {
+  image: Arc<DecodedEmote>,
+  loaded_at: Instant,
+  last_used: Instant,
+}
+
+/// Emotes decoded recently, so an emote used over and over isn't read from sqlite and
+/// decoded again every time. The least recently used one is dropped when it's full.
+/// Cloning shares the same cache.
+#[derive(Clone, Default)]
+pub struct DecodedCache {
+  entries: Arc<Mutex<HashMap<(EmoteProvider, String), DecodedEntry>>>,
+}
+
+impl DecodedCache {
+  pub fn get(&self, provider: EmoteProvider, id: &str) -> Option<Arc<DecodedEmote>> {
+    let Ok(mut entries) = self.entries.lock() else { return None; };
+    let key = (provider, id.to_owned());
+    let entry = entries.get_mut(&key)?;
+    if entry.loaded_at.elapsed() > DECODED_TTL {
+      entries.remove(&key);
+      return None;
+    }
+    entry.last_used = Instant::now();
+    Some(entry.image.clone())
+  }
+  pub fn insert(&self, provider: EmoteProvider, id: &str, image: Arc<DecodedEmote>) {
+    let Ok(mut entries) = self.entries.lock() else { return; };
+    if entries.len() >= DECODED_CAPACITY
+      && let Some(oldest) = entries.iter().min_by_key(|(_, entry)| entry.last_used ).map(|(key, _)| key.clone() )
+    {
+      entries.remove(&oldest);
+    }
+    let now = Instant::now();
+    entries.insert((provider, id.to_owned()), DecodedEntry { image, loaded_at: now, last_used: now });
+  }
+  pub fn clear(&self) {
+    if let Ok(mut entries) = self.entries.lock() {
+      entries.clear();
+    }
+  }
+}
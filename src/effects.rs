@@ -1,4 +1,7 @@
 use {
+  crate::chat::{
+    ChatSender, MessageTrigger,
+  },
   enterpolation::{
     easing::{
       smootherstep,
@@ -10,6 +13,10 @@ use {
       // vec2,
     },
   },
+  image::{
+    DynamicImage,
+    imageops::FilterType,
+  },
   obs_wrapper::graphics::*,
   rand::{
     // Rng,
@@ -19,8 +26,232 @@ use {
     Distribution,
     UnitCircle,
   },
+  std::{
+    collections::HashMap,
+    rc::Rc,
+  },
 };
 
+pub const GRAVITY: f32 = 1800.;
+pub const BOUNCE: f32 = 0.6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EffectKind {
+  Gravity,
+  InchWorm,
+  SlideUp,
+  JumpingPlumber,
+  GrowingTree,
+}
+
+impl EffectKind {
+  pub const ALL: [EffectKind; 5] = [
+    EffectKind::Gravity, EffectKind::InchWorm, EffectKind::SlideUp,
+    EffectKind::JumpingPlumber, EffectKind::GrowingTree,
+  ];
+  /// Node name used for this effect in config.kdl
+  pub fn kdl_name(&self) -> &'static str {
+    match self {
+      EffectKind::Gravity => { "gravity" }
+      EffectKind::InchWorm => { "inchworm" }
+      EffectKind::SlideUp => { "slideup" }
+      EffectKind::JumpingPlumber => { "plumber" }
+      EffectKind::GrowingTree => { "tree" }
+    }
+  }
+  pub fn from_kdl_name(name: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|kind| kind.kdl_name().eq_ignore_ascii_case(name))
+  }
+  /// Effects that draw artwork of their own over the wall, a plumber sprite or a whole tree,
+  /// rather than only moving the emote. An `effects` block has to name them to use them, so
+  /// a block written for a plain emote wall stays plain.
+  pub fn is_opt_in(&self) -> bool {
+    matches!(self, EffectKind::JumpingPlumber | EffectKind::GrowingTree)
+  }
+  pub fn default_weight(&self) -> u32 {
+    match self {
+      EffectKind::Gravity => { 70 }
+      EffectKind::InchWorm => { 20 }
+      EffectKind::SlideUp => { 10 }
+      EffectKind::JumpingPlumber => { 10 }
+      EffectKind::GrowingTree => { 5 }
+    }
+  }
+  pub fn init(&self, screen_w: f32, screen_h: f32, emote_w: f32, emote_h: f32, assets: &mut EffectAssets, rng: &mut ThreadRng) -> Box<dyn EmoteEffect + 'static> {
+    match self {
+      EffectKind::Gravity => { GravityEffect::init(screen_w, screen_h, emote_w, emote_h, GRAVITY, BOUNCE, rng) }
+      EffectKind::InchWorm => { InchWormEffect::init(screen_w, screen_h, emote_w, emote_h, rng) }
+      EffectKind::SlideUp => { SlideUpEffect::init(screen_w, screen_h, emote_w, emote_h, rng) }
+      EffectKind::JumpingPlumber => {
+        match assets.plumber() {
+          Some(sprite) => { JumpingPlumberEffect::init(screen_w, screen_h, emote_w, emote_h, sprite, rng) }
+          None => { GravityEffect::init(screen_w, screen_h, emote_w, emote_h, GRAVITY, BOUNCE, rng) }
+        }
+      }
+      EffectKind::GrowingTree => { GrowingTreeEffect::init(screen_w, screen_h, emote_w, emote_h, rng) }
+    }
+  }
+}
+
+const PLUMBER_SHEET: &[u8] = include_bytes!("../assets/jumping_plumber.png");
+const PLUMBER_FRAME_SIZE: u32 = 16;
+const PLUMBER_SCALE: u32 = 6;
+const PLUMBER_RUN_FRAMES: usize = 3;
+const PLUMBER_JUMP_FRAME: usize = 3;
+
+/// Textures shared by every effect that needs more than the emote itself.
+/// Loaded the first time an effect asks for them.
+#[derive(Default)]
+pub struct EffectAssets {
+  plumber: Option<Rc<SpriteSheet>>,
+}
+
+impl EffectAssets {
+  pub fn plumber(&mut self) -> Option<Rc<SpriteSheet>> {
+    if self.plumber.is_none() {
+      match SpriteSheet::from_png(PLUMBER_SHEET, PLUMBER_FRAME_SIZE, PLUMBER_FRAME_SIZE, PLUMBER_SCALE) {
+        Ok(sheet) => { self.plumber = Some(Rc::new(sheet)); }
+        Err(e) => { log::error!("Failed to load bundled plumber sprite sheet: {}", e); }
+      }
+    }
+    self.plumber.clone()
+  }
+}
+
+pub struct SpriteSheet {
+  right: Vec<GraphicsTexture>,
+  left: Vec<GraphicsTexture>,
+  frame_w: u32,
+  frame_h: u32,
+}
+
+impl SpriteSheet {
+  /// Frames are read left to right from a single row, all facing right, and
+  /// scaled up with nearest neighbour so the pixel art stays sharp.
+  pub fn from_png(bytes: &[u8], frame_w: u32, frame_h: u32, scale: u32) -> Result<Self, image::ImageError> {
+    let sheet = image::load_from_memory(bytes)?;
+    let mut right = vec![];
+    let mut left = vec![];
+    for i in 0..(sheet.width() / frame_w) {
+      let frame = sheet.crop_imm(i * frame_w, 0, frame_w, frame_h)
+        .resize_exact(frame_w * scale, frame_h * scale, FilterType::Nearest);
+      left.push(texture_from_image(&frame.fliph()));
+      right.push(texture_from_image(&frame));
+    }
+    Ok(Self { right, left, frame_w: frame_w * scale, frame_h: frame_h * scale })
+  }
+  pub fn frame(&self, index: usize, facing_right: bool) -> &GraphicsTexture {
+    let frames = if facing_right { &self.right } else { &self.left };
+    &frames[index % frames.len()]
+  }
+}
+
+pub fn texture_from_image(img: &DynamicImage) -> GraphicsTexture {
+  let rgba = img.to_rgba8();
+  let mut texture = GraphicsTexture::new(
+    rgba.width(), rgba.height(),
+    GraphicsColorFormat::RGBA,
+  );
+  texture.set_image(rgba.as_raw(), rgba.width() * 4, false);
+  texture
+}
+
+/// Relative odds of each effect being picked for a new emote. A weight of 0 disables the effect.
+#[derive(Debug, Clone)]
+pub struct EffectWeights {
+  weights: Vec<(EffectKind, u32)>,
+}
+
+impl Default for EffectWeights {
+  fn default() -> Self {
+    Self { weights: EffectKind::ALL.iter().map(|kind| (*kind, kind.default_weight()) ).collect() }
+  }
+}
+
+impl EffectWeights {
+  pub fn weight(&self, kind: EffectKind) -> u32 {
+    self.weights.iter().find(|(k,_)| *k == kind).map(|(_,w)| *w).unwrap_or(0)
+  }
+  pub fn set_weight(&mut self, kind: EffectKind, weight: u32) {
+    match self.weights.iter_mut().find(|(k,_)| *k == kind) {
+      Some((_,w)) => { *w = weight; }
+      None => { self.weights.push((kind, weight)); }
+    }
+  }
+  /// Returns None when every effect has a weight of 0.
+  pub fn pick(&self, rng: &mut ThreadRng) -> Option<EffectKind> {
+    self.pick_where(rng, |_| true)
+  }
+  /// Weighted pick among only the effects `allowed` accepts.
+  pub fn pick_where(&self, rng: &mut ThreadRng, allowed: impl Fn(EffectKind) -> bool) -> Option<EffectKind> {
+    let candidates: Vec<(EffectKind, u32)> = self.weights.iter().copied().filter(|(kind,_)| allowed(*kind) ).collect();
+    let total: u32 = candidates.iter().map(|(_,w)| *w).sum();
+    if total == 0 { return None; }
+    let mut picker = rng.random_range(0..total);
+    for (kind, weight) in candidates.into_iter() {
+      if picker < weight { return Some(kind); }
+      picker -= weight;
+    }
+    None
+  }
+}
+
+/// What a message needs for an effect to be used. Any one of the requirements set is enough.
+#[derive(Debug, Clone, Default)]
+pub struct EffectRequirement {
+  pub subscriber: bool,
+  pub min_bits: u32,
+  pub reward_id: Option<String>,
+}
+
+impl EffectRequirement {
+  pub fn is_open(&self) -> bool {
+    !self.subscriber && self.min_bits == 0 && self.reward_id.is_none()
+  }
+  pub fn is_met(&self, sender: &ChatSender, trigger: &MessageTrigger) -> bool {
+    self.is_open()
+      || (self.subscriber && sender.is_subscriber())
+      || (self.min_bits > 0 && trigger.bits >= self.min_bits)
+      || (self.reward_id.is_some() && trigger.custom_reward_id == self.reward_id)
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EffectGates {
+  pub requirements: HashMap<EffectKind, EffectRequirement>,
+  /// Drop emotes whose picked effect isn't unlocked instead of falling back to an effect that is.
+  pub ignore_unqualified: bool,
+}
+
+impl EffectGates {
+  pub fn allows(&self, kind: EffectKind, sender: &ChatSender, trigger: &MessageTrigger) -> bool {
+    self.requirements.get(&kind).is_none_or(|req| req.is_met(sender, trigger) )
+  }
+  pub fn pick(&self, weights: &EffectWeights, rng: &mut ThreadRng, sender: &ChatSender, trigger: &MessageTrigger, ready: impl Fn(EffectKind) -> bool) -> Option<EffectKind> {
+    if self.ignore_unqualified {
+      weights.pick_where(rng, ready).filter(|kind| self.allows(*kind, sender, trigger) )
+    } else {
+      weights.pick_where(rng, |kind| ready(kind) && self.allows(kind, sender, trigger) )
+    }
+  }
+}
+
+/// Overrides for one channel when monitoring several. Channels without a profile use the
+/// top level `effects` block at full weight.
+#[derive(Debug, Clone)]
+pub struct ChannelProfile {
+  /// Chance from 0 to 1 that an emote from this channel is used at all.
+  pub weight: f32,
+  /// Replaces the top level effect weights and gates for this channel.
+  pub effects: Option<(EffectWeights, EffectGates)>,
+}
+
+impl Default for ChannelProfile {
+  fn default() -> Self {
+    Self { weight: 1., effects: None }
+  }
+}
+
 pub trait EmoteEffect {
   fn update_dimensions(&mut self, w: f32, h: f32);
   fn update(&mut self, seconds: f32);
@@ -46,19 +277,24 @@ impl GravityEffect {
   pub fn init(screen_w: f32, screen_h: f32, emote_w: f32, emote_h: f32, gravity: f32, bounce: f32, rng: &mut ThreadRng) -> Box<dyn EmoteEffect + 'static> {
     let mut pos = Vec2::ZERO;
     let mut vel = Vec2::ZERO;
-    let scl = Vec2::ONE;
     pos.x = rng.random_range(0.1..0.9) as f32 * screen_w;
     vel.x = rng.random_range(-0.15..0.15) * screen_w;
     let life_total = rng.random_range(2.0..5.0);
-    Box::new(Self {
+    Box::new(Self::new(screen_w, screen_h, emote_w, emote_h, gravity, bounce, pos, vel, life_total))
+  }
+  /// For effects that hand an emote over to gravity part way through.
+  #[allow(clippy::too_many_arguments)]
+  pub fn new(screen_w: f32, screen_h: f32, emote_w: f32, emote_h: f32, gravity: f32, bounce: f32, pos: Vec2, vel: Vec2, life_total: f32) -> Self {
+    Self {
       screen_w, screen_h,
       emote_w, emote_h,
       life_total,
       life_lived: 0.,
       g: gravity,
       bounce,
-      pos, vel, scl,
-    })
+      pos, vel,
+      scl: Vec2::ONE,
+    }
   }
 }
 
@@ -265,3 +501,305 @@ impl EmoteEffect for InchWormEffect {
   }
 }
 
+
+pub struct JumpingPlumberEffect {
+  screen_w: f32,
+  screen_h: f32,
+  emote_w: f32,
+  emote_h: f32,
+  sprite: Rc<SpriteSheet>,
+  /// 1.0 when the plumber runs right and the emote slides left, -1.0 for the reverse.
+  dir: f32,
+  life_lived: f32,
+  anim_time: f32,
+  emote_pos: Vec2,
+  emote_vel: Vec2,
+  knock_vel: Vec2,
+  knocked: bool,
+  plumber_pos: Vec2,
+  plumber_vel: Vec2,
+  jump_speed: f32,
+  jumping: bool,
+}
+
+impl JumpingPlumberEffect {
+  const RUN_SPEED: f32 = 320.;
+  const SLIDE_SPEED: f32 = 220.;
+  const MAX_LIFE: f32 = 15.;
+  pub fn init(screen_w: f32, screen_h: f32, emote_w: f32, emote_h: f32, sprite: Rc<SpriteSheet>, rng: &mut ThreadRng) -> Box<dyn EmoteEffect + 'static> {
+    let dir = if rng.random_bool(0.5) { 1. } else { -1. };
+    let (pw, ph) = (sprite.frame_w as f32, sprite.frame_h as f32);
+    let emote_pos = Vec2::new(if dir > 0. { screen_w } else { -emote_w }, screen_h - emote_h);
+    let plumber_pos = Vec2::new(if dir > 0. { -pw } else { screen_w }, screen_h - ph);
+    let emote_vel = Vec2::new(-dir * Self::SLIDE_SPEED, 0.);
+    let plumber_vel = Vec2::new(dir * Self::RUN_SPEED, 0.);
+    let knock_vel = Vec2::new(
+      dir * rng.random_range(0.2..0.5) * screen_w,
+      -rng.random_range(900.0..1300.0),
+    );
+    // Jump high enough to clear the emote with some air to spare.
+    let jump_speed = (2. * GRAVITY * (emote_h + 150.)).sqrt();
+    Box::new(Self {
+      screen_w, screen_h,
+      emote_w, emote_h,
+      sprite,
+      dir,
+      life_lived: 0.,
+      anim_time: 0.,
+      emote_pos, emote_vel, knock_vel,
+      knocked: false,
+      plumber_pos, plumber_vel,
+      jump_speed,
+      jumping: false,
+    })
+  }
+  fn plumber_size(&self) -> Vec2 {
+    Vec2::new(self.sprite.frame_w as f32, self.sprite.frame_h as f32)
+  }
+  /// Seconds after leaving the floor until the plumber's feet come back down to the top of the emote.
+  fn time_to_stomp(&self) -> f32 {
+    let v = self.jump_speed;
+    (v + (v * v - 2. * GRAVITY * self.emote_h).max(0.).sqrt()) / GRAVITY
+  }
+  fn plumber_offscreen(&self) -> bool {
+    let pw = self.plumber_size().x;
+    if self.dir > 0. { self.plumber_pos.x > self.screen_w } else { self.plumber_pos.x + pw < 0. }
+  }
+  fn emote_offscreen(&self) -> bool {
+    if self.knocked {
+      self.emote_pos.y > self.screen_h
+    } else if self.dir > 0. {
+      self.emote_pos.x + self.emote_w < 0.
+    } else {
+      self.emote_pos.x > self.screen_w
+    }
+  }
+}
+
+impl EmoteEffect for JumpingPlumberEffect {
+  fn update_dimensions(&mut self, w: f32, h: f32) {
+    (self.screen_w, self.screen_h) = (w, h);
+  }
+  fn update(&mut self, seconds: f32) {
+    self.life_lived += seconds;
+    self.anim_time += seconds;
+    let plumber_size = self.plumber_size();
+    let plumber_floor = self.screen_h - plumber_size.y;
+    // Emote
+    if self.knocked {
+      self.emote_vel.y += GRAVITY * seconds;
+    }
+    self.emote_pos += self.emote_vel * seconds;
+    // Plumber
+    if !self.jumping && !self.knocked {
+      let plumber_center = self.plumber_pos.x + plumber_size.x / 2.;
+      let emote_center = self.emote_pos.x + self.emote_w / 2.;
+      let gap = (emote_center - plumber_center) * self.dir;
+      let closing_speed = Self::RUN_SPEED + Self::SLIDE_SPEED;
+      if gap > 0. && gap <= closing_speed * self.time_to_stomp() {
+        self.jumping = true;
+        self.plumber_vel.y = -self.jump_speed;
+      }
+    }
+    if self.jumping {
+      self.plumber_vel.y += GRAVITY * seconds;
+    }
+    self.plumber_pos += self.plumber_vel * seconds;
+    // Stomp
+    if self.jumping && !self.knocked && self.plumber_vel.y > 0. {
+      let feet = self.plumber_pos.y + plumber_size.y;
+      let overlap = ((self.plumber_pos.x + plumber_size.x / 2.) - (self.emote_pos.x + self.emote_w / 2.)).abs();
+      if feet >= self.emote_pos.y && overlap < (plumber_size.x + self.emote_w) / 2. {
+        self.knocked = true;
+        self.emote_vel = self.knock_vel;
+        self.plumber_vel.y = -self.jump_speed * 0.5;
+      }
+    }
+    if self.plumber_pos.y >= plumber_floor && self.plumber_vel.y > 0. {
+      self.plumber_pos.y = plumber_floor;
+      self.plumber_vel.y = 0.;
+      self.jumping = false;
+    }
+  }
+  fn draw(&self, tex: &GraphicsTexture) {
+    tex.draw(self.emote_pos.x as i32, self.emote_pos.y as i32, 0, 0, false);
+    let frame = if self.jumping { PLUMBER_JUMP_FRAME } else { (self.anim_time / 0.08) as usize % PLUMBER_RUN_FRAMES };
+    self.sprite.frame(frame, self.dir > 0.)
+      .draw(self.plumber_pos.x as i32, self.plumber_pos.y as i32, 0, 0, false);
+  }
+  fn is_alive(&self) -> bool {
+    self.life_lived < Self::MAX_LIFE && !(self.plumber_offscreen() && self.emote_offscreen())
+  }
+}
+
+struct TreeNode {
+  /// Center of this copy of the emote.
+  pos: Vec2,
+  size: f32,
+  /// Fraction of the grow time at which this copy appears.
+  birth: f32,
+}
+
+pub struct GrowingTreeEffect {
+  screen_w: f32,
+  screen_h: f32,
+  emote_w: f32,
+  emote_h: f32,
+  nodes: Vec<TreeNode>,
+  fruit_pos: Vec2,
+  fruit: Option<GravityEffect>,
+  fruit_life: f32,
+  phase: usize,
+  phase_time: f32,
+}
+
+impl GrowingTreeEffect {
+  /// Seconds for grow, ripen, drop and wither. Withering shrinks the copies away, tips first.
+  const PHASES: [f32; 4] = [3.5, 1.0, 0.0, 1.5];
+  const MAX_DEPTH: u32 = 3;
+  const MIN_SIZE: f32 = 12.;
+  pub fn init(screen_w: f32, screen_h: f32, emote_w: f32, emote_h: f32, rng: &mut ThreadRng) -> Box<dyn EmoteEffect + 'static> {
+    let trunk_size = (screen_h * 0.1).clamp(48., 160.);
+    let root = Vec2::new(rng.random_range(0.15..0.85) * screen_w, screen_h);
+    let angle = -std::f32::consts::FRAC_PI_2 + rng.random_range(-0.15..0.15);
+    let mut nodes = vec![];
+    let mut tips = vec![];
+    Self::grow_branch(&mut nodes, &mut tips, root, angle, trunk_size, 0, 0., rng);
+    // Leave the last copies a moment to pop in before the grow phase ends.
+    let last_birth = nodes.iter().map(|node| node.birth ).fold(1., f32::max);
+    for node in nodes.iter_mut() {
+      node.birth = node.birth / last_birth * 0.85;
+    }
+    let fruit_pos = tips.choose(rng).copied().unwrap_or(root);
+    Box::new(Self {
+      screen_w, screen_h,
+      emote_w, emote_h,
+      nodes,
+      fruit_pos,
+      fruit: None,
+      fruit_life: rng.random_range(2.0..4.0),
+      phase: 0,
+      phase_time: 0.,
+    })
+  }
+  /// Lays copies of the emote along one branch, then splits into smaller branches until they get too small.
+  #[allow(clippy::too_many_arguments)]
+  fn grow_branch(nodes: &mut Vec<TreeNode>, tips: &mut Vec<Vec2>, start: Vec2, angle: f32, size: f32, depth: u32, birth: f32, rng: &mut ThreadRng) {
+    let steps = rng.random_range(3..=5);
+    let mut pos = start;
+    let mut size = size;
+    let mut birth = birth;
+    let mut angle = angle;
+    for _ in 0..steps {
+      pos += Vec2::from_angle(angle) * size * 0.55;
+      nodes.push(TreeNode { pos, size, birth });
+      size *= 0.92;
+      birth += 1.;
+      angle += rng.random_range(-0.08..0.08);
+    }
+    if depth >= Self::MAX_DEPTH || size * 0.7 < Self::MIN_SIZE {
+      tips.push(pos);
+      return;
+    }
+    let branches = rng.random_range(2..=3);
+    for i in 0..branches {
+      let spread = (i as f32 - (branches - 1) as f32 / 2.) * rng.random_range(0.4..0.7);
+      Self::grow_branch(nodes, tips, pos, angle + spread, size * 0.7, depth + 1, birth, rng);
+    }
+  }
+  fn draw_scaled(&self, tex: &GraphicsTexture, center: Vec2, size: f32) {
+    if size < 1. { return; }
+    let (w, h) = (size, size * self.emote_h / self.emote_w);
+    tex.draw((center.x - w / 2.) as i32, (center.y - h / 2.) as i32, w as u32, h as u32, false);
+  }
+}
+
+impl EmoteEffect for GrowingTreeEffect {
+  fn update_dimensions(&mut self, w: f32, h: f32) {
+    (self.screen_w, self.screen_h) = (w, h);
+    if let Some(fruit) = self.fruit.as_mut() {
+      fruit.update_dimensions(w, h);
+    }
+  }
+  fn update(&mut self, seconds: f32) {
+    self.phase_time += seconds;
+    match self.phase {
+      2 => {
+        if self.fruit.is_none() {
+          let pos = self.fruit_pos - Vec2::new(self.emote_w, self.emote_h) / 2.;
+          self.fruit = Some(GravityEffect::new(
+            self.screen_w, self.screen_h, self.emote_w, self.emote_h,
+            GRAVITY, BOUNCE, pos, Vec2::ZERO, self.fruit_life,
+          ));
+        }
+        let Some(fruit) = self.fruit.as_mut() else { return; };
+        fruit.update(seconds);
+        if !fruit.is_alive() {
+          self.phase += 1;
+          self.phase_time = 0.;
+        }
+      }
+      phase if phase < Self::PHASES.len() => {
+        if self.phase_time > Self::PHASES[phase] {
+          self.phase += 1;
+          self.phase_time = 0.;
+        }
+      }
+      _ => {}
+    }
+  }
+  fn draw(&self, tex: &GraphicsTexture) {
+    let Some(duration) = Self::PHASES.get(self.phase) else { return; };
+    let pct = if *duration > 0. { (self.phase_time / duration).min(1.) } else { 1. };
+    for node in self.nodes.iter() {
+      let scale = match self.phase {
+        // Each copy pops in as the growth reaches it
+        0 => { smootherstep(((pct - node.birth) * 8.).clamp(0., 1.)) }
+        // Withers by shrinking back down, tips first.
+        3 => { smootherstep((1. - pct * 2. + (1. - node.birth)).clamp(0., 1.)) }
+        _ => { 1. }
+      };
+      self.draw_scaled(tex, node.pos, node.size * scale);
+    }
+    match self.phase {
+      1 => { self.draw_scaled(tex, self.fruit_pos, self.emote_w * smootherstep(pct)); }
+      2 => {
+        if let Some(fruit) = self.fruit.as_ref() {
+          fruit.draw(tex);
+        }
+      }
+      _ => {}
+    }
+  }
+  fn is_alive(&self) -> bool {
+    self.phase < Self::PHASES.len()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pick_where_only_picks_allowed_weighted_effects() {
+    let mut rng = rand::rng();
+    let mut weights = EffectWeights::default();
+    weights.set_weight(EffectKind::SlideUp, 0);
+    for _ in 0..200 {
+      let kind = weights.pick_where(&mut rng, |kind| kind != EffectKind::Gravity ).unwrap();
+      assert!(kind != EffectKind::Gravity && kind != EffectKind::SlideUp, "picked {:?}", kind);
+    }
+    assert_eq!(weights.pick_where(&mut rng, |kind| kind == EffectKind::InchWorm ), Some(EffectKind::InchWorm));
+    assert_eq!(weights.pick_where(&mut rng, |kind| kind == EffectKind::SlideUp ), None);
+    assert_eq!(weights.pick_where(&mut rng, |_| false ), None);
+  }
+
+  #[test]
+  fn pick_is_none_when_every_weight_is_zero() {
+    let mut weights = EffectWeights::default();
+    for kind in EffectKind::ALL {
+      weights.set_weight(kind, 0);
+    }
+    assert_eq!(weights.pick(&mut rand::rng()), None);
+  }
+}
@@ -0,0 +1,172 @@
+use {
+  crate::emote_image::DecodedEmote,
+  anyhow::{
+    Result,
+    anyhow,
+  },
+  cosmic_text::{
+    Attrs, Buffer, Color, FontSystem, Metrics, Shaping, SwashCache,
+  },
+  image::{
+    Rgba, RgbaImage,
+    imageops,
+  },
+  std::{
+    collections::HashMap,
+    sync::{
+      Arc, Mutex,
+    },
+  },
+  unicode_segmentation::UnicodeSegmentation,
+};
+
+/// Rendered size, about the same as Twitch's 3.0 emote images.
+const EMOJI_PX: f32 = 112.;
+
+/// Emoji in message order. Each one is a whole grapheme cluster, so ZWJ sequences,
+/// skin tones, flags and keycaps come out as a single emoji.
+pub fn find_emoji(text: &str) -> Vec<&str> {
+  text.graphemes(true).filter(|cluster| is_emoji_cluster(cluster) ).collect()
+}
+
+/// Symbols that default to text presentation, like © or ❤, only count with the emoji variation selector.
+fn is_emoji_cluster(cluster: &str) -> bool {
+  cluster.chars().next().is_some_and(is_emoji_presentation) || cluster.contains('\u{FE0F}')
+}
+
+/// Close to the Unicode `Emoji_Presentation` property without carrying its tables around.
+fn is_emoji_presentation(c: char) -> bool {
+  matches!(c as u32,
+    0x231A..=0x231B | 0x23E9..=0x23EC | 0x23F0 | 0x23F3 | 0x25FD..=0x25FE
+    | 0x2614..=0x2615 | 0x2648..=0x2653 | 0x267F | 0x2693 | 0x26A1 | 0x26AA..=0x26AB
+    | 0x26BD..=0x26BE | 0x26C4..=0x26C5 | 0x26CE | 0x26D4 | 0x26EA | 0x26F2..=0x26F3
+    | 0x26F5 | 0x26FA | 0x26FD | 0x2705 | 0x270A..=0x270B | 0x2728 | 0x274C | 0x274E
+    | 0x2753..=0x2755 | 0x2757 | 0x2795..=0x2797 | 0x27B0 | 0x27BF | 0x2B1B..=0x2B1C
+    | 0x2B50 | 0x2B55
+    | 0x1F004 | 0x1F0CF | 0x1F18E | 0x1F191..=0x1F19A | 0x1F1E6..=0x1F1FF | 0x1F201
+    | 0x1F21A | 0x1F22F | 0x1F232..=0x1F236 | 0x1F238..=0x1F23A | 0x1F250..=0x1F251
+    | 0x1F300..=0x1F64F | 0x1F680..=0x1F6FF | 0x1F7E0..=0x1F7F0 | 0x1F90C..=0x1F9FF
+    | 0x1FA70..=0x1FAFF
+  )
+}
+
+/// Draws emoji with cosmic-text, the same way `FontStudio` draws text, but keeping the
+/// colors of the system's color emoji font.
+pub struct EmojiRasterizer {
+  font_system: FontSystem,
+  swash_cache: SwashCache,
+  buffer: Buffer,
+}
+
+impl EmojiRasterizer {
+  /// Loading the system fonts is slow, so only do this off the render thread.
+  pub fn new() -> Self {
+    let mut font_system = FontSystem::new();
+    let swash_cache = SwashCache::new();
+    let metrics = Metrics::new(EMOJI_PX, EMOJI_PX * 1.25);
+    let buffer = Buffer::new(&mut font_system, metrics);
+    Self { font_system, swash_cache, buffer }
+  }
+  /// Cropped to the drawn pixels. Fails if nothing was drawn.
+  pub fn rasterize(&mut self, emoji: &str) -> Result<DecodedEmote> {
+    let canvas = (EMOJI_PX * 2.) as u32; // Room for sequences the font draws as several glyphs
+    let mut buffer = self.buffer.borrow_with(&mut self.font_system);
+    buffer.set_size(Some(canvas as f32), Some(canvas as f32));
+    buffer.set_text(emoji, &Attrs::new(), Shaping::Advanced, None);
+    buffer.shape_until_scroll(false);
+    let mut img = RgbaImage::from_pixel(canvas, canvas, Rgba([0,0,0,0]));
+    buffer.draw(&mut self.swash_cache, Color::rgb(0xFF, 0xFF, 0xFF), |x,y,_w,_h,color| {
+      if color.a() == 0 || x < 0 || y < 0 || x >= canvas as i32 || y >= canvas as i32 {
+        return;
+      }
+      blend_over(img.get_pixel_mut(x as u32, y as u32), color);
+    });
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (canvas, canvas, 0, 0);
+    for (x, y, pxl) in img.enumerate_pixels() {
+      if pxl[3] == 0 { continue; }
+      (min_x, min_y) = (min_x.min(x), min_y.min(y));
+      (max_x, max_y) = (max_x.max(x), max_y.max(y));
+    }
+    if min_x > max_x {
+      return Err(anyhow!("No font could draw emoji {}", emoji));
+    }
+    let cropped = imageops::crop_imm(&img, min_x, min_y, max_x - min_x + 1, max_y - min_y + 1).to_image();
+    Ok(DecodedEmote { frames: vec![cropped.into()], delays: vec![] })
+  }
+}
+
+impl Default for EmojiRasterizer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Glyphs of a sequence the font can't join may overlap, so blend rather than overwrite.
+fn blend_over(pxl: &mut Rgba<u8>, color: Color) {
+  let src_a = u32::from(color.a());
+  let dst_a = u32::from(pxl[3]) * (255 - src_a) / 255;
+  let out_a = src_a + dst_a;
+  if out_a == 0 { return; }
+  let mix = |src: u8, dst: u8| ((u32::from(src) * src_a + u32::from(dst) * dst_a) / out_a) as u8;
+  *pxl = Rgba([
+    mix(color.r(), pxl[0]), mix(color.g(), pxl[1]), mix(color.b(), pxl[2]), out_a as u8,
+  ]);
+}
+
+/// Rasterized emoji shared across reconnects. The rasterizer is created on first use
+/// and runs on tokio's blocking pool.
+#[derive(Clone, Default)]
+pub struct EmojiImages {
+  rasterizer: Arc<Mutex<Option<EmojiRasterizer>>>,
+  images: Arc<Mutex<HashMap<String, Arc<DecodedEmote>>>>,
+}
+
+impl EmojiImages {
+  pub async fn get(&self, emoji: &str) -> Option<Arc<DecodedEmote>> {
+    if let Ok(images) = self.images.lock()
+      && let Some(image) = images.get(emoji)
+    {
+      return Some(image.clone());
+    }
+    let rasterizer = self.rasterizer.clone();
+    let key = emoji.to_owned();
+    let rendered = tokio::task::spawn_blocking(move || {
+      let mut rasterizer = rasterizer.lock().map_err(|_| anyhow!("Emoji rasterizer lock poisoned") )?;
+      rasterizer.get_or_insert_with(EmojiRasterizer::new).rasterize(&key)
+    }).await;
+    match rendered {
+      Ok(Ok(image)) => {
+        let image = Arc::new(image);
+        if let Ok(mut images) = self.images.lock() {
+          images.insert(emoji.to_owned(), image.clone());
+        }
+        Some(image)
+      }
+      Ok(Err(e)) => {
+        log::error!("Failed to rasterize emoji {}: {}", emoji, e);
+        None
+      }
+      Err(e) => {
+        log::error!("Emoji rasterizer task failed: {}", e);
+        None
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn finds_whole_emoji_clusters() {
+    assert_eq!(find_emoji("hi 😀 there 👍🏽!"), ["😀", "👍🏽"]);
+    assert_eq!(find_emoji("👨‍👩‍👧 🇯🇵 1️⃣"), ["👨‍👩‍👧", "🇯🇵", "1️⃣"]);
+  }
+
+  #[test]
+  fn text_symbols_need_the_variation_selector() {
+    assert!(find_emoji("© ❤ 1 # plain text").is_empty());
+    assert_eq!(find_emoji("❤️ ©️"), ["❤️", "©️"]);
+  }
+}
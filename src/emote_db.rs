@@ -0,0 +1,427 @@
+use {
+  crate::{
+    EmoteData,
+    config_kdl::EkbConfigDirs,
+    emote_image::DecodedEmote,
+    providers::{
+      EmoteProvider, ThirdPartyEmote,
+    },
+  },
+  reqwest::header::{
+    ETAG, HeaderMap, HeaderName, LAST_MODIFIED,
+  },
+  rusqlite::{
+    Connection, OptionalExtension, Transaction,
+    params,
+  },
+  std::time::{
+    SystemTime, UNIX_EPOCH,
+  },
+};
+
+/// Limits for `emotes.db3`. 0 disables a limit.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+  pub max_entries: u32,
+  /// Total bytes of cached images, leaving out sqlite's own overhead.
+  pub max_bytes: u64,
+  /// Seconds before a cached emote is checked with its CDN again.
+  pub ttl: u64,
+}
+
+impl Default for CacheConfig {
+  fn default() -> Self {
+    Self {
+      max_entries: 0,
+      max_bytes: 256 * 1024 * 1024,
+      ttl: 7 * 24 * 60 * 60,
+    }
+  }
+}
+
+/// One step of the `emotes.db3` schema. Each runs once, in its own transaction,
+/// and is recorded in `schema_version`. Never edit a released migration, add a new one.
+struct Migration {
+  version: u32,
+  description: &'static str,
+  apply: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+  Migration { version: 1, description: "provider and usage metadata columns", apply: migrate_v1_metadata },
+  Migration { version: 2, description: "source URL and HTTP validators for revalidation", apply: migrate_v2_validators },
+];
+
+pub fn connect_sqlite(path: &mut EkbConfigDirs) -> Result<Connection, rusqlite::Error> {
+  if path.data.is_file() { path.data.pop(); }
+  path.data.push("emotes.db3");
+  let mut db = Connection::open(&path.data)?;
+  migrate(&mut db)?;
+  Ok(db)
+}
+
+/// Brings the database up to the newest schema this build knows about.
+pub fn migrate(db: &mut Connection) -> rusqlite::Result<()> {
+  db.execute(
+    "CREATE TABLE IF NOT EXISTS schema_version (
+        version     INTEGER PRIMARY KEY,
+        description TEXT NOT NULL,
+        applied_at  INTEGER NOT NULL
+    )",
+    (),
+  )?;
+  let current: u32 = db.query_row("SELECT COALESCE(MAX(version), 0) FROM schema_version", (), |row| row.get(0) )?;
+  let latest = MIGRATIONS.last().map(|migration| migration.version ).unwrap_or(0);
+  if current > latest {
+    log::warn!("emotes.db3 is at schema version {} but this build only knows up to {}. Leaving it as is.", current, latest);
+    return Ok(());
+  }
+  for migration in MIGRATIONS.iter().filter(|migration| migration.version > current ) {
+    let tx = db.transaction()?;
+    (migration.apply)(&tx)?;
+    tx.execute(
+      "INSERT INTO schema_version (version, description, applied_at) VALUES (?1, ?2, ?3)",
+      params![migration.version, migration.description, unix_now()],
+    )?;
+    tx.commit()?;
+    log::info!("Migrated emotes.db3 to schema version {}: {}", migration.version, migration.description);
+  }
+  Ok(())
+}
+
+/// Ids are only unique per provider, so the primary key changes and the table is rebuilt.
+/// Handles databases from before `provider` existed as well as brand new ones.
+fn migrate_v1_metadata(tx: &Transaction) -> rusqlite::Result<()> {
+  tx.execute(
+    "CREATE TABLE emotes_v1 (
+        provider     VARCHAR(32) NOT NULL DEFAULT 'twitch',
+        id           VARCHAR(255) NOT NULL,
+        name         VARCHAR(255),
+        img          BLOB NOT NULL,
+        format       VARCHAR(16),
+        width        INTEGER,
+        height       INTEGER,
+        frame_count  INTEGER,
+        fetched_at   INTEGER,
+        last_used_at INTEGER,
+        use_count    INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (provider, id)
+    )",
+    (),
+  )?;
+  if tx.table_exists(None, "emotes")? {
+    let provider = match tx.column_exists(None, "emotes", "provider")? {
+      true => { "provider" }
+      false => { "'twitch'" }
+    };
+    tx.execute(
+      &format!("INSERT INTO emotes_v1 (provider, id, name, img) SELECT {}, id, name, img FROM emotes", provider),
+      (),
+    )?;
+    tx.execute("DROP TABLE emotes", ())?;
+  }
+  tx.execute("ALTER TABLE emotes_v1 RENAME TO emotes", ())?;
+  Ok(())
+}
+
+/// Rows from before this keep NULLs and fall back to the URLs the emote was found with.
+fn migrate_v2_validators(tx: &Transaction) -> rusqlite::Result<()> {
+  tx.execute_batch(
+    "ALTER TABLE emotes ADD COLUMN url TEXT;
+     ALTER TABLE emotes ADD COLUMN etag TEXT;
+     ALTER TABLE emotes ADD COLUMN last_modified TEXT;
+     CREATE INDEX emotes_last_used ON emotes (last_used_at);",
+  )
+}
+
+pub fn unix_now() -> i64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64 ).unwrap_or(0)
+}
+
+/// What's known about an emote image once it has been decoded.
+pub struct EmoteMeta {
+  pub format: Option<&'static str>,
+  pub width: u32,
+  pub height: u32,
+  pub frame_count: u32,
+}
+
+impl EmoteMeta {
+  pub fn new(img: &[u8], decoded: &DecodedEmote) -> Self {
+    let first = decoded.first_frame();
+    Self {
+      format: image::guess_format(img).ok().and_then(|format| format.extensions_str().first().copied() ),
+      width: first.width,
+      height: first.height,
+      frame_count: decoded.frames.len() as u32,
+    }
+  }
+  /// For an emote decoded earlier, whose bytes are gone. The format is left for the store to keep.
+  pub fn from_decoded(decoded: &DecodedEmote) -> Self {
+    let first = decoded.first_frame();
+    Self { format: None, width: first.width, height: first.height, frame_count: decoded.frames.len() as u32 }
+  }
+}
+
+/// What a CDN gave us to ask whether an image changed since it was fetched.
+#[derive(Debug, Clone, Default)]
+pub struct CacheValidators {
+  pub url: Option<String>,
+  pub etag: Option<String>,
+  pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+  pub fn from_response(url: &str, headers: &HeaderMap) -> Self {
+    let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok() ).map(str::to_owned);
+    Self {
+      url: Some(url.to_owned()),
+      etag: header(ETAG),
+      last_modified: header(LAST_MODIFIED),
+    }
+  }
+}
+
+/// A cache hit, with what's needed to decide whether to revalidate it.
+pub struct CachedEmote {
+  pub emote: EmoteData,
+  pub fetched_at: Option<i64>,
+  pub validators: CacheValidators,
+}
+
+impl CachedEmote {
+  pub fn is_stale(&self, ttl: u64) -> bool {
+    ttl > 0 && self.fetched_at.is_none_or(|fetched_at| unix_now() - fetched_at >= ttl as i64 )
+  }
+}
+
+/// Totals for the log and the cache limits.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+  pub entries: u64,
+  /// Image bytes only, see `CacheConfig::max_bytes`.
+  pub bytes: u64,
+  pub uses: u64,
+}
+
+/// The emote cache: lookups, and inserts that respect the size limits. Blocking, so it's
+/// only used from the `EmoteStore` thread.
+pub struct EmoteCache {
+  db: Connection,
+  config: CacheConfig,
+}
+
+impl EmoteCache {
+  pub fn open(dirs: &mut EkbConfigDirs, config: CacheConfig) -> rusqlite::Result<Self> {
+    let db = connect_sqlite(dirs)?;
+    let cache = Self { db, config };
+    match cache.evict() {
+      Ok(0) => {}
+      Ok(evicted) => { log::info!("Evicted {} emotes to stay within the cache limits", evicted); }
+      Err(e) => { log::error!("Failed to evict emotes from DB: {}", e); }
+    }
+    Ok(cache)
+  }
+  pub fn config(&self) -> &CacheConfig {
+    &self.config
+  }
+  pub fn find(&self, provider: EmoteProvider, id: &str) -> rusqlite::Result<Option<CachedEmote>> {
+    self.db.query_one(
+      "SELECT id, name, img, fetched_at, url, etag, last_modified FROM emotes WHERE provider=?1 AND id=?2", params![provider.as_str(), id], |row| {
+        Ok(CachedEmote {
+          emote: EmoteData{
+            provider,
+            id: row.get(0)?,
+            name: row.get(1)?,
+            img: row.get(2)?,
+          },
+          fetched_at: row.get(3)?,
+          validators: CacheValidators {
+            url: row.get(4)?,
+            etag: row.get(5)?,
+            last_modified: row.get(6)?,
+          },
+        })
+      }
+    ).optional()
+  }
+  /// Evicts least recently used emotes afterwards if the cache went over a limit.
+  pub fn insert(&self, emote: &EmoteData, meta: &EmoteMeta, validators: &CacheValidators) -> rusqlite::Result<()> {
+    let now = unix_now();
+    self.db.execute(
+      "INSERT OR REPLACE INTO emotes (provider, id, name, img, format, width, height, frame_count, fetched_at, last_used_at, use_count, url, etag, last_modified)
+       VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9, 1, ?10, ?11, ?12)",
+      params![
+        emote.provider.as_str(), emote.id, emote.name, emote.img,
+        meta.format, meta.width, meta.height, meta.frame_count, now,
+        validators.url, validators.etag, validators.last_modified,
+      ],
+    )?;
+    let evicted = self.evict()?;
+    if evicted > 0 {
+      log::info!("Evicted {} least recently used emotes from DB", evicted);
+    }
+    Ok(())
+  }
+  /// Records a cache hit. Rows carried over from before the metadata columns get them filled in here.
+  pub fn touch(&self, provider: EmoteProvider, id: &str, meta: &EmoteMeta) -> rusqlite::Result<()> {
+    self.db.execute(
+      "UPDATE emotes SET
+          last_used_at = ?3,
+          use_count    = use_count + 1,
+          format       = COALESCE(format, ?4),
+          width        = COALESCE(width, ?5),
+          height       = COALESCE(height, ?6),
+          frame_count  = COALESCE(frame_count, ?7),
+          fetched_at   = COALESCE(fetched_at, ?3)
+       WHERE provider=?1 AND id=?2",
+      params![provider.as_str(), id, unix_now(), meta.format, meta.width, meta.height, meta.frame_count],
+    )?;
+    Ok(())
+  }
+  /// Deletes least recently used emotes until both `max_entries` and `max_bytes` are met.
+  pub fn evict(&self) -> rusqlite::Result<usize> {
+    let CacheConfig { max_entries, max_bytes, .. } = self.config;
+    if max_entries == 0 && max_bytes == 0 { return Ok(0); }
+    let CacheStats { entries: count, bytes, .. } = self.stats()?;
+    let over_entries = |count: u64| max_entries > 0 && count > max_entries as u64;
+    let over_bytes = |bytes: u64| max_bytes > 0 && bytes > max_bytes;
+    if !over_entries(count) && !over_bytes(bytes) { return Ok(0); }
+    let mut doomed = vec![];
+    {
+      let mut oldest = self.db.prepare(
+        "SELECT rowid, LENGTH(img) FROM emotes ORDER BY COALESCE(last_used_at, fetched_at, 0) ASC",
+      )?;
+      let (mut count, mut bytes) = (count, bytes);
+      let mut rows = oldest.query(())?;
+      while (over_entries(count) || over_bytes(bytes))
+        && let Some(row) = rows.next()?
+      {
+        let (rowid, size): (i64, u64) = (row.get(0)?, row.get(1)?);
+        doomed.push(rowid);
+        count -= 1;
+        bytes = bytes.saturating_sub(size);
+      }
+    }
+    for rowid in doomed.iter() {
+      self.db.execute("DELETE FROM emotes WHERE rowid=?1", params![rowid])?;
+    }
+    Ok(doomed.len())
+  }
+  pub fn stats(&self) -> rusqlite::Result<CacheStats> {
+    self.db.query_row(
+      "SELECT COUNT(*), COALESCE(SUM(LENGTH(img)), 0), COALESCE(SUM(use_count), 0) FROM emotes", (), |row| {
+        Ok(CacheStats { entries: row.get(0)?, bytes: row.get(1)?, uses: row.get(2)? })
+      }
+    )
+  }
+  /// Cached 7TV, BTTV and FFZ emotes with their codes, for recognizing them in chat offline.
+  pub fn third_party_emotes(&self) -> rusqlite::Result<Vec<ThirdPartyEmote>> {
+    let mut query = self.db.prepare(
+      "SELECT provider, id, name, COALESCE(url, '') FROM emotes WHERE provider IN ('seventv', 'bttv', 'ffz') AND name IS NOT NULL",
+    )?;
+    let rows = query.query_map((), |row| {
+      Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+    })?;
+    let mut emotes = vec![];
+    for row in rows {
+      let (provider, id, code, url) = row?;
+      let Some(provider) = EmoteProvider::from_name(&provider) else { continue; };
+      emotes.push(ThirdPartyEmote { provider, id, code, url });
+    }
+    Ok(emotes)
+  }
+  /// Cached Twitch emotes as `(id, name)`, most used first.
+  pub fn popular_twitch_emotes(&self, limit: usize) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut query = self.db.prepare(
+      "SELECT id, name FROM emotes WHERE provider='twitch' AND name IS NOT NULL ORDER BY use_count DESC LIMIT ?1",
+    )?;
+    let rows = query.query_map(params![limit as i64], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)) )?;
+    rows.collect()
+  }
+  /// Deletes every cached emote and shrinks the file. Returns how many were removed.
+  pub fn purge(&self) -> rusqlite::Result<usize> {
+    let purged = self.db.execute("DELETE FROM emotes", ())?;
+    self.db.execute_batch("VACUUM;")?;
+    Ok(purged)
+  }
+  /// The CDN answered 304 Not Modified.
+  pub fn mark_fresh(&self, provider: EmoteProvider, id: &str) -> rusqlite::Result<()> {
+    self.db.execute(
+      "UPDATE emotes SET fetched_at=?3 WHERE provider=?1 AND id=?2",
+      params![provider.as_str(), id, unix_now()],
+    )?;
+    Ok(())
+  }
+  /// The CDN sent the image again. Returns true if it changed, in which case its metadata is
+  /// cleared and filled in again on the next cache hit. False if the row was evicted meanwhile.
+  pub fn refresh_image(&self, provider: EmoteProvider, id: &str, img: &[u8], validators: &CacheValidators) -> rusqlite::Result<bool> {
+    let unchanged = self.db.query_row(
+      "SELECT img = ?3 FROM emotes WHERE provider=?1 AND id=?2",
+      params![provider.as_str(), id, img],
+      |row| row.get::<_, bool>(0),
+    ).optional()?.unwrap_or(false);
+    if unchanged {
+      self.mark_fresh(provider, id)?;
+      return Ok(false);
+    }
+    let updated = self.db.execute(
+      "UPDATE emotes SET img=?3, fetched_at=?4, url=?5, etag=?6, last_modified=?7,
+          format=NULL, width=NULL, height=NULL, frame_count=NULL
+       WHERE provider=?1 AND id=?2",
+      params![provider.as_str(), id, img, unix_now(), validators.url, validators.etag, validators.last_modified],
+    )?;
+    Ok(updated > 0)
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn columns(db: &Connection) -> Vec<String> {
+    let mut statement = db.prepare("SELECT name FROM pragma_table_info('emotes') ORDER BY cid").unwrap();
+    statement.query_map((), |row| row.get(0) ).unwrap().collect::<rusqlite::Result<_>>().unwrap()
+  }
+
+  #[test]
+  fn migrates_pre_versioned_cache_and_keeps_emotes() {
+    let mut db = Connection::open_in_memory().unwrap();
+    // The table as the plugin created it before schema versions existed.
+    db.execute_batch(
+      "CREATE TABLE emotes (
+          id   VARCHAR(255) PRIMARY KEY,
+          name VARCHAR(255),
+          img  BLOB NOT NULL
+      );
+      INSERT INTO emotes (id, name, img) VALUES ('25', 'Kappa', x'0102'), ('1902', 'Keepo', x'03');",
+    ).unwrap();
+    migrate(&mut db).unwrap();
+    migrate(&mut db).unwrap();
+
+    assert_eq!(columns(&db), [
+      "provider", "id", "name", "img", "format", "width", "height", "frame_count",
+      "fetched_at", "last_used_at", "use_count", "url", "etag", "last_modified",
+    ]);
+    let versions: Vec<u32> = db.prepare("SELECT version FROM schema_version ORDER BY version").unwrap()
+      .query_map((), |row| row.get(0) ).unwrap().collect::<rusqlite::Result<_>>().unwrap();
+    assert_eq!(versions, MIGRATIONS.iter().map(|migration| migration.version ).collect::<Vec<_>>());
+    let rows: Vec<(String, String, String, Vec<u8>, u32)> = db.prepare("SELECT provider, id, name, img, use_count FROM emotes ORDER BY id").unwrap()
+      .query_map((), |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)) ).unwrap()
+      .collect::<rusqlite::Result<_>>().unwrap();
+    assert_eq!(rows, [
+      ("twitch".to_owned(), "1902".to_owned(), "Keepo".to_owned(), vec![3], 0),
+      ("twitch".to_owned(), "25".to_owned(), "Kappa".to_owned(), vec![1, 2], 0),
+    ]);
+  }
+
+  #[test]
+  fn migrates_a_new_cache() {
+    let mut db = Connection::open_in_memory().unwrap();
+    migrate(&mut db).unwrap();
+    assert_eq!(columns(&db).len(), 14);
+    let count: u32 = db.query_row("SELECT COUNT(*) FROM emotes", (), |row| row.get(0) ).unwrap();
+    assert_eq!(count, 0);
+  }
+}
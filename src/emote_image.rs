@@ -0,0 +1,90 @@
+use {
+  anyhow::{
+    Result,
+    anyhow,
+  },
+  image::{
+    AnimationDecoder, Frames, ImageFormat, RgbaImage,
+    codecs::{
+      gif::GifDecoder,
+      webp::WebPDecoder,
+    },
+  },
+  std::io::Cursor,
+};
+
+/// One RGBA8 frame, ready to be handed to `GraphicsTexture::set_image`.
+pub struct DecodedFrame {
+  pub width: u32,
+  pub height: u32,
+  pub rgba: Vec<u8>,
+}
+
+impl DecodedFrame {
+  pub fn linesize(&self) -> u32 {
+    self.width * 4 // pixels wide * 4 bytes per pixel for RGBA
+  }
+}
+
+impl From<RgbaImage> for DecodedFrame {
+  fn from(img: RgbaImage) -> Self {
+    Self {
+      width: img.width(),
+      height: img.height(),
+      rgba: img.into_raw(),
+    }
+  }
+}
+
+/// Every frame of an emote, decoded off the render thread. `delays` is empty for still images.
+pub struct DecodedEmote {
+  pub frames: Vec<DecodedFrame>,
+  pub delays: Vec<f32>,
+}
+
+impl DecodedEmote {
+  pub fn first_frame(&self) -> &DecodedFrame {
+    &self.frames[0]
+  }
+}
+
+/// Decodes GIF and animated WebP frame by frame, anything else `image` understands as a still.
+/// Never returns an emote without frames.
+pub fn decode_emote(img_data: &[u8]) -> Result<DecodedEmote> {
+  let format = image::guess_format(img_data).map_err(|e| anyhow!("Failed to guess_format of image data: {}", e) )?;
+  let decoded = match format {
+    ImageFormat::Gif => {
+      let gif = GifDecoder::new(Cursor::new(img_data)).map_err(|e| anyhow!("Failed to read GIF header: {}", e) )?;
+      decode_frames(gif.into_frames())?
+    }
+    ImageFormat::WebP => {
+      let webp = WebPDecoder::new(Cursor::new(img_data)).map_err(|e| anyhow!("Failed to read WebP header: {}", e) )?;
+      if webp.has_animation() {
+        decode_frames(webp.into_frames())?
+      } else {
+        decode_still(img_data)?
+      }
+    }
+    _ => { decode_still(img_data)? }
+  };
+  if decoded.frames.is_empty() {
+    return Err(anyhow!("Image data decoded without any frames"));
+  }
+  Ok(decoded)
+}
+
+fn decode_frames(frames: Frames<'_>) -> Result<DecodedEmote> {
+  let mut decoded = DecodedEmote { frames: vec![], delays: vec![] };
+  for frame_result in frames {
+    let frame = frame_result.map_err(|e| anyhow!("Failed to decode animation frame: {}", e) )?;
+    let (ms,_) = frame.delay().numer_denom_ms();
+    decoded.delays.push((ms as f32) / 1000.);
+    decoded.frames.push(frame.into_buffer().into());
+  }
+  Ok(decoded)
+}
+
+fn decode_still(img_data: &[u8]) -> Result<DecodedEmote> {
+  let img = image::load_from_memory(img_data).map_err(|e| anyhow!("Failed to decode image: {}", e) )?;
+  Ok(DecodedEmote { frames: vec![img.into_rgba8().into()], delays: vec![] })
+}
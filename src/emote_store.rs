@@ -0,0 +1,156 @@
+use {
+  crate::{
+    EmoteData,
+    config_kdl::EkbConfigDirs,
+    emote_db::*,
+    providers::{
+      EmoteProvider, ThirdPartyEmote,
+    },
+  },
+  anyhow::{
+    Result,
+    anyhow,
+  },
+  std::{
+    collections::HashSet,
+    sync::{
+      Arc, Mutex,
+    },
+  },
+  tokio::sync::{
+    mpsc::{
+      UnboundedReceiver, UnboundedSender,
+    },
+    oneshot,
+  },
+};
+
+type Reply<T> = oneshot::Sender<rusqlite::Result<T>>;
+
+enum StoreRequest {
+  Get { provider: EmoteProvider, id: String, reply: Reply<Option<CachedEmote>> },
+  Put { emote: EmoteData, meta: EmoteMeta, validators: CacheValidators, reply: Reply<()> },
+  Touch { provider: EmoteProvider, id: String, meta: EmoteMeta, reply: Reply<()> },
+  MarkFresh { provider: EmoteProvider, id: String, reply: Reply<()> },
+  RefreshImage { provider: EmoteProvider, id: String, img: Vec<u8>, validators: CacheValidators, reply: Reply<bool> },
+  Stats { reply: Reply<CacheStats> },
+  ThirdParty { reply: Reply<Vec<ThirdPartyEmote>> },
+  PopularTwitch { limit: usize, reply: Reply<Vec<(String, String)>> },
+  Purge { reply: Reply<usize> },
+}
+
+/// Async access to `emotes.db3`. A dedicated thread owns the sqlite connection and answers
+/// requests in order, so disk I/O never holds up a tokio worker. Cloning shares the same
+/// thread, which exits once every handle is dropped.
+#[derive(Clone)]
+pub struct EmoteStore {
+  tx: UnboundedSender<StoreRequest>,
+  config: CacheConfig,
+  revalidating: Arc<Mutex<HashSet<(EmoteProvider, String)>>>,
+}
+
+impl EmoteStore {
+  /// Opens and migrates the database on the store's thread, and waits until that's done.
+  pub async fn open(mut dirs: EkbConfigDirs, config: CacheConfig) -> Result<Self> {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let cache_config = config.clone();
+    std::thread::Builder::new().name("ekb-emote-store".to_owned()).spawn(move || {
+      let cache = match EmoteCache::open(&mut dirs, cache_config) {
+        Ok(cache) => { cache }
+        Err(e) => {
+          _ = ready_tx.send(Err(e));
+          return;
+        }
+      };
+      _ = ready_tx.send(Ok(()));
+      serve(cache, rx);
+    })?;
+    ready_rx.await.map_err(|_| anyhow!("Emote store thread exited while opening the database") )??;
+    Ok(Self { tx, config, revalidating: Arc::default() })
+  }
+  pub fn config(&self) -> &CacheConfig {
+    &self.config
+  }
+  async fn request<T>(&self, request: impl FnOnce(Reply<T>) -> StoreRequest) -> Result<T> {
+    let (reply, rx) = oneshot::channel();
+    self.tx.send(request(reply)).map_err(|_| anyhow!("Emote store has shut down") )?;
+    Ok(rx.await.map_err(|_| anyhow!("Emote store dropped a request") )??)
+  }
+  pub async fn get(&self, provider: EmoteProvider, id: &str) -> Result<Option<CachedEmote>> {
+    let id = id.to_owned();
+    self.request(|reply| StoreRequest::Get { provider, id, reply } ).await
+  }
+  /// Stores a downloaded emote. Least recently used emotes are evicted if that went over a limit.
+  pub async fn put(&self, emote: EmoteData, meta: EmoteMeta, validators: CacheValidators) -> Result<()> {
+    self.request(|reply| StoreRequest::Put { emote, meta, validators, reply } ).await
+  }
+  /// Records a cache hit.
+  pub async fn touch(&self, provider: EmoteProvider, id: &str, meta: EmoteMeta) -> Result<()> {
+    let id = id.to_owned();
+    self.request(|reply| StoreRequest::Touch { provider, id, meta, reply } ).await
+  }
+  pub async fn mark_fresh(&self, provider: EmoteProvider, id: &str) -> Result<()> {
+    let id = id.to_owned();
+    self.request(|reply| StoreRequest::MarkFresh { provider, id, reply } ).await
+  }
+  /// True if the image had changed and was replaced.
+  pub async fn refresh_image(&self, provider: EmoteProvider, id: &str, img: Vec<u8>, validators: CacheValidators) -> Result<bool> {
+    let id = id.to_owned();
+    self.request(|reply| StoreRequest::RefreshImage { provider, id, img, validators, reply } ).await
+  }
+  pub async fn stats(&self) -> Result<CacheStats> {
+    self.request(|reply| StoreRequest::Stats { reply } ).await
+  }
+  pub async fn third_party_emotes(&self) -> Result<Vec<ThirdPartyEmote>> {
+    self.request(|reply| StoreRequest::ThirdParty { reply } ).await
+  }
+  /// Cached Twitch emotes as `(id, name)`, most used first.
+  pub async fn popular_twitch_emotes(&self, limit: usize) -> Result<Vec<(String, String)>> {
+    self.request(|reply| StoreRequest::PopularTwitch { limit, reply } ).await
+  }
+  /// Empties the cache. Returns how many emotes were removed.
+  pub async fn purge(&self) -> Result<usize> {
+    self.request(|reply| StoreRequest::Purge { reply } ).await
+  }
+  /// None if the emote is already being revalidated. It counts as in progress until the ticket drops.
+  pub fn begin_revalidation(&self, provider: EmoteProvider, id: &str) -> Option<RevalidationTicket> {
+    let key = (provider, id.to_owned());
+    let mut revalidating = self.revalidating.lock().ok()?;
+    if !revalidating.insert(key.clone()) { return None; }
+    Some(RevalidationTicket { revalidating: self.revalidating.clone(), key })
+  }
+}
+
+pub struct RevalidationTicket {
+  revalidating: Arc<Mutex<HashSet<(EmoteProvider, String)>>>,
+  key: (EmoteProvider, String),
+}
+
+impl Drop for RevalidationTicket {
+  fn drop(&mut self) {
+    if let Ok(mut revalidating) = self.revalidating.lock() {
+      revalidating.remove(&self.key);
+    }
+  }
+}
+
+/// Runs on the store's thread until every `EmoteStore` handle is dropped.
+fn serve(cache: EmoteCache, mut rx: UnboundedReceiver<StoreRequest>) {
+  while let Some(request) = rx.blocking_recv() {
+    match request {
+      StoreRequest::Get { provider, id, reply } => { _ = reply.send(cache.find(provider, &id)); }
+      StoreRequest::Put { emote, meta, validators, reply } => { _ = reply.send(cache.insert(&emote, &meta, &validators)); }
+      StoreRequest::Touch { provider, id, meta, reply } => { _ = reply.send(cache.touch(provider, &id, &meta)); }
+      StoreRequest::MarkFresh { provider, id, reply } => { _ = reply.send(cache.mark_fresh(provider, &id)); }
+      StoreRequest::RefreshImage { provider, id, img, validators, reply } => {
+        _ = reply.send(cache.refresh_image(provider, &id, &img, &validators));
+      }
+      StoreRequest::Stats { reply } => { _ = reply.send(cache.stats()); }
+      StoreRequest::ThirdParty { reply } => { _ = reply.send(cache.third_party_emotes()); }
+      StoreRequest::PopularTwitch { limit, reply } => { _ = reply.send(cache.popular_twitch_emotes(limit)); }
+      StoreRequest::Purge { reply } => { _ = reply.send(cache.purge()); }
+    }
+  }
+  log::info!("Emote store closed");
+}
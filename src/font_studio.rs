@@ -1,5 +1,11 @@
 // #![allow(dead_code,unused)]
 use {
+  crate::{
+    emote_image::DecodedEmote,
+    opacity::draw_with_opacity,
+    text_animation::*,
+    text_style::*,
+  },
   // crate::{
   //   config_kdl::*,
   //   plugin::{
@@ -13,46 +19,37 @@ use {
   // },
   image::{
     // AnimationDecoder, DynamicImage, ImageFormat,
-    Rgba, RgbaImage,
+    GrayImage, Luma, Pixel, Rgba, RgbaImage,
+    imageops::{
+      self, FilterType,
+    },
     // codecs::gif::GifDecoder,
   },
   cosmic_text::{
-    Attrs, AttrsOwned, Color, FontSystem, SwashCache, Buffer, Metrics, Shaping,
+    Align, Attrs, Color, Family, FontSystem, SwashCache, Buffer, Metrics, Shaping, Style, Weight,
   },
   // futures::StreamExt,
   obs_wrapper::graphics::*,
   std::{
     collections::VecDeque,
-    // sync::Arc,
+    sync::Arc,
   },
   // tokio::sync::mpsc::UnboundedSender,
 };
 
 pub struct FontStudio {
-  font_system: FontSystem,
-  swash_cache: SwashCache,
-  buffer: Buffer,
-  attrs: AttrsOwned,
+  rasterizer: TextRasterizer,
+  style: TextStyle,
   pub text_blocks: VecDeque<TextBlock>,
-  #[allow(dead_code)]
   screen_w: f32,
-  #[allow(dead_code)]
   screen_h: f32,
 }
 
 impl FontStudio {
   pub fn new() -> Self {
-    let mut font_system = FontSystem::new();
-    let swash_cache = SwashCache::new();
-    let metrics = Metrics::new(20.0, 24.0);
-    let buffer = Buffer::new(&mut font_system, metrics);
-    let attrs = Attrs::new();
-    let attrs = AttrsOwned::new(&attrs);
     Self {
-      font_system,
-      swash_cache,
-      buffer,
-      attrs,
+      rasterizer: TextRasterizer::new(),
+      style: TextStyle::default(),
       text_blocks: VecDeque::new(),
       screen_w: 1920.0, // probably fixme
       screen_h: 1080.0,
@@ -61,72 +58,352 @@ impl FontStudio {
   pub fn update_dimensions(&mut self, w: f32, h: f32) {
     (self.screen_w, self.screen_h) = (w, h);
   }
-  pub fn add_text_block(&mut self, image_width: u32, offset: (i32,i32), metrics: (f32,f32), life: Option<f32>, txt: &str) {
-    let (x_offset, y_offset) = offset;
+  /// The style set from config.kdl or Properties. Blocks are rendered with it, or with a
+  /// variation of it such as `style().sized(..)`.
+  pub fn style(&self) -> &TextStyle {
+    &self.style
+  }
+  /// Blocks already rendered keep the old style.
+  pub fn set_style(&mut self, style: TextStyle) {
+    self.style = style;
+  }
+  pub fn add_text_block(&mut self, image_width: u32, offset: (i32,i32), style: &TextStyle, transition: TextTransition, life: Option<f32>, txt: &str) {
+    let tblk = self.render_text_block(image_width, offset, style, life, txt).with_transition(transition);
+    self.text_blocks.push_back(tblk);
+  }
+  /// Like `add_text_block`, but replaces any block previously added under the same key.
+  pub fn set_text_block(&mut self, key: &str, image_width: u32, offset: (i32,i32), style: &TextStyle, transition: TextTransition, life: Option<f32>, txt: &str) {
+    self.remove_text_block(key);
+    let mut tblk = self.render_text_block(image_width, offset, style, life, txt).with_transition(transition);
+    tblk.key = Some(key.to_owned());
+    self.text_blocks.push_back(tblk);
+  }
+  pub fn remove_text_block(&mut self, key: &str) {
+    self.text_blocks.retain(|tblk| tblk.key.as_deref() != Some(key) );
+  }
+  fn render_text_block(&mut self, image_width: u32, offset: (i32,i32), style: &TextStyle, life: Option<f32>, txt: &str) -> TextBlock {
+    self.render_spans(image_width, offset, style, life, &[TextSpan::plain(txt)])
+  }
+  /// Renders mixed text and inline images into a block the caller keeps and draws itself.
+  /// `image_width` is the wrapping width plus padding. The block grows past it on every
+  /// side by however far the style's outline and shadow reach.
+  pub fn render_spans(&mut self, image_width: u32, offset: (i32,i32), style: &TextStyle, life: Option<f32>, spans: &[TextSpan]) -> TextBlock {
+    let rendered = self.rasterizer.rasterize(image_width, style, spans);
+    self.upload(rendered, offset, life)
+  }
+  /// Turns text rasterized elsewhere, like on a `TextRasterizer` of another thread, into a block.
+  pub fn upload(&self, rendered: RenderedText, offset: (i32,i32), life: Option<f32>) -> TextBlock {
+    TextBlock::new(rendered, offset, life, (self.screen_w, self.screen_h))
+  }
+  pub fn draw(&self) {
+    for tblk in self.text_blocks.iter() {
+      tblk.draw();
+    }
+  }
+}
+
+/// Shapes and draws text into plain pixels. Needs no graphics context, so it can run on any
+/// thread, but each one loads the system fonts again.
+pub struct TextRasterizer {
+  font_system: FontSystem,
+  swash_cache: SwashCache,
+  buffer: Buffer,
+}
+
+impl TextRasterizer {
+  /// Loading the system fonts is slow.
+  pub fn new() -> Self {
+    let mut font_system = FontSystem::new();
+    let swash_cache = SwashCache::new();
+    let metrics = Metrics::new(20.0, 24.0);
+    let buffer = Buffer::new(&mut font_system, metrics);
+    Self { font_system, swash_cache, buffer }
+  }
+  /// Everything `FontStudio::render_spans` does short of making the texture.
+  pub fn rasterize(&mut self, image_width: u32, style: &TextStyle, spans: &[TextSpan]) -> RenderedText {
     let mut buffer = self.buffer.borrow_with(&mut self.font_system);
-    let text_color = Color::rgb(0xFF, 0xFF, 0xFF);
-    let img_w = image_width.max(MIN_WIDTH);
-    let inner_w = img_w - (2 * PADDING);
+    let [r, g, b, a] = style.color;
+    let text_color = Color::rgba(r, g, b, a);
+    let metrics = style.metrics();
+    let padding = PADDING + style.effect_margin();
+    let inner_w = image_width.max(MIN_WIDTH) - (2 * PADDING);
+    let img_w = inner_w + (2 * padding);
     buffer.set_size(Some(inner_w as f32), None);
     buffer.set_metrics(Metrics::new(metrics.0, metrics.1));
-    buffer.set_text(txt, &self.attrs.as_attrs(), Shaping::Advanced, None);
+    let family = match style.family.as_deref() {
+      Some(name) => { Family::Name(name) }
+      None => { Family::SansSerif }
+    };
+    let mut default_attrs = Attrs::new().family(family).weight(Weight(style.weight));
+    if style.italic {
+      default_attrs = default_attrs.style(Style::Italic);
+    }
+    // Images are shaped as a no-break space stretched to the image's width, tagged with
+    // metadata so they can be found in the layout and drawn over once the text is done.
+    let mut images = vec![];
+    let rich_text: Vec<(&str, Attrs)> = spans.iter().map(|span| match span {
+      TextSpan::Text { text, color, bold } => {
+        let mut attrs = default_attrs.clone();
+        if let Some([r, g, b]) = color {
+          attrs = attrs.color(Color::rgb(*r, *g, *b));
+        }
+        if *bold {
+          attrs = attrs.weight(Weight(style.weight.max(Weight::BOLD.0)));
+        }
+        (text.as_str(), attrs)
+      }
+      TextSpan::Image(image) => {
+        let frame = image.first_frame();
+        let h = metrics.1.round().max(1.) as u32;
+        let w = ((frame.width as f32 * h as f32) / frame.height.max(1) as f32).round().max(1.) as u32;
+        images.push((image, w, h));
+        let attrs = default_attrs.clone()
+          .color(Color::rgba(0, 0, 0, 0))
+          .metadata(images.len())
+          .letter_spacing(w as f32 / metrics.0);
+        ("\u{A0}", attrs)
+      }
+    }).collect();
+    let align = style.centered.then_some(Align::Center);
+    buffer.set_rich_text(rich_text, &default_attrs, Shaping::Advanced, align);
     buffer.shape_until_scroll(false);
-    let img_h = buffer.layout_runs().map(|run| run.line_top + run.line_height)
-        .fold(0.0f32, f32::max).ceil() as u32 + (2 * PADDING);
-    let mut img = RgbaImage::from_pixel(img_w, img_h, Rgba([0,0,0,0]));
+    let placements: Vec<(usize, f32, f32, f32)> = buffer.layout_runs().flat_map(|run| {
+      run.glyphs.iter()
+        .filter(|glyph| glyph.metadata > 0 )
+        .map(move |glyph| (glyph.metadata - 1, glyph.x + glyph.w / 2., run.line_top, run.line_height) )
+    }).collect();
+    // Lines only start past the left edge when they're aligned away from it.
+    let content_x = buffer.layout_runs().filter_map(|run| run.glyphs.first().map(|glyph| glyph.x ) )
+        .reduce(f32::min).unwrap_or(0.).floor();
+    let content_w = buffer.layout_runs().map(|run| run.glyphs.first().map(|glyph| glyph.x ).unwrap_or(0.) + run.line_w - content_x)
+        .fold(0.0f32, f32::max).ceil();
+    let content_h = buffer.layout_runs().map(|run| run.line_top + run.line_height)
+        .fold(0.0f32, f32::max).ceil();
+    let img_h = content_h as u32 + (2 * padding);
+    let lines: Vec<TextLine> = buffer.layout_runs().map(|run| TextLine {
+      top: padding as f32 + run.line_top,
+      glyph_ends: run.glyphs.iter().map(|glyph| padding as f32 + glyph.x + glyph.w ).collect(),
+    }).collect();
+    // Glyphs go on their own layer first, so their coverage can be grown into an outline
+    // and blurred into a shadow underneath them.
+    let mut fill = RgbaImage::from_pixel(img_w, img_h, Rgba([0,0,0,0]));
     buffer.draw(&mut self.swash_cache, text_color, |x,y,_w,_h,color| {
       if color.a() == 0 { return; }
-      let img_x = x + PADDING as i32;
-      let img_y = y + PADDING as i32;
+      let img_x = x + padding as i32;
+      let img_y = y + padding as i32;
       if img_x < 0 || img_y < 0 || img_x >= img_w as i32 || img_y >= img_h as i32 {
         return;
       }
-      let pxl = img.get_pixel_mut(img_x as u32, img_y as u32);
+      let pxl = fill.get_pixel_mut(img_x as u32, img_y as u32);
       // let src_a = u16::from(color.a());
       // let dst_a = src_a + u16::from(pxl[3]) * (255 - src_a) / 255; // If we want to draw text on and existing image
       *pxl = Rgba([
         color.r(), color.g(), color.b(), color.a(),
       ]);
     });
-    let mut tex = GraphicsTexture::new(
-      img.width(), img.height(), 
-      GraphicsColorFormat::RGBA,
-    );
-    let linesize = img.width() * 4; // pixels wide * 4 bytes per pixel for RGBA
-    let pixels = img.into_raw();
-    tex.set_image(&pixels, linesize, false);
-    let tblk = TextBlock { tex, life, x_offset, y_offset, };
-    self.text_blocks.push_back(tblk);
-  }
-  pub fn draw(&self) {
-    for tblk in self.text_blocks.iter() {
-      tblk.draw();
+    let mut img = RgbaImage::from_pixel(img_w, img_h, Rgba([0,0,0,0]));
+    if let Some(background) = style.background {
+      let margin = BACKGROUND_MARGIN as f32;
+      let rect = (padding as f32 + content_x - margin, padding as f32 - margin, content_w + 2. * margin, content_h + 2. * margin);
+      fill_rounded_rect(&mut img, rect, style.background_radius, background);
+    }
+    let mask = GrayImage::from_fn(img_w, img_h, |x, y| Luma([fill.get_pixel(x, y)[3]]) );
+    let outline = style.has_outline().then(|| dilate(&mask, style.outline_width) );
+    if style.has_shadow() {
+      let shape = outline.as_ref().unwrap_or(&mask);
+      let shadow = match style.shadow_blur > 0. {
+        true => { imageops::blur(shape, style.shadow_blur) }
+        false => { shape.clone() }
+      };
+      let (dx, dy) = style.shadow_offset;
+      paint_mask(&mut img, &shadow, style.shadow_color, (dx.round() as i64, dy.round() as i64));
+    }
+    if let Some(outline) = outline.as_ref() {
+      paint_mask(&mut img, outline, style.outline_color, (0, 0));
     }
+    for (dst, src) in img.pixels_mut().zip(fill.pixels()) {
+      dst.blend(src);
+    }
+    for (index, center_x, line_top, line_height) in placements {
+      let Some((image, w, h)) = images.get(index) else { continue; };
+      let frame = image.first_frame();
+      let Some(still) = RgbaImage::from_raw(frame.width, frame.height, frame.rgba.clone()) else { continue; };
+      let still = imageops::resize(&still, *w, *h, FilterType::Triangle);
+      let x = padding as f32 + center_x - (*w as f32 / 2.);
+      let y = padding as f32 + line_top + (line_height - *h as f32) / 2.;
+      imageops::overlay(&mut img, &still, x.round() as i64, y.round() as i64);
+    }
+    RenderedText { img, padding, lines }
   }
 }
 
+/// A block's pixels before they're uploaded.
+pub struct RenderedText {
+  img: RgbaImage,
+  padding: u32,
+  lines: Vec<TextLine>,
+}
+
+/// A run of `render_spans` input.
+#[derive(Clone)]
+pub enum TextSpan {
+  /// White unless a color is given.
+  Text { text: String, color: Option<[u8; 3]>, bold: bool },
+  /// Drawn as tall as the line, in place of a character. Only the first frame is used.
+  Image(Arc<DecodedEmote>),
+}
+
+impl TextSpan {
+  pub fn plain(text: &str) -> Self {
+    Self::Text { text: text.to_owned(), color: None, bold: false }
+  }
+}
+
+/// Where a line of a block sits in its image, for the typewriter reveal.
+struct TextLine {
+  top: f32,
+  /// Right edge of each glyph, left to right.
+  glyph_ends: Vec<f32>,
+}
+
 pub struct TextBlock {
   tex: GraphicsTexture,
+  /// Kept so the texture can be re-uploaded partly revealed.
+  pixels: Vec<u8>,
+  width: u32,
+  height: u32,
+  /// Transparent border around the contents, including room for outlines and shadows.
+  padding: u32,
+  lines: Vec<TextLine>,
+  transition: TextTransition,
+  frame: TransitionFrame,
+  /// Glyphs revealed in the texture as last uploaded.
+  uploaded: usize,
+  /// Seconds since the block appeared.
+  age: f32,
   life: Option<f32>,
   x_offset: i32,
   y_offset: i32,
+  /// Slides start and end just past the edge of the screen.
+  screen: (f32, f32),
+  key: Option<String>,
 }
 
 impl TextBlock {
+  fn new(rendered: RenderedText, offset: (i32,i32), life: Option<f32>, screen: (f32, f32)) -> Self {
+    let RenderedText { img, padding, lines } = rendered;
+    let (x_offset, y_offset) = offset;
+    let (width, height) = (img.width(), img.height());
+    let mut tex = GraphicsTexture::new(
+      width, height, 
+      GraphicsColorFormat::RGBA,
+    );
+    let linesize = width * 4; // pixels wide * 4 bytes per pixel for RGBA
+    let pixels = img.into_raw();
+    tex.set_image(&pixels, linesize, false);
+    let glyphs = lines.iter().map(|line| line.glyph_ends.len() ).sum();
+    Self {
+      tex, pixels, width, height, padding, lines,
+      transition: TextTransition::default(),
+      frame: TransitionFrame::SHOWN,
+      uploaded: glyphs,
+      age: 0.,
+      life, x_offset, y_offset, screen, key: None,
+    }
+  }
+  /// Starts the block at the beginning of its enter animation.
+  pub fn with_transition(mut self, transition: TextTransition) -> Self {
+    self.transition = transition;
+    self.frame = transition.frame(self.age, self.life);
+    self.refresh_texture();
+    self
+  }
   pub fn draw(&self) {
-    self.tex.draw(self.x_offset, self.y_offset, 0, 0, false);
+    if self.frame.opacity <= 0. { return; }
+    let (mut x, mut y) = (self.x_offset as f32, self.y_offset as f32);
+    let (w, h) = (self.width as f32, self.height as f32);
+    if let Some((edge, remaining)) = self.frame.slide {
+      let (screen_w, screen_h) = self.screen;
+      match edge {
+        Edge::Left => { x -= (x + w) * remaining; }
+        Edge::Right => { x += (screen_w - x) * remaining; }
+        Edge::Top => { y -= (y + h) * remaining; }
+        Edge::Bottom => { y += (screen_h - y) * remaining; }
+      }
+    }
+    let (scaled_w, scaled_h) = (w * self.frame.scale, h * self.frame.scale);
+    if scaled_w < 1. || scaled_h < 1. { return; } // A size of 0 would draw it full size
+    x += (w - scaled_w) / 2.;
+    y += (h - scaled_h) / 2.;
+    draw_with_opacity(&self.tex, x.round() as i32, y.round() as i32, scaled_w.round() as u32, scaled_h.round() as u32, self.frame.opacity);
+  }
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+  pub fn padding(&self) -> u32 {
+    self.padding
+  }
+  pub fn life(&self) -> Option<f32> {
+    self.life
+  }
+  pub fn set_position(&mut self, x: i32, y: i32) {
+    (self.x_offset, self.y_offset) = (x, y);
+  }
+  /// Typewriter reveals re-upload the pixels with the unrevealed glyphs cleared, only when
+  /// another glyph shows up. Fades are left to `draw`.
+  fn refresh_texture(&mut self) {
+    let glyphs: usize = self.lines.iter().map(|line| line.glyph_ends.len() ).sum();
+    let revealed = (self.frame.reveal.clamp(0., 1.) * glyphs as f32).round() as usize;
+    if revealed == self.uploaded { return; }
+    self.uploaded = revealed;
+    let mut pixels = self.pixels.clone();
+    if revealed < glyphs {
+      // Outlines and shadows reach past the glyph they belong to.
+      let reach = self.padding.saturating_sub(PADDING) as f32;
+      let mut remaining = revealed;
+      let mut cut = vec![];
+      for (index, line) in self.lines.iter().enumerate() {
+        let shown = remaining.min(line.glyph_ends.len());
+        remaining -= shown;
+        let visible_x = match shown {
+          0 => { f32::NEG_INFINITY }
+          _ if shown == line.glyph_ends.len() => { f32::INFINITY }
+          _ => { line.glyph_ends[shown - 1] + reach }
+        };
+        // Each line owns the rows from its top down to the next line's.
+        let top = if index == 0 { 0 } else { line.top.max(0.) as u32 };
+        cut.push((top, visible_x));
+      }
+      for (i, alpha) in pixels.iter_mut().skip(3).step_by(4).enumerate() {
+        let (x, y) = ((i as u32 % self.width) as f32, i as u32 / self.width);
+        let visible_x = cut.iter().rev().find(|(top, _)| y >= *top ).map(|(_, visible_x)| *visible_x ).unwrap_or(f32::INFINITY);
+        if x > visible_x {
+          *alpha = 0;
+        }
+      }
+    }
+    self.tex.set_image(&pixels, self.width * 4, false);
   }
   pub fn is_alive(&self) -> bool {
     self.life.is_none() || (self.life.is_some() && self.life.unwrap() > 0.0)
   }
   pub fn update(&mut self, seconds: f32) {
+    self.age += seconds;
     if let Some(life) = self.life.as_mut() {
       *life -= seconds;
     }
+    self.frame = self.transition.frame(self.age, self.life);
+    self.refresh_texture();
   } 
 }
 
+/// Transparent border around every block's contents, before outlines and shadows.
 const PADDING: u32 = 10;
+/// How far a background reaches past the text. Less than `PADDING` so it isn't cut off.
+const BACKGROUND_MARGIN: u32 = 6;
 const MIN_WIDTH: u32 = 40;
 pub const LOREM_IPSUM: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit, \
 sed do eiusmod tempor incididunt ut labore et dolore magna aliqua. Ut enim ad minim \
@@ -0,0 +1,59 @@
+use {
+  crate::{
+    emote_image::DecodedEmote,
+    providers::EmoteProvider,
+  },
+  futures::{
+    FutureExt,
+    future::{
+      BoxFuture, Shared,
+    },
+  },
+  std::{
+    collections::HashMap,
+    sync::{
+      Arc, Mutex,
+    },
+  },
+};
+
+type SharedLoad = Shared<BoxFuture<'static, Option<Arc<DecodedEmote>>>>;
+
+/// Emote loads that haven't finished yet. An emote used several times at once, in one
+/// message or many, is only looked up, downloaded and stored once, and everyone waiting
+/// on it gets the same result. Cloning shares the same registry.
+#[derive(Clone, Default)]
+pub struct InFlightLoads {
+  loads: Arc<Mutex<HashMap<(EmoteProvider, String), SharedLoad>>>,
+}
+
+impl InFlightLoads {
+  /// Waits on the load already running for this emote, or runs `load` if there isn't one.
+  /// The entry is removed as soon as the load finishes, so failures are retried next time.
+  pub async fn get_or_load<F>(&self, provider: EmoteProvider, id: &str, load: F) -> Option<Arc<DecodedEmote>>
+  where
+    F: Future<Output = Option<Arc<DecodedEmote>>> + Send + 'static,
+  {
+    let key = (provider, id.to_owned());
+    let shared = {
+      let Ok(mut loads) = self.loads.lock() else { return load.await; };
+      match loads.get(&key) {
+        Some(shared) => { shared.clone() }
+        None => {
+          let registry = self.loads.clone();
+          let done = key.clone();
+          let shared = async move {
+            let image = load.await;
+            if let Ok(mut loads) = registry.lock() {
+              loads.remove(&done);
+            }
+            image
+          }.boxed().shared();
+          loads.insert(key, shared.clone());
+          shared
+        }
+      }
+    };
+    shared.await
+  }
+}
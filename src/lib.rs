@@ -1,10 +1,26 @@
 use {
   crate::{
+    alerts::*,
+    badges::{
+      BadgeUrls, HELIX_TIMEOUT,
+    },
+    chat::*,
     config_kdl::*,
+    connection::Backoff,
+    cooldown::UserLimiter,
+    custom_emotes::*,
+    decoded_cache::DecodedCache,
+    emoji::*,
+    emote_db::*,
+    emote_image::*,
+    emote_store::*,
+    in_flight::InFlightLoads,
+    providers::*,
     plugin::{
       TwitchOAuthRcvr::*,
       *,
     },
+    twitch_emotes::ChannelTwitchEmotes,
   },
   anyhow::{
     Result,
@@ -27,17 +43,31 @@ use {
     string::ObsString
   },
   platform_dirs::AppDirs,
-  rusqlite::{
-    Connection, 
-    params,
-  },
+  rand::prelude::*,
   std::{
+    collections::HashSet,
     path::PathBuf,
-    sync::Arc,
+    sync::{
+      Arc, Mutex,
+    },
+    time::{
+      Duration, Instant,
+    },
+  },
+  tokio::{
+    sync::{
+      mpsc::{
+        UnboundedReceiver, UnboundedSender,
+      },
+      oneshot,
+    },
+    task::JoinSet,
   },
-  tokio::sync::mpsc::UnboundedSender,
   twitch_api::{
-    helix::HelixClient, 
+    helix::{
+      HelixClient,
+      chat::GetChannelEmotesRequest,
+    },
     twitch_oauth2::{
       AccessToken, 
       UserToken, 
@@ -45,10 +75,30 @@ use {
   },
 };
 
+pub mod alerts;
+pub mod badges;
+pub mod chat;
+pub mod chat_overlay;
 pub mod config_kdl;
+pub mod connection;
+pub mod cooldown;
+pub mod custom_emotes;
+pub mod decoded_cache;
 pub mod effects;
+pub mod emoji;
+pub mod emote_db;
+pub mod emote_image;
+pub mod emote_store;
 pub mod font_studio;
+pub mod in_flight;
+pub mod opacity;
 pub mod plugin;
+pub mod prewarm;
+pub mod providers;
+pub mod text_animation;
+pub mod text_style;
+pub mod texture_cache;
+pub mod twitch_emotes;
 
 const PROMOTE_DEBUG_LOGS: bool = false;
 
@@ -91,86 +141,559 @@ impl Module for EKBModule {
 
 obs_register_module!(EKBModule);
 
-pub async fn start_twitch_monitor(mut ekb_conf_dirs: EkbConfigDirs, conf: EkbTwitchConfig, tx: UnboundedSender<EmoteComEnum>) {
-  let emotes = match connect_sqlite(&mut ekb_conf_dirs) {
-    Ok(emotes) => emotes,
+/// Supervises the Twitch connection, reconnecting with backoff until `max-retries`
+/// failed attempts in a row, at which point `TwitchConnectionFailure` is sent.
+/// `chat_enabled` follows the chat overlay's setting. While it's false, chat lines and the
+/// badges and emotes only they use aren't loaded. Each message on `purge_rx` empties the
+/// emote cache, and `CachePurged` is sent once it's done.
+pub async fn start_twitch_monitor(mut ekb_conf_dirs: EkbConfigDirs, conf: EkbTwitchConfig, chat_enabled: Arc<Mutex<bool>>, mut purge_rx: UnboundedReceiver<()>, tx: UnboundedSender<EmoteComEnum>) {
+  if ekb_conf_dirs.data.is_file() { ekb_conf_dirs.data.pop(); }
+  let custom_emotes = CustomEmotes::new(ekb_conf_dirs.data.join("custom"));
+  let store = match EmoteStore::open(ekb_conf_dirs.clone(), conf.cache()).await {
+    Ok(store) => store,
     Err(e) => {
-      _ = tx.send(EmoteComEnum::SqliteConnectionFailure(Arc::new(Err(e.into()))));
+      _ = tx.send(EmoteComEnum::SqliteConnectionFailure(Arc::new(Err(e))));
       return;
     }
   };
-  let mut client = match connect_twitch_client(&conf).await {
-    Ok(client) => { client }
-    Err(e) => {
-      _ = tx.send(EmoteComEnum::TwitchConnectionFailure(Arc::new(Err(e.into()))));
-      return;
+  match store.stats().await {
+    Ok(stats) => { log::info!("Emote cache holds {} emotes, {:.1} MB, used {} times", stats.entries, stats.bytes as f64 / (1024. * 1024.), stats.uses); }
+    Err(e) => { log::warn!("Failed to read emote cache stats: {}", e); }
+  }
+  let mut user_limiter = UserLimiter::new(conf.user_limits());
+  // Emote loads for each message and provider fetches, kept across reconnects and aborted along with the monitor.
+  let mut message_loads = JoinSet::new();
+  let mut providers = ProviderEmotes::new(conf.providers());
+  match conf.offline() {
+    true => {
+      match store.third_party_emotes().await {
+        Ok(emotes) => { providers.load_cached(emotes); }
+        Err(e) => { log::error!("Failed to read cached third-party emotes: {}", e); }
+      }
+    }
+    false => { providers.spawn_global_load(&mut message_loads); }
+  }
+  let mut badges = BadgeUrls::new(conf.badges(), &conf).await;
+  badges.spawn_global_load(&mut message_loads);
+  let mut twitch_emotes = ChannelTwitchEmotes::new(conf.clone(), store.clone());
+  let loaders = EmoteLoaders {
+    store,
+    offline: conf.offline(),
+    in_flight: InFlightLoads::default(),
+    decoded: DecodedCache::default(),
+    emoji_images: EmojiImages::default(),
+  };
+  // Chat lines finish as their emotes load, but are sent on in the order they were said.
+  let (chat_order, mut chat_pending) = tokio::sync::mpsc::unbounded_channel::<oneshot::Receiver<ChatLine>>();
+  {
+    let tx = tx.clone();
+    message_loads.spawn(async move {
+      while let Some(line) = chat_pending.recv().await {
+        if let Ok(line) = line.await {
+          _ = tx.send(EmoteComEnum::Chat(line));
+        }
+      }
+    });
+  }
+  // Purged through the store, so the cache isn't touched from another connection, and the
+  // decoded copies go with it.
+  {
+    let (store, decoded, tx) = (loaders.store.clone(), loaders.decoded.clone(), tx.clone());
+    message_loads.spawn(async move {
+      while purge_rx.recv().await.is_some() {
+        match store.purge().await {
+          Ok(purged) => {
+            decoded.clear();
+            log::info!("Purged {} emotes from the emote cache", purged);
+            _ = tx.send(EmoteComEnum::CachePurged);
+          }
+          Err(e) => { log::error!("Failed to purge the emote cache: {}", e); }
+        }
+      }
+    });
+  }
+  let _custom_watcher = match conf.providers().custom {
+    true => { Some(custom_emotes.watch().await) }
+    false => { None }
+  };
+  let connection = conf.connection();
+  let mut backoff = Backoff::new(&connection);
+  loop {
+    _ = tx.send(EmoteComEnum::TwitchStatus(MonitorStatus::Connecting { attempt: backoff.attempts() + 1 }));
+    match run_twitch_session(&conf, &loaders, &mut message_loads, &chat_order, &chat_enabled, &mut user_limiter, &mut providers, &mut badges, &mut twitch_emotes, &custom_emotes, &tx).await {
+      SessionEnd::Reconnect => {
+        log::info!("Twitch requested a reconnect. Reconnecting now.");
+        backoff.reset();
+      }
+      SessionEnd::Failed { error, established } => {
+        if established { backoff.reset(); }
+        if connection.max_retries > 0 && backoff.attempts() >= connection.max_retries {
+          log::error!("Giving up on Twitch after {} failed attempts.", backoff.attempts());
+          _ = tx.send(EmoteComEnum::TwitchConnectionFailure(Arc::new(Err(error))));
+          return;
+        }
+        let delay = backoff.next_delay();
+        log::warn!("Twitch connection lost: {}\nReconnecting in {:.1}s (attempt {})", error, delay.as_secs_f32(), backoff.attempts());
+        _ = tx.send(EmoteComEnum::TwitchStatus(MonitorStatus::Retrying { error: error.to_string(), delay: delay.as_secs_f32() }));
+        tokio::time::sleep(delay).await;
+      }
     }
+  }
+}
+
+/// How long a session has to stay up after Twitch let us in before its failure stops counting
+/// toward `max-retries`. A server that lets us connect and then drops us right away, as Twitch
+/// does after "Login authentication failed", keeps counting and is eventually given up on.
+const MIN_ESTABLISHED_UPTIME: Duration = Duration::from_secs(30);
+
+enum SessionEnd {
+  /// Twitch sent RECONNECT ahead of maintenance. Not a failure.
+  Reconnect,
+  /// `established` is true if the connection worked for a while before failing.
+  Failed { error: anyhow::Error, established: bool },
+}
+
+/// `welcomed_at` is when the server sent its welcome or we joined a channel.
+fn is_established(welcomed_at: Option<Instant>) -> bool {
+  welcomed_at.is_some_and(|at| at.elapsed() >= MIN_ESTABLISHED_UPTIME )
+}
+
+async fn run_twitch_session(conf: &EkbTwitchConfig, loaders: &EmoteLoaders, message_loads: &mut JoinSet<()>, chat_order: &UnboundedSender<oneshot::Receiver<ChatLine>>, chat_enabled: &Mutex<bool>, user_limiter: &mut UserLimiter, providers: &mut ProviderEmotes, badges: &mut BadgeUrls, twitch_emotes: &mut ChannelTwitchEmotes, custom_emotes: &CustomEmotes, tx: &UnboundedSender<EmoteComEnum>) -> SessionEnd {
+  let mut client = match connect_twitch_client(conf, &conf.channels()).await {
+    Ok(client) => { client }
+    Err(e) => { return SessionEnd::Failed { error: e.into(), established: false }; }
   };
   let mut stream = match client.stream() {
     Ok(client) => { client }
-    Err(e) => {
-      _ = tx.send(EmoteComEnum::TwitchConnectionFailure(Arc::new(Err(e.into()))));
-      return;
-    }
+    Err(e) => { return SessionEnd::Failed { error: e.into(), established: false }; }
   };
-  while let Some(irc_response) = stream.next().await.transpose().unwrap_or_else(|e| {
-    _ = tx.send(EmoteComEnum::TwitchConnectionFailure(Arc::new(Err(e.into()))));
-    None
-  }) {
+  let mut welcomed_at = None;
+  let use_emoji = conf.providers().emoji;
+  loop {
+    let irc_response = match stream.next().await {
+      None => { return SessionEnd::Failed { error: anyhow!("Twitch closed the connection"), established: is_established(welcomed_at) }; }
+      Some(Err(e)) => { return SessionEnd::Failed { error: e.into(), established: is_established(welcomed_at) }; } // Includes PING timeouts
+      Some(Ok(irc_response)) => { irc_response }
+    };
+    while message_loads.try_join_next().is_some() {} // Reap finished messages
+    providers.receive_loaded();
+    badges.receive_loaded();
+    twitch_emotes.receive_loaded();
+    let welcome = match &irc_response.command {
+      Command::Response(Response::RPL_WELCOME, _) | Command::JOIN(..) => { true }
+      Command::Raw(command, _) => { command == "ROOMSTATE" }
+      _ => { false }
+    };
+    if welcome && welcomed_at.is_none() {
+      welcomed_at = Some(Instant::now());
+      _ = tx.send(EmoteComEnum::TwitchStatus(MonitorStatus::Connected));
+    }
+    if let Command::Raw(command, _) = &irc_response.command
+      && command == "RECONNECT"
+    {
+      return SessionEnd::Reconnect;
+    }
+    let tags = IrcTags::from_message(&irc_response);
+    let login = irc_response.source_nickname().unwrap_or_default().to_owned();
+    let (channel, text) = match &irc_response.command {
+      Command::PRIVMSG(target, text) => { (target.trim_start_matches('#').to_ascii_lowercase(), text.to_owned()) }
+      Command::Raw(command, args) if command == "USERNOTICE" => {
+        let channel = args.first().map(|target| target.trim_start_matches('#').to_ascii_lowercase() ).unwrap_or_default();
+        (channel, args.get(1).cloned().unwrap_or_default())
+      }
+      Command::Raw(command, args) if command == "ROOMSTATE" => {
+        (args.first().map(|target| target.trim_start_matches('#').to_ascii_lowercase() ).unwrap_or_default(), String::new())
+      }
+      _ => { (String::new(), String::new()) }
+    };
+    // ROOMSTATE arrives on join with the channel's user id, which the emote providers key on.
+    if let Some(room_id) = tags.get("room-id")
+      && !conf.offline()
+      && !channel.is_empty()
+      && !providers.has_channel(&channel)
+    {
+      providers.spawn_channel_load(message_loads, &channel, room_id);
+    }
+    if let Some(room_id) = tags.get("room-id")
+      && !channel.is_empty()
+      && !badges.has_channel(&channel)
+    {
+      badges.spawn_channel_load(message_loads, &channel, room_id);
+    }
+    if let Some(room_id) = tags.get("room-id")
+      && !channel.is_empty()
+      && !twitch_emotes.has_channel(&channel)
+    {
+      twitch_emotes.spawn_channel_load(message_loads, &channel, room_id);
+    }
     match irc_response.to_twitch_message_privmsg() {
-      Err(_msg) => {
-        // Do something with this?
+      Err(msg) => {
+        if let Command::Raw(command, _) = &msg.command
+          && command == "USERNOTICE"
+          && let Some(event) = AlertEvent::from_usernotice(&tags)
+        {
+          let sender = ChatSender::from_tags(tags.get("login").unwrap_or(&login), &tags);
+          let alert = Alert { channel: channel.clone(), event, sender, message: text.clone(), burst: vec![] };
+          spawn_alert(loaders, message_loads, providers, twitch_emotes, &tags, alert, tx);
+        }
       }
       Ok(pm) => {
-        for emote in pm.emotes() {
-          let uri_v1 = format!("https://static-cdn.jtvnw.net/emoticons/v1/{}/3.0", emote.id);
-          let uri_v2 = format!("https://static-cdn.jtvnw.net/emoticons/v2/{}/default/light/3.0", emote.id);
-          // println!("Emote URI: {}", uri_v1);
-          let emote_data: EmoteData = if let Ok(emote_data) = emotes.query_one(
-            "SELECT id, name, img FROM emotes WHERE id=?1", params![emote.id.to_string()], |row| {
-              Ok(EmoteData{
-                id: row.get(0)?,
-                name: row.get(1)?,
-                img: row.get(2)?,
-              })
-            })
-          {
-            log::info!("Loaded emote id {} from sqlite", emote.id);
-            emote_data
-          } else {
-            log::info!("Could not find id {} in DB, downloading image to DB...", emote.id);
-            let img_data = if let Ok(data) = reqwest::get(uri_v2).await {
-              data 
-            } else if let Ok(data) = reqwest::get(uri_v1).await { data } else {
-              log::error!("Failed to download image data for emote id {} at step 1", emote.id);
-              continue;
-            };
-            let img_bytes = if let Ok(bytes) = img_data.bytes().await { bytes } else {
-              log::error!("Failed to download image data for emote id {} at step 2", emote.id);
-              continue;
-            };
-            if image::load_from_memory(&img_bytes).is_err() {
-              log::error!("Failed to validate downloaded image data for emote id {}\n  data: {:?}", emote.id, img_bytes);
-              continue;
-            }
-            let emote_data = EmoteData{
-              id: emote.id.to_string(),
-              name: emote.name.into_owned(), // FixMe: this sometimes ends up with several names, probably when multiple emotes are used in the same chat
-              img: img_bytes.into(),
-            };
-            if let Err(e) = emotes.execute(
-              "INSERT INTO emotes (id, name, img) VALUES (?1, ?2, ?3)",
-              params![emote_data.id.clone(), emote_data.name.clone(), emote_data.img.clone()],
-            ) {
-              log::error!("Failed to write emote to DB: {}", e)
-            };
-            log::info!("Loaded emote id {} from URI", emote.id);
-            emote_data
+        let sender = ChatSender::from_tags(&login, &tags);
+        let (message, action) = match text.strip_prefix("\u{1}ACTION ") {
+          Some(message) => { (message.trim_end_matches('\u{1}'), true) }
+          None => { (text.as_str(), false) }
+        };
+        let show_chat = chat_enabled.lock().is_ok_and(|enabled| *enabled );
+        let chat_parts = match show_chat {
+          true => { split_message(message, &tags.get("emotes").map(parse_emote_positions).unwrap_or_default()) }
+          false => { vec![] }
+        };
+        let chat_line = ChatLine { channel: channel.clone(), color: sender.name_color(&tags), sender: sender.clone(), action, badges: vec![], parts: vec![] };
+        let badge_sources: Vec<EmoteSource> = match show_chat && badges.enabled() {
+          true => { sender.badges.iter().filter_map(|(set, version)| badges.source(&channel, set, version) ).collect() }
+          false => { vec![] }
+        };
+        let native: Vec<String> = pm.emotes().map(|emote| emote.name.to_string() ).collect();
+        let third_party = providers.find_in_message(&channel, &text, &native);
+        let emoji = if use_emoji { find_emoji(&text) } else { vec![] };
+        let custom = custom_emotes.find_in_message(&text);
+        let has_emotes = !(native.is_empty() && third_party.is_empty() && emoji.is_empty() && custom.is_empty());
+        let trigger = MessageTrigger::from_tags(&tags);
+        if let Some(event) = AlertEvent::from_cheer(&sender, trigger.bits) {
+          let alert = Alert { channel: channel.clone(), event, sender: sender.clone(), message: message.to_owned(), burst: vec![] };
+          spawn_alert(loaders, message_loads, providers, twitch_emotes, &tags, alert, tx);
+        }
+        // Every message still goes to the chat overlay, only the wall is limited.
+        let emote_cap = match has_emotes.then(|| user_limiter.admit(&sender) ) {
+          None => { 0 }
+          Some(None) => {
+            log::debug!("Skipping emotes from {} while they are on cooldown", sender.login);
+            0
+          }
+          Some(Some(emote_cap)) => { emote_cap }
+        };
+        let sources: Vec<EmoteSource> = pm.emotes().map(|emote| EmoteSource {
+          provider: EmoteProvider::Twitch,
+          urls: emote_urls(EmoteProvider::Twitch, &emote.id, providers.config()),
+          id: emote.id.to_string(),
+          name: emote.name.into_owned(),
+          image: None,
+        }).chain(third_party.into_iter().map(|emote| EmoteSource {
+          provider: emote.provider,
+          urls: vec![emote.url],
+          id: emote.id,
+          name: emote.code,
+          image: None,
+        })).chain(emoji.into_iter().map(|emoji| EmoteSource {
+          provider: EmoteProvider::Emoji,
+          urls: vec![],
+          id: emoji.to_owned(),
+          name: emoji.to_owned(),
+          image: None,
+        })).chain(custom.into_iter().map(|emote| EmoteSource {
+          provider: EmoteProvider::Custom,
+          urls: vec![],
+          id: emote.id,
+          name: emote.name,
+          image: Some(emote.image),
+        })).take(emote_cap).collect();
+        // Loaded off the read loop, all at once, then sent in message order.
+        let loaders = loaders.clone();
+        let provider_config = providers.config().clone();
+        let tx = tx.clone();
+        let line_tx = show_chat.then(|| {
+          let (line_tx, line_rx) = oneshot::channel();
+          _ = chat_order.send(line_rx);
+          line_tx
+        });
+        message_loads.spawn(async move {
+          let (images, badges, parts) = futures::join!(
+            futures::future::join_all(sources.iter().map(|source| loaders.load(source) )),
+            futures::future::join_all(badge_sources.iter().map(|source| loaders.load(source) )),
+            load_chat_parts(&loaders, chat_parts, &provider_config),
+          );
+          for (source, image) in sources.into_iter().zip(images) {
+            let Some(image) = image else { continue; };
+            let EmoteSource { provider, id, name, .. } = source;
+            let _ = tx.send(EmoteComEnum::Data(EmoteEvent { provider, id, name, image, channel: channel.clone(), sender: sender.clone(), trigger: trigger.clone() }));
+          }
+          if let Some(line_tx) = line_tx {
+            let badges = badges.into_iter().flatten().collect();
+            _ = line_tx.send(ChatLine { badges, parts, ..chat_line });
+          }
+        });
+      }
+    }
+  }
+}
+
+/// Loads the emotes for an alert's burst and sends it on. The burst is made of the Twitch
+/// emotes in the alert's message, then the channel's own Twitch emotes, then its 7TV, BTTV
+/// and FFZ emotes.
+fn spawn_alert(loaders: &EmoteLoaders, message_loads: &mut JoinSet<()>, providers: &ProviderEmotes, twitch_emotes: &ChannelTwitchEmotes, tags: &IrcTags, alert: Alert, tx: &UnboundedSender<EmoteComEnum>) {
+  let positions = tags.get("emotes").map(parse_emote_positions).unwrap_or_default();
+  let mut channel_twitch: Vec<EmoteSource> = twitch_emotes.emotes(&alert.channel).to_vec();
+  channel_twitch.shuffle(&mut rand::rng());
+  let mut channel_emotes: Vec<&ThirdPartyEmote> = providers.channel_emotes(&alert.channel).collect();
+  channel_emotes.shuffle(&mut rand::rng());
+  let mut sources: Vec<EmoteSource> = split_message(&alert.message, &positions).into_iter()
+    .filter_map(|part| match part {
+      MessagePart::Emote { id, name } => { Some((id, name)) }
+      MessagePart::Text(_) => { None }
+    })
+    .map(|(id, name)| EmoteSource {
+      provider: EmoteProvider::Twitch,
+      urls: emote_urls(EmoteProvider::Twitch, &id, providers.config()),
+      id,
+      name,
+      image: None,
+    })
+    .chain(channel_twitch)
+    .chain(channel_emotes.into_iter().map(|emote| EmoteSource {
+      provider: emote.provider,
+      urls: vec![emote.url.to_owned()],
+      id: emote.id.to_owned(),
+      name: emote.code.to_owned(),
+      image: None,
+    }))
+    .collect();
+  let mut seen = HashSet::new();
+  sources.retain(|source| seen.insert((source.provider, source.id.to_owned())) );
+  sources.truncate(ALERT_BURST_VARIETY);
+  let trigger = MessageTrigger::from_tags(tags);
+  let loaders = loaders.clone();
+  let tx = tx.clone();
+  message_loads.spawn(async move {
+    let images = futures::future::join_all(sources.iter().map(|source| loaders.load(source) )).await;
+    let burst = sources.into_iter().zip(images)
+      .filter_map(|(source, image)| {
+        let EmoteSource { provider, id, name, .. } = source;
+        Some(EmoteEvent { provider, id, name, image: image?, channel: alert.channel.clone(), sender: alert.sender.clone(), trigger: trigger.clone() })
+      })
+      .collect();
+    _ = tx.send(EmoteComEnum::Alert(Alert { burst, ..alert }));
+  });
+}
+
+/// Emotes loaded for one chat line. Spam past this is shown as the emotes' names, so a
+/// single message can't queue up dozens of downloads.
+const MAX_CHAT_LINE_EMOTES: usize = 30;
+
+/// Loads a chat line's Twitch emotes. One that fails to load, or comes after the first
+/// `MAX_CHAT_LINE_EMOTES`, is shown as its name instead.
+async fn load_chat_parts(loaders: &EmoteLoaders, parts: Vec<MessagePart>, config: &ProviderConfig) -> Vec<ChatPart> {
+  let mut emotes = 0;
+  let loads = parts.into_iter().map(|part| {
+    let load = matches!(part, MessagePart::Emote { .. }) && { emotes += 1; emotes <= MAX_CHAT_LINE_EMOTES };
+    async move {
+      match part {
+        MessagePart::Text(text) => { ChatPart::Text(text) }
+        MessagePart::Emote { name, .. } if !load => { ChatPart::Text(name) }
+        MessagePart::Emote { id, name } => {
+          let source = EmoteSource {
+            provider: EmoteProvider::Twitch,
+            urls: emote_urls(EmoteProvider::Twitch, &id, config),
+            id,
+            name,
+            image: None,
           };
-          let _ = tx.send(EmoteComEnum::Data(emote_data));
+          match loaders.load(&source).await {
+            Some(image) => { ChatPart::Emote { name: source.name, image } }
+            None => { ChatPart::Text(source.name) }
+          }
         }
       }
     }
+  });
+  futures::future::join_all(loads).await
+}
+
+/// An emote used in chat before it's been found in sqlite or downloaded.
+#[derive(Clone)]
+pub(crate) struct EmoteSource {
+  pub(crate) provider: EmoteProvider,
+  pub(crate) id: String,
+  pub(crate) name: String,
+  /// Tried in order until one downloads. Empty for emoji and custom emotes, which are local.
+  pub(crate) urls: Vec<String>,
+  /// Already decoded, for custom emotes.
+  pub(crate) image: Option<Arc<DecodedEmote>>,
+}
+
+/// Shared by the tasks loading each message's emotes.
+#[derive(Clone)]
+struct EmoteLoaders {
+  store: EmoteStore,
+  offline: bool,
+  in_flight: InFlightLoads,
+  decoded: DecodedCache,
+  emoji_images: EmojiImages,
+}
+
+impl EmoteLoaders {
+  async fn load(&self, source: &EmoteSource) -> Option<Arc<DecodedEmote>> {
+    match source.provider {
+      EmoteProvider::Custom => { source.image.clone() }
+      EmoteProvider::Emoji => {
+        let (emoji_images, emoji) = (self.emoji_images.clone(), source.id.clone());
+        self.in_flight.get_or_load(source.provider, &source.id, async move { emoji_images.get(&emoji).await }).await
+      }
+      _ => {
+        if let Some(image) = self.decoded.get(source.provider, &source.id) {
+          // Still counted as a use, without waiting on the store.
+          let (store, provider, id, meta) = (self.store.clone(), source.provider, source.id.clone(), EmoteMeta::from_decoded(&image));
+          tokio::spawn(async move {
+            if let Err(e) = store.touch(provider, &id, meta).await {
+              log::error!("Failed to record use of {} emote id {}: {}", provider.as_str(), id, e);
+            }
+          });
+          return Some(image);
+        }
+        let image = self.in_flight.get_or_load(source.provider, &source.id, load_emote_image(self.store.clone(), source.clone(), self.offline)).await?;
+        self.decoded.insert(source.provider, &source.id, image.clone());
+        Some(image)
+      }
+    }
+  }
+}
+
+/// Finds or downloads the emote, then decodes it on the blocking pool.
+/// Downloads are stored once decoding proves the image is usable, cache hits are counted.
+/// A hit older than the cache TTL is still used, and checked with its CDN in the background.
+/// Offline, only hits are used.
+async fn load_emote_image(store: EmoteStore, source: EmoteSource, offline: bool) -> Option<Arc<DecodedEmote>> {
+  let (emote_data, validators) = match store.get(source.provider, &source.id).await {
+    Ok(Some(cached)) => {
+      log::info!("Loaded {} emote id {} from sqlite", source.provider.as_str(), source.id);
+      let mut validators = cached.validators.clone();
+      if validators.url.is_none() { validators.url = source.urls.first().cloned(); }
+      // Badges from a mirror folder are plain files, with no server to ask whether they changed.
+      let local_badge = source.provider == EmoteProvider::Badge && validators.url.as_deref().is_some_and(|url| url.starts_with("file://") );
+      if !offline
+        && !local_badge
+        && cached.is_stale(store.config().ttl)
+        && let Some(ticket) = store.begin_revalidation(source.provider, &source.id)
+      {
+        tokio::spawn(revalidate_emote(store.clone(), source.provider, source.id.clone(), validators, ticket));
+      }
+      (cached.emote, None)
+    }
+    result => {
+      if let Err(e) = result {
+        log::error!("Failed to read {} emote id {} from DB: {}", source.provider.as_str(), source.id, e);
+      }
+      if offline {
+        log::info!("Offline: skipping {} emote id {}, it isn't cached", source.provider.as_str(), source.id);
+        return None;
+      }
+      let (emote_data, validators) = download_emote(&source).await?;
+      (emote_data, Some(validators))
+    }
+  };
+  let EmoteData { provider, id, img, .. } = &emote_data;
+  let bytes = img.clone();
+  let image = match tokio::task::spawn_blocking(move || decode_emote(&bytes) ).await {
+    Ok(Ok(image)) => { Arc::new(image) }
+    Ok(Err(e)) => {
+      log::error!("Rejected image data for {} emote id {}: {}", provider.as_str(), id, e);
+      return None;
+    }
+    Err(e) => {
+      log::error!("Decoder task for {} emote id {} failed: {}", provider.as_str(), id, e);
+      return None;
+    }
+  };
+  let meta = EmoteMeta::new(img, &image);
+  let stored = match validators {
+    None => { store.touch(*provider, id, meta).await }
+    Some(validators) => { store.put(emote_data, meta, validators).await }
+  };
+  if let Err(e) = stored {
+    log::error!("Failed to write emote to DB: {}", e)
+  }
+  Some(image)
+}
+
+/// Tries each of the source's URLs until one returns an image.
+pub(crate) async fn download_emote(source: &EmoteSource) -> Option<(EmoteData, CacheValidators)> {
+  let EmoteSource { provider, id, name, urls, .. } = source;
+  log::info!("Could not find {} emote id {} in DB, downloading image to DB...", provider.as_str(), id);
+  let mut img_bytes = None;
+  for uri in urls.iter() {
+    // println!("Emote URI: {}", uri);
+    // Badge mirrors can be a local folder. Files have nothing to revalidate against.
+    if let Some(path) = uri.strip_prefix("file://") {
+      let path = PathBuf::from(path);
+      if let Ok(Ok(bytes)) = tokio::task::spawn_blocking(move || std::fs::read(path) ).await {
+        img_bytes = Some((bytes.into(), CacheValidators::default()));
+        break;
+      }
+      continue;
+    }
+    let Ok(img_data) = reqwest::get(uri).await.and_then(|data| data.error_for_status() ) else { continue; };
+    let validators = CacheValidators::from_response(uri, img_data.headers());
+    if let Ok(bytes) = img_data.bytes().await {
+      img_bytes = Some((bytes, validators));
+      break;
+    }
+  }
+  let Some((img_bytes, validators)) = img_bytes else {
+    log::error!("Failed to download image data for {} emote id {}", provider.as_str(), id);
+    return None;
+  };
+  if image::load_from_memory(&img_bytes).is_err() {
+    log::error!("Failed to validate downloaded image data for {} emote id {}\n  data: {:?}", provider.as_str(), id, img_bytes);
+    return None;
+  }
+  log::info!("Loaded {} emote id {} from URI", provider.as_str(), id);
+  Some((EmoteData{
+    provider: *provider,
+    id: id.to_owned(),
+    name: name.to_owned(), // FixMe: this sometimes ends up with several names, probably when multiple emotes are used in the same chat
+    img: img_bytes.into(),
+  }, validators))
+}
+
+/// Asks the CDN whether a stale emote changed, with If-None-Match / If-Modified-Since when
+/// the first download gave us validators. CDNs without them send the image again, which is
+/// compared to what's cached. Failures leave the cached image in place to try again later.
+async fn revalidate_emote(store: EmoteStore, provider: EmoteProvider, id: String, validators: CacheValidators, _ticket: RevalidationTicket) {
+  let Some(url) = validators.url.clone() else { return; };
+  let mut request = reqwest::Client::new().get(&url);
+  if let Some(etag) = &validators.etag {
+    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+  }
+  if let Some(last_modified) = &validators.last_modified {
+    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+  }
+  let response = match request.send().await.and_then(|response| response.error_for_status() ) {
+    Ok(response) => { response }
+    Err(e) => {
+      log::warn!("Failed to revalidate {} emote id {}: {}", provider.as_str(), id, e);
+      return;
+    }
+  };
+  let img = match response.status() {
+    reqwest::StatusCode::NOT_MODIFIED => { None }
+    _ => {
+      let fresh = CacheValidators::from_response(&url, response.headers());
+      match response.bytes().await {
+        Ok(bytes) if image::load_from_memory(&bytes).is_ok() => { Some((bytes, fresh)) }
+        _ => {
+          log::warn!("Revalidating {} emote id {} returned no usable image. Keeping the cached one.", provider.as_str(), id);
+          return;
+        }
+      }
+    }
+  };
+  let stored = match img {
+    None => { store.mark_fresh(provider, &id).await.map(|_| false ) }
+    Some((bytes, fresh)) => { store.refresh_image(provider, &id, bytes.into(), fresh).await }
+  };
+  match stored {
+    Ok(true) => { log::info!("Refreshed changed image for {} emote id {}", provider.as_str(), id); }
+    Ok(false) => {}
+    Err(e) => { log::error!("Failed to write revalidated emote to DB: {}", e); }
   }
 }
 
@@ -208,31 +731,23 @@ impl ToTwitchMessagePrivmsg for irc::proto::Message {
   }
 }
 
-fn connect_sqlite(path: &mut EkbConfigDirs) -> Result<Connection, rusqlite::Error> {
-  if path.data.is_file() { path.data.pop(); }
-  path.data.push("emotes.db3");
-  let db = Connection::open(&mut path.data)?;
-  if let Ok(false) = db.table_exists(None, "emotes") {
-    db.execute(
-      "CREATE TABLE emotes (
-          id   VARCHAR(255) PRIMARY KEY,
-          name VARCHAR(255),
-          img  BLOB NOT NULL
-      )",
-      (),
-    )?;
-  }
-  Ok(db)
-}
-
-async fn connect_twitch_client(conf: &EkbTwitchConfig) -> Result<irc::client::Client, irc::error::Error> {
+async fn connect_twitch_client(conf: &EkbTwitchConfig, channels: &[String]) -> Result<irc::client::Client, irc::error::Error> {
+  let connection = conf.connection();
+  let (nickname, password) = if conf.is_anonymous() {
+    // Twitch accepts any justinfan<number> login read-only, no password needed.
+    (format!("justinfan{}", rand::rng().random_range(1000..100_000_000_u32)), None)
+  } else {
+    (conf.bot_account(), Some(format!("oauth:{}", conf.oauth())))
+  };
   let config = Config {
-    nickname: Some(conf.bot_account()),
-    server: Some("irc.chat.twitch.tv".to_owned()),
-    port: Some(6697_u16),
-    use_tls: Some(true),
-    channels: vec![format!("#{}", conf.channel())],
-    password: Some(format!("oauth:{}", conf.oauth())),
+    nickname: Some(nickname),
+    server: Some(connection.server),
+    port: Some(connection.port),
+    use_tls: Some(connection.use_tls),
+    ping_time: Some(connection.ping_time),
+    ping_timeout: Some(connection.ping_timeout),
+    channels: channels.iter().map(|channel| format!("#{}", channel) ).collect(),
+    password,
     ..Default::default()
   };
   let client = irc::client::Client::from_config(config).await?;
@@ -241,13 +756,66 @@ async fn connect_twitch_client(conf: &EkbTwitchConfig) -> Result<irc::client::Cl
   Ok(client)
 }
 
+/// Joins `channel` just long enough to read its user id from ROOMSTATE, without needing a Helix token.
+pub(crate) async fn fetch_room_id(conf: &EkbTwitchConfig, channel: &str) -> Result<String> {
+  let mut client = connect_twitch_client(conf, &[channel.to_owned()]).await?;
+  let mut stream = client.stream()?;
+  let read = async {
+    while let Some(message) = stream.next().await {
+      let message = message?;
+      if let Command::Raw(command, _) = &message.command
+        && command == "ROOMSTATE"
+        && let Some(room_id) = IrcTags::from_message(&message).get("room-id")
+      {
+        return Ok(room_id.to_owned());
+      }
+    }
+    Err(anyhow!("Twitch closed the connection before #{} was joined", channel))
+  };
+  match tokio::time::timeout(Duration::from_secs(15), read).await {
+    Ok(room_id) => { room_id }
+    Err(_) => { Err(anyhow!("Timed out joining #{}. Is the channel name right?", channel)) }
+  }
+}
+
+/// Emotes the channel itself has, like its subscriber, follower and bits emotes. Helix needs
+/// an authenticated account, so this fails in anonymous mode.
+pub(crate) async fn fetch_channel_twitch_emotes(conf: &EkbTwitchConfig, room_id: &str) -> Result<Vec<EmoteSource>> {
+  if conf.is_anonymous() {
+    return Err(anyhow!("listing a channel's Twitch emotes needs mode authenticated"));
+  }
+  let http = reqwest::Client::builder().timeout(HELIX_TIMEOUT).build()?;
+  let client: HelixClient<reqwest::Client> = HelixClient::with_client(http);
+  let token = UserToken::from_token(&client, AccessToken::new(conf.oauth())).await?;
+  let emotes = client.req_get(GetChannelEmotesRequest::broadcaster_id(room_id), &token).await?.data;
+  let providers = conf.providers();
+  Ok(emotes.into_iter().map(|emote| {
+    let id = emote.id.to_string();
+    EmoteSource {
+      provider: EmoteProvider::Twitch,
+      urls: emote_urls(EmoteProvider::Twitch, &id, &providers),
+      id,
+      name: emote.name,
+      image: None,
+    }
+  }).collect())
+}
+
 #[allow(clippy::needless_return)] // 'return' statements make the intention more obvious.
 pub async fn get_or_create_config_emojikanban(config_update: EkbConfigUpdate, tx: UnboundedSender<TwitchOAuthRcvr>) { // -> Result<(EkbConfigDirs, EkbTwitchConfig), String>
   let app_name = Some("emojikanban");
   let config_file = "config.kdl";
   let config_kdl = 
-r#"bot-account bot-name                       // <- Replace 'bot-name' with the name of the account used to monitor chat
-channel     streamer-name                  // <- and 'streamer-name' with the streamer, most likely your own
+r##"mode        anonymous                      // <- Read chat without logging in. Connecting from Properties switches to 'authenticated'
+channel     streamer-name                  // <- Replace 'streamer-name' with the streamer, most likely your own
+// For collabs, list more channels, each on the same line or its own. weight=<0 to 1> is the chance
+// an emote from that channel is used, and an effects block replaces the one below for that channel.
+// channel collab-partner weight=0.5 {
+//   effects {
+//     gravity weight=100
+//   }
+// }
+bot-account bot-name                       // <- With 'mode authenticated', replace 'bot-name' with the name of the account used to monitor chat
 oauth       g0Bble0dEE0GukK0enCryPTIon0KEy // <- With or without "oauth:" prefix
 // The oauth should be generated from the account you use
 // as the 'bot-account'. If you use your streamer account,
@@ -255,7 +823,100 @@ oauth       g0Bble0dEE0GukK0enCryPTIon0KEy // <- With or without "oauth:" prefix
 // 'channel'. 'channel' is only used to select the irc channel to 
 // monitor for emotes, and eventually for chat.
 // 
-"#;
+// 7TV, BetterTTV and FrankerFaceZ emotes are found by name in chat messages, Unicode emoji
+// are drawn with your system's emoji font, and PNG/GIF/WebP files in the 'custom' folder next to
+// emotes.db3 are used when chat says :filename:. Listing providers here uses only those listed.
+// url= and cdn= (bttv only) point them somewhere else, like a test server.
+// providers {
+//   seventv url="https://7tv.io/v3"
+//   bttv    url="https://api.betterttv.net/3" cdn="https://cdn.betterttv.net"
+//   ffz     url="https://api.frankerfacez.com/v1"
+//   emoji
+//   custom
+// }
+// Downloaded emotes are kept in emotes.db3. The least recently used are removed once it passes
+// max-entries or max-size-mb (0 for no limit), and ones older than ttl-days are checked for changes.
+// cache {
+//   max-entries 0
+//   max-size-mb 256
+//   ttl-days 7
+// }
+// offline #true draws only emotes already in emotes.db3 and never contacts emote CDNs or providers.
+// Fill the cache beforehand with `ekb prewarm`.
+// offline #true
+// Reconnects use exponential backoff, giving up after max-retries failures in a row (0 never gives up).
+// connection {
+//   server "irc.chat.twitch.tv"
+//   port 6697
+//   tls #true
+//   max-retries 10
+//   backoff initial=1.0 max=120.0 // seconds, initial is at least 0.1
+//   ping interval=180 timeout=20
+// }
+// Limits for individual chatters. Broadcaster, moderators and VIPs are exempt by default.
+// user-limits {
+//   cooldown 10                    // seconds before the same chatter can trigger emotes again
+//   max-per-message 5              // emotes drawn from a single message
+//   exempt broadcaster moderator vip
+// }
+// Relative odds of each effect being picked. 0 disables an effect.
+// cooldown=<seconds> limits how often a single effect can appear.
+// require="sub", bits=<min bits> or reward="<channel point reward id>" lock an effect
+// behind any one of them. Messages that don't qualify fall back to an unlocked effect,
+// or are skipped with: effects unqualified="ignore" { ... }
+effects {
+  gravity  weight=70
+  inchworm weight=20
+  slideup  weight=10 // cooldown=30
+  plumber  weight=10
+  tree     weight=5
+}
+// Uncomment to limit how often emotes spawn. These override the OBS properties when loaded.
+// cooldown {
+//   global 0.25                   // minimum seconds between any two emotes
+//   burst capacity=20 refill=4.0  // allow bursts of 20, regaining 4 per second
+// }
+// Uncomment to show chat on screen, names in their Twitch colors and emotes inline.
+// These override the OBS properties when loaded.
+// chat {
+//   position x=20 y=20
+//   size width=480 height=600     // lines stack up from the bottom of this area
+//   max-lines 12
+//   lifetime 30                   // seconds before a line leaves, 0 keeps it
+//   enter "fade" time=0.3         // none, fade, slide-left/right/top/bottom, pop or typewriter
+//   exit "fade" time=2
+//   font-size 24                  // leave out to use the text-style size
+// }
+// Uncomment to change how text is drawn. These override the OBS properties when loaded.
+// text-style {
+//   font "Noto Sans" weight=700 italic=#false
+//   size 24 line-height=1.2
+//   color "#FFFFFF"
+//   outline width=2 color="#000000"
+//   shadow x=2 y=2 blur=2 color="#00000099"
+//   background color="#00000080" radius=8
+// }
+// Chat badges come from Helix when authenticated. Anonymous setups can point at a mirror,
+// a folder or URL with <set>/<version>.png images.
+// badges {
+//   mirror "/path/to/badges"
+//   enabled #false                // leave badges out of the chat overlay
+// }
+// Uncomment for sub, resub, gift, raid and cheer alerts, with a burst of the channel's own emotes.
+// alerts {
+//   position y=120                // add x= to stop centering
+//   font-size 48                  // 0 uses the text-style size
+//   duration 6
+//   burst 20                      // emotes thrown onto the wall, 0 for none
+//   min-bits 100
+//   sub "{user} just subscribed!"
+//   resub "{user} subscribed for {months} months!"
+//   subgift "{user} gifted a sub to {recipient}!"
+//   submysterygift "{user} gifted {count} subs!"
+//   raid "{user} is raiding with {viewers} viewers!"
+//   cheer "{user} cheered {bits} bits!"
+// }
+"##;
   if let Some(app_dirs) = AppDirs::new(app_name, true) {
     let mut config_path = app_dirs.config_dir;
     if let Err(e) = std::fs::create_dir_all(&config_path) {
@@ -308,7 +969,7 @@ oauth       g0Bble0dEE0GukK0enCryPTIon0KEy // <- With or without "oauth:" prefix
 }
 
 #[allow(clippy::needless_return, unused)]
-async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: String, config_update: EkbConfigUpdate) -> Result<(EkbConfigDirs, EkbTwitchConfig), anyhow::Error> {
+async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: String, config_update: EkbConfigUpdate) -> Result<(EkbConfigDirs, EkbTwitchConfig, EkbWallConfig), anyhow::Error> {
   let mut doc_res: Result<KdlDocument, KdlError> = conf.parse();
   let mut write_changes = false;
   match doc_res {
@@ -335,7 +996,19 @@ async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: Str
           Ok(_) => { write_changes = true; }
           Err(e) => { log::error!("kdl update_error for oauth: {}", e); }
         }
+        // A new token means the user wants to log in, so stop reading anonymously.
+        if let Err(e) = doc.mode_update(TwitchAuthMode::Authenticated) {
+          log::error!("kdl update error for mode: {}", e);
+        }
       }
+      let wall = match EkbWallConfig::try_from(doc.clone()) {
+        Err(e) => {
+          let error = anyhow!("Failed to parse {}\nError: {}", config_path.display(), e);
+          log::error!("{}", error);
+          return Err(error);
+        }
+        Ok(wall) => { wall }
+      };
       let client: HelixClient<reqwest::Client> = HelixClient::default();
       match EkbTwitchConfig::try_from(doc.clone()) {
         Err(e) => {
@@ -343,6 +1016,19 @@ async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: Str
           log::error!("{}", error);
           return Err(error);
         }
+        Ok(conf) if conf.is_anonymous() => {
+          // No token to validate and Helix needs one, so only the channel names are checked.
+          if let Some(channel) = conf.channels().into_iter().find(|channel| validate_twitch_name(channel.into()).is_none() ) {
+            let error = anyhow!("channel '{}' in {} is not a valid Twitch name", channel, config_path.display());
+            log::error!("{}", error);
+            return Err(error);
+          }
+          if write_changes && let Err(e) = std::fs::write(&config_path, doc.to_string()) {
+            log::error!("Failed to write new values to {}\nValues will not be retained after this session.\nError: {}", config_path.display(), e);
+          }
+          config_path.pop();
+          return Ok((EkbConfigDirs{ config: config_path, data: data_path}, conf, wall));
+        }
         Ok(conf) => {
           let token = AccessToken::new(conf.oauth());
           match UserToken::from_token(&client, token.clone()).await {
@@ -353,17 +1039,22 @@ async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: Str
             }
             Ok(token) => {
               let bot_account = conf.bot_account();
-              let channel = conf.channel();
               let bot_valid = client.get_channel_from_login(&bot_account, &token).await
                 .map_err(|e| { anyhow!("Failure awaiting client.get_channel_from_login for bot account. {}", e) });
-              let chn_valid = client.get_channel_from_login(&channel, &token).await
-                .map_err(|e| { anyhow!("Failure awaiting client.get_channel_from_login for streamer channel. {}", e) });
+              let mut channel = String::new();
+              let mut chn_valid = Ok(None);
+              for login in conf.channels() {
+                chn_valid = client.get_channel_from_login(&login, &token).await
+                  .map_err(|e| { anyhow!("Failure awaiting client.get_channel_from_login for streamer channel. {}", e) });
+                channel = login;
+                if !matches!(chn_valid, Ok(Some(_))) { break; } // Report the first channel that fails
+              }
               if bot_valid.is_ok() && bot_valid.as_ref().unwrap().is_some() && chn_valid.is_ok() && chn_valid.as_ref().unwrap().is_some() {
                 if write_changes && let Err(e) = std::fs::write(&config_path, doc.to_string()) {
                   log::error!("Failed to write new values to {}\nValues will not be retained after this session.\nError: {}", config_path.display(), e);
                 }
                 config_path.pop();
-                return Ok((EkbConfigDirs{ config: config_path, data: data_path}, conf));
+                return Ok((EkbConfigDirs{ config: config_path, data: data_path}, conf, wall));
               } else {
                 let error = anyhow!(
                   "OAUTH Token valid, but either the bot_username or the channel is invalid in: {}\nbot-account: {} {:?}\nchannel: {} {:?}",
@@ -382,15 +1073,163 @@ async fn validate_config(mut config_path: PathBuf, data_path: PathBuf, conf: Str
 
 #[derive(Clone)]
 pub struct EmoteData {
+  pub provider: EmoteProvider,
   pub id: String,
   pub name: String,
   pub img: Vec<u8>,
 }
 
+/// An emote used in chat, decoded and ready for upload, along with who used it.
+#[derive(Clone)]
+pub struct EmoteEvent {
+  pub provider: EmoteProvider,
+  pub id: String,
+  pub name: String,
+  pub image: Arc<DecodedEmote>,
+  /// Lowercase channel name without the `#`.
+  pub channel: String,
+  pub sender: ChatSender,
+  pub trigger: MessageTrigger,
+}
+
+impl EmoteEvent {
+  /// Ids are only unique per provider.
+  pub fn cache_key(&self) -> String {
+    format!("{}/{}", self.provider.as_str(), self.id)
+  }
+}
+
+/// Progress reports from the Twitch monitor's connection supervisor.
+#[derive(Clone, Debug)]
+pub enum MonitorStatus {
+  Connecting { attempt: u32 },
+  Connected,
+  Retrying { error: String, delay: f32 },
+}
+
 #[derive(Clone)]
 pub enum EmoteComEnum {
-  Data(EmoteData),
+  Data(EmoteEvent),
+  Chat(ChatLine),
+  Alert(Alert),
+  TwitchStatus(MonitorStatus),
   SqliteConnectionFailure(Arc<anyhow::Result<(),anyhow::Error>>),
   TwitchConnectionFailure(Arc<anyhow::Result<(),anyhow::Error>>),
+  /// The emote cache was emptied, so textures of its emotes shouldn't be reused.
+  CachePurged,
 }
 
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    std::{
+      io::{BufRead, BufReader, Write},
+      net::{TcpListener, TcpStream},
+    },
+  };
+
+  /// What the fake server does with one connection.
+  enum FakeSession {
+    /// Welcomes the client, then asks it to reconnect like Twitch does before maintenance.
+    Reconnect,
+    /// Hangs up as soon as the client has logged in.
+    Close,
+  }
+
+  /// A fake Twitch IRC server on a free local port, serving `script` one connection at a time.
+  /// Connections past the end of the script are closed.
+  fn fake_irc_server(script: Vec<FakeSession>) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    std::thread::spawn(move || {
+      let mut script = script.into_iter();
+      for stream in listener.incoming() {
+        let Ok(stream) = stream else { break; };
+        serve(stream, script.next().unwrap_or(FakeSession::Close));
+      }
+    });
+    port
+  }
+
+  fn serve(mut stream: TcpStream, session: FakeSession) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    // USER is the last line of the client's login.
+    while reader.read_line(&mut line).is_ok_and(|read| read > 0 ) {
+      if line.starts_with("USER ") { break; }
+      line.clear();
+    }
+    match session {
+      FakeSession::Reconnect => {
+        _ = stream.write_all(b":tmi.twitch.tv 001 justinfan123 :Welcome, GLHF!\r\n:tmi.twitch.tv RECONNECT\r\n");
+        // Wait for the client to hang up before taking the next connection.
+        while reader.read_line(&mut line).is_ok_and(|read| read > 0 ) {
+          line.clear();
+        }
+      }
+      FakeSession::Close => {}
+    }
+  }
+
+  fn test_config(port: u16) -> EkbTwitchConfig {
+    let kdl = format!(r#"
+      mode anonymous
+      channel "test"
+      offline #true
+      connection {{
+        server "127.0.0.1"
+        port {}
+        tls #false
+        max-retries 3
+        backoff initial=0.1 max=1.0
+      }}
+    "#, port);
+    EkbTwitchConfig::try_from(kdl.parse::<KdlDocument>().unwrap()).unwrap()
+  }
+
+  fn test_dirs() -> EkbConfigDirs {
+    let data = std::env::temp_dir().join(format!("ekb-monitor-test-{}", std::process::id()));
+    std::fs::create_dir_all(&data).unwrap();
+    EkbConfigDirs { config: data.join("config.kdl"), data }
+  }
+
+  #[tokio::test(flavor = "multi_thread")]
+  async fn monitor_reconnects_backs_off_and_gives_up() {
+    let port = fake_irc_server(vec![FakeSession::Reconnect]);
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let (_purge_tx, purge_rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(start_twitch_monitor(test_dirs(), test_config(port), Arc::new(Mutex::new(false)), purge_rx, tx));
+    let mut statuses = vec![];
+    let gave_up = tokio::time::timeout(Duration::from_secs(20), async {
+      while let Some(message) = rx.recv().await {
+        match message {
+          EmoteComEnum::TwitchStatus(status) => { statuses.push(status); }
+          EmoteComEnum::TwitchConnectionFailure(_) => { return true; }
+          EmoteComEnum::SqliteConnectionFailure(e) => { panic!("emote store failed to open: {:?}", e); }
+          _ => {}
+        }
+      }
+      false
+    }).await;
+    assert_eq!(gave_up.ok(), Some(true), "monitor should give up after max-retries, got {:?}", statuses);
+    // RECONNECT goes straight back to the first attempt, without waiting.
+    assert!(matches!(statuses[..3], [
+      MonitorStatus::Connecting { attempt: 1 },
+      MonitorStatus::Connected,
+      MonitorStatus::Connecting { attempt: 1 },
+    ]), "{:?}", statuses);
+    // Each close waits about twice as long as the one before, until max-retries runs out.
+    let delays: Vec<f32> = statuses.iter().filter_map(|status| match status {
+      MonitorStatus::Retrying { delay, .. } => { Some(*delay) }
+      _ => { None }
+    }).collect();
+    assert_eq!(delays.len(), 3, "{:?}", statuses);
+    for (attempt, delay) in delays.iter().enumerate() {
+      let ceiling = 0.1 * 2_f32.powi(attempt as i32);
+      assert!(*delay >= ceiling / 2. - 0.001 && *delay <= ceiling + 0.001, "delay {} of attempt {} isn't within {}..={}", delay, attempt + 1, ceiling / 2., ceiling);
+    }
+    assert!(matches!(statuses.last(), Some(MonitorStatus::Connecting { attempt: 4 })), "{:?}", statuses);
+  }
+}
@@ -2,17 +2,34 @@
 use {
   emojikanban::{
     config_kdl::*,
+    prewarm::*,
   },
   std::{
     io::Write,
+    path::PathBuf,
+    sync::{
+      Arc, Mutex,
+    },
   },
 };
 
+const USAGE: &str = "Usage: ekb [prewarm [--channel <name>]... [--file <emote id list>]...]";
+
 fn main() -> Result<(), anyhow::Error> {
   env_logger::Builder::from_default_env()
     .filter(None, log::LevelFilter::Info)
     .init();
 
+  let args: Vec<String> = std::env::args().skip(1).collect();
+  match args.first().map(String::as_str) {
+    None => {}
+    Some("prewarm") => { return run_prewarm(&args[1..]); }
+    Some(_) => {
+      println!("{USAGE}");
+      return Ok(());
+    }
+  }
+
   print!("Update Twitch authentication? (y/N) ");
   std::io::stdout().flush()?;
   
@@ -31,24 +48,34 @@ fn main() -> Result<(), anyhow::Error> {
   };
   
   let runtime = tokio::runtime::Runtime::new().unwrap();
-  let (oauth_tx, mut oauth_rx) = tokio::sync::mpsc::unbounded_channel();
-  let _handle = runtime.spawn(async {
-    emojikanban::get_or_create_config_emojikanban(config_update, oauth_tx).await;
-  });
-  let (ekb_config_dirs, conf) = match oauth_rx.blocking_recv() {
-    Some(emojikanban::plugin::TwitchOAuthRcvr::NewConfigData(data)) => data,
-    Some(emojikanban::plugin::TwitchOAuthRcvr::OAuthToken(_)) => { panic!("Got token not asked for in main") }
-    Some(emojikanban::plugin::TwitchOAuthRcvr::RcvrError(e)) => { panic!("Error getting config in main: {}", e) }
-    None => { unreachable!() }
-  };
+  let (ekb_config_dirs, conf) = load_config(&runtime, config_update);
   let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<emojikanban::EmoteComEnum>();
+  // Nothing here asks for a purge, the channel just stays open.
+  let (_purge_tx, purge_rx) = tokio::sync::mpsc::unbounded_channel();
   runtime.spawn(async move {
-    emojikanban::start_twitch_monitor(ekb_config_dirs, conf, tx).await;
+    // Chat lines are printed below, so they're loaded like the overlay was on.
+    emojikanban::start_twitch_monitor(ekb_config_dirs, conf, Arc::new(Mutex::new(true)), purge_rx, tx).await;
   });
   while let Some(emote_data) = rx.blocking_recv() {
     match emote_data {
-      emojikanban::EmoteComEnum::Data(emote_data) => {
-        println!("Emote :{}: used.", emote_data.name);
+      emojikanban::EmoteComEnum::Data(event) => {
+        println!("{} emote :{}: used by {} in #{}.", event.provider.as_str(), event.name, event.sender.display_name, event.channel);
+      }
+      emojikanban::EmoteComEnum::Chat(line) => {
+        let text: String = line.parts.iter().map(|part| match part {
+          emojikanban::chat::ChatPart::Text(text) => { text.as_str() }
+          emojikanban::chat::ChatPart::Emote { name, .. } => { name.as_str() }
+        }).collect();
+        println!("#{} {}: {}", line.channel, line.sender.display_name, text);
+      }
+      emojikanban::EmoteComEnum::Alert(alert) => {
+        println!("#{} alert: {} ({} burst emotes)", alert.channel, alert.text(alert.event.kind().default_template()), alert.burst.len());
+      }
+      emojikanban::EmoteComEnum::CachePurged => {
+        log::info!("Emote cache purged");
+      }
+      emojikanban::EmoteComEnum::TwitchStatus(status) => {
+        log::info!("Twitch monitor: {:?}", status);
       }
       emojikanban::EmoteComEnum::SqliteConnectionFailure(e) => {
         // let e = e.clone();
@@ -64,3 +91,40 @@ fn main() -> Result<(), anyhow::Error> {
   
   Ok(())
 }
+
+fn load_config(runtime: &tokio::runtime::Runtime, config_update: EkbConfigUpdate) -> (EkbConfigDirs, EkbTwitchConfig) {
+  let (oauth_tx, mut oauth_rx) = tokio::sync::mpsc::unbounded_channel();
+  let _handle = runtime.spawn(async {
+    emojikanban::get_or_create_config_emojikanban(config_update, oauth_tx).await;
+  });
+  match oauth_rx.blocking_recv() {
+    Some(emojikanban::plugin::TwitchOAuthRcvr::NewConfigData((dirs, conf, _wall))) => (dirs, conf),
+    Some(emojikanban::plugin::TwitchOAuthRcvr::OAuthToken(_)) => { panic!("Got token not asked for in main") }
+    Some(emojikanban::plugin::TwitchOAuthRcvr::RcvrError(e)) => { panic!("Error getting config in main: {}", e) }
+    None => { unreachable!() }
+  }
+}
+
+/// Downloads emotes into emotes.db3 ahead of stream. With no targets, every channel in config.kdl is used.
+fn run_prewarm(args: &[String]) -> Result<(), anyhow::Error> {
+  let mut targets = vec![];
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match (arg.as_str(), args.next()) {
+      ("--channel", Some(channel)) => { targets.push(PrewarmTarget::Channel(channel.to_owned())); }
+      ("--file", Some(path)) => { targets.push(PrewarmTarget::IdFile(PathBuf::from(path))); }
+      _ => {
+        println!("{USAGE}");
+        return Ok(());
+      }
+    }
+  }
+  let runtime = tokio::runtime::Runtime::new()?;
+  let (ekb_config_dirs, conf) = load_config(&runtime, EkbConfigUpdate::default());
+  if targets.is_empty() {
+    targets = conf.channels().into_iter().map(PrewarmTarget::Channel).collect();
+  }
+  let report = runtime.block_on(prewarm(ekb_config_dirs, &conf, &targets))?;
+  println!("Prewarm done: {} downloaded, {} already cached, {} failed.", report.downloaded, report.already_cached, report.failed);
+  Ok(())
+}
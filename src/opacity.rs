@@ -0,0 +1,109 @@
+use {
+  obs_wrapper::{
+    graphics::*,
+    obs_sys::{
+      bfree, gs_effect_create, gs_effect_get_param_by_name, gs_effect_loop, gs_effect_set_float,
+      gs_effect_t, gs_eparam_t,
+    },
+  },
+  std::{
+    cell::OnceCell,
+    ffi::{
+      CStr, c_char,
+    },
+  },
+};
+
+/// OBS's default sprite shader with an `opacity` uniform, so a fade changes a float per draw
+/// instead of the texture.
+const OPACITY_SHADER: &CStr = c"
+uniform float4x4 ViewProj;
+uniform texture2d image;
+uniform float opacity;
+
+sampler_state def_sampler {
+  Filter   = Linear;
+  AddressU = Clamp;
+  AddressV = Clamp;
+};
+
+struct VertInOut {
+  float4 pos : POSITION;
+  float2 uv  : TEXCOORD0;
+};
+
+VertInOut VSDefault(VertInOut vert_in)
+{
+  VertInOut vert_out;
+  vert_out.pos = mul(float4(vert_in.pos.xyz, 1.0), ViewProj);
+  vert_out.uv  = vert_in.uv;
+  return vert_out;
+}
+
+float4 PSDraw(VertInOut vert_in) : TARGET
+{
+  float4 rgba = image.Sample(def_sampler, vert_in.uv);
+  rgba.a *= opacity;
+  return rgba;
+}
+
+technique Draw
+{
+  pass
+  {
+    vertex_shader = VSDefault(vert_in);
+    pixel_shader  = PSDraw(vert_in);
+  }
+}
+";
+
+struct OpacityEffect {
+  effect: *mut gs_effect_t,
+  opacity: *mut gs_eparam_t,
+}
+
+impl OpacityEffect {
+  /// Needs the graphics context. OBS frees the effect along with it.
+  fn create() -> Option<Self> {
+    let mut error: *mut c_char = std::ptr::null_mut();
+    let effect = unsafe { gs_effect_create(OPACITY_SHADER.as_ptr(), c"emojikanban-opacity".as_ptr(), &mut error) };
+    if effect.is_null() {
+      let reason = match error.is_null() {
+        true => { "unknown error".into() }
+        false => { unsafe { CStr::from_ptr(error) }.to_string_lossy() }
+      };
+      log::error!("Failed to compile the opacity shader, fading text will be drawn opaque: {}", reason);
+      if !error.is_null() {
+        unsafe { bfree(error.cast()) };
+      }
+      return None;
+    }
+    let opacity = unsafe { gs_effect_get_param_by_name(effect, c"opacity".as_ptr()) };
+    Some(Self { effect, opacity })
+  }
+}
+
+thread_local! {
+  /// Created on first use by the graphics thread, the only one that draws.
+  static OPACITY_EFFECT: OnceCell<Option<OpacityEffect>> = const { OnceCell::new() };
+}
+
+/// Draws `tex` like `GraphicsTexture::draw`, with its alpha scaled by `opacity`.
+pub fn draw_with_opacity(tex: &GraphicsTexture, x: i32, y: i32, w: u32, h: u32, opacity: f32) {
+  if opacity >= 1. {
+    tex.draw(x, y, w, h, false);
+    return;
+  }
+  OPACITY_EFFECT.with(|cell| {
+    match cell.get_or_init(OpacityEffect::create) {
+      Some(effect) => {
+        unsafe { gs_effect_set_float(effect.opacity, opacity.max(0.)) };
+        // The texture is drawn with whichever effect is running, which is this one in the loop.
+        while unsafe { gs_effect_loop(effect.effect, c"Draw".as_ptr()) } {
+          tex.draw(x, y, w, h, false);
+        }
+      }
+      None => { tex.draw(x, y, w, h, false); }
+    }
+  });
+}
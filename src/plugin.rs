@@ -1,8 +1,11 @@
 use {
   crate::{
-    EmoteComEnum, EmoteData,
+    EmoteComEnum, EmoteEvent, MonitorStatus,
+    alerts::*,
+    chat_overlay::*,
+    cooldown::*,
     config_kdl::{
-      EkbConfigDirs, EkbConfigUpdate, EkbTwitchConfig,
+      EkbConfigDirs, EkbConfigUpdate, EkbTwitchConfig, EkbWallConfig,
       TWITCH_CALLBACK_URL,
       serve_oauth_receiver, validate_twitch_name,
     },
@@ -11,10 +14,9 @@ use {
     plugin::{
       TwitchConnectionStatus::*, TwitchOAuthRcvr::*,
     },
-  },
-  image::{
-    AnimationDecoder, DynamicImage, ImageFormat,
-    codecs::gif::GifDecoder,
+    text_animation::*,
+    text_style::*,
+    texture_cache::*,
   },
   obs_wrapper::{
     graphics::*,
@@ -33,11 +35,17 @@ use {
   rand::prelude::*,
   std::{
     borrow::Cow,
-    collections::VecDeque,
+    collections::{
+      HashMap, VecDeque,
+    },
     ops::Deref,
+    rc::Rc,
     sync::{
       Arc, Mutex,
     },
+    time::{
+      SystemTime, UNIX_EPOCH,
+    },
   },
   tokio::{
     runtime::Runtime,
@@ -50,7 +58,7 @@ use {
 
 pub enum TwitchOAuthRcvr {
   OAuthToken(String),
-  NewConfigData((EkbConfigDirs, EkbTwitchConfig)),
+  NewConfigData((EkbConfigDirs, EkbTwitchConfig, EkbWallConfig)),
   RcvrError(anyhow::Error),
 }
 
@@ -58,6 +66,51 @@ enum TwitchConnectionStatus {
   InitConnection,
   AwaitingConfig,
   Connected,
+  /// The monitor ran out of retries. Waits for a new OAuth token or config before trying again.
+  Disconnected,
+}
+
+/// How long decoded emote textures stay uploaded after the last on-screen use.
+const TEXTURE_IDLE_SECONDS: f32 = 60.;
+const STATUS_BADGE_KEY: &str = "status_badge";
+
+/// What the Properties window and the on-screen badge report about the Twitch connection.
+struct ConnectionInfo {
+  status: String,
+  last_error: Option<String>,
+  channel: Option<String>,
+  connected_since: Option<SystemTime>,
+}
+
+impl Default for ConnectionInfo {
+  fn default() -> Self {
+    Self {
+      status: "Loading config".to_owned(),
+      last_error: None,
+      channel: None,
+      connected_since: None,
+    }
+  }
+}
+
+impl ConnectionInfo {
+  fn since_text(&self) -> String {
+    let Some(since) = self.connected_since else { return "-".to_owned(); };
+    let elapsed = since.elapsed().unwrap_or_default().as_secs();
+    let clock = since.duration_since(UNIX_EPOCH).map(|d| d.as_secs() % 86400 ).unwrap_or(0);
+    let ago = match elapsed {
+      0..60 => { format!("{}s", elapsed) }
+      60..3600 => { format!("{}m {:02}s", elapsed / 60, elapsed % 60) }
+      _ => { format!("{}h {:02}m", elapsed / 3600, elapsed / 60 % 60) }
+    };
+    format!("{:02}:{:02}:{:02} UTC ({} ago)", clock / 3600, clock / 60 % 60, clock % 60, ago)
+  }
+  fn badge_text(&self) -> String {
+    match self.channel.as_ref() {
+      Some(channel) => { format!("Twitch {}: {}", channel, self.status) }
+      None => { format!("Twitch: {}", self.status) }
+    }
+  }
 }
 
 pub struct EmojiKanBan {
@@ -65,6 +118,8 @@ pub struct EmojiKanBan {
   runtime: Option<Runtime>,
   need_oauth_update: Arc<Mutex<bool>>,
   need_config_file_update: Arc<Mutex<bool>>,
+  /// Set by the purge button, handed to the monitor on the next tick.
+  need_cache_purge: Arc<Mutex<bool>>,
   config_data: Option<(EkbConfigDirs, EkbTwitchConfig)>,
   config_draft: EkbConfigUpdate,
   config_handle: Option<JoinHandle<()>>,
@@ -72,10 +127,23 @@ pub struct EmojiKanBan {
   twitch_status: TwitchConnectionStatus,
   oauth_tx: Option<UnboundedSender<TwitchOAuthRcvr>>,
   oauth_rx: Option<UnboundedReceiver<TwitchOAuthRcvr>>,
+  /// Asks the running monitor to empty the emote cache.
+  cache_purge_tx: Option<UnboundedSender<()>>,
   emote_rx: Option<UnboundedReceiver<EmoteComEnum>>, // EmoteData -> anyhow::Result<EmoteData, String> to return error to try to reconnect to Twitch
   emote_queue: VecDeque<EmoteOBS>,
   emote_queue_max_length: u32,
+  effect_weights: EffectWeights,
+  effect_gates: EffectGates,
+  channel_profiles: HashMap<String, ChannelProfile>,
+  effect_assets: EffectAssets,
+  texture_cache: TextureCache,
+  connection_info: ConnectionInfo,
+  show_status_badge: bool,
+  status_badge_text: Option<String>,
+  cooldowns: Cooldowns,
   font_studio: FontStudio,
+  chat_overlay: ChatOverlay,
+  alert_overlay: AlertOverlay,
   rng: ThreadRng,
   screen_w: u32,
   screen_h: u32,
@@ -115,9 +183,17 @@ impl Sourceable for EmojiKanBan {
     let screen_h = settings.get(obs_string!("screen_height")).unwrap_or(1080);
     let screen_offset_x = settings.get(obs_string!("offset_x")).unwrap_or(0);
     let screen_offset_y = settings.get(obs_string!("offset_y")).unwrap_or(0);
+    let cooldowns = Cooldowns::new(read_cooldown_settings(settings, &CooldownConfig::default()));
+    let show_status_badge = settings.get(obs_string!("status_badge")).unwrap_or(false);
+    let chat_overlay = ChatOverlay::new(read_chat_settings(settings, &ChatOverlayConfig::default()));
+    let alert_overlay = AlertOverlay::new(read_alert_settings(settings, &AlertConfig::default()));
     
     let mut font_studio = FontStudio::new();
-    font_studio.add_text_block(500, (50,50), (36.0,40.0), Some(15.0), "emojiKanBan Loaded");
+    font_studio.set_style(read_text_style_settings(settings, &TextStyle::default()));
+    let loaded_style = font_studio.style().sized(36.0);
+    font_studio.update_dimensions(screen_w as f32, screen_h as f32);
+    let loaded_transition = TextTransition::new(TextAnimation::ScalePop, TextAnimation::Fade);
+    font_studio.add_text_block(500, (50,50), &loaded_style, loaded_transition, Some(15.0), "emojiKanBan Loaded");
     
     source.update_source_settings(settings);
     
@@ -126,6 +202,7 @@ impl Sourceable for EmojiKanBan {
       runtime: None,
       need_oauth_update: Arc::new(Mutex::new(false)),
       need_config_file_update: Arc::new(Mutex::new(false)),
+      need_cache_purge: Arc::new(Mutex::new(false)),
       config_data: None,
       config_draft: EkbConfigUpdate::default(),
       config_handle: None,
@@ -133,10 +210,22 @@ impl Sourceable for EmojiKanBan {
       twitch_status: InitConnection,
       oauth_tx: None,
       oauth_rx: None,
+      cache_purge_tx: None,
       emote_rx: None,
       emote_queue: vec![].into(),
       emote_queue_max_length,
+      effect_weights: EffectWeights::default(),
+      effect_gates: EffectGates::default(),
+      channel_profiles: HashMap::new(),
+      effect_assets: EffectAssets::default(),
+      texture_cache: TextureCache::new(TEXTURE_IDLE_SECONDS),
+      connection_info: ConnectionInfo::default(),
+      show_status_badge,
+      status_badge_text: None,
+      cooldowns,
       font_studio,
+      chat_overlay,
+      alert_overlay,
       rng: rand::rng(),
       screen_w,
       screen_h,
@@ -144,6 +233,7 @@ impl Sourceable for EmojiKanBan {
       screen_offset_y,
     };
     ekb.check_twitch_connection();
+    ekb.refresh_status_display();
     ekb
   }
 }
@@ -167,6 +257,7 @@ impl EmojiKanBan {
         if self.runtime.is_some() { // We have a runtime and now need to set up the Twitch connection
           if need_oauth_update {
             self.twitch_status = AwaitingConfig;
+            self.set_connection_status("Waiting for Twitch authentication");
           } else {
             if let Err(_) = self.start_config_thread(EkbConfigUpdate::default()) {
               log::error!("start_config_thread failed with default values.");
@@ -197,21 +288,60 @@ impl EmojiKanBan {
                   log::error!("start_config_thread failed with new oauth data.")
                 };
               }
-              NewConfigData(data) => {
+              NewConfigData((dirs, conf, wall)) => {
+                let data = (dirs, conf);
+                self.effect_weights = wall.effects;
+                self.effect_gates = wall.gates;
+                self.channel_profiles = wall.channels;
+                if let Some(cooldowns) = wall.cooldowns.as_ref() {
+                  self.cooldowns.set_config(cooldowns.clone());
+                }
+                if let Some(chat) = wall.chat.as_ref() {
+                  self.chat_overlay.set_config(chat.clone());
+                }
+                if let Some(style) = wall.text_style.as_ref() {
+                  self.set_text_style(style.clone());
+                }
+                if let Some(alerts) = wall.alerts.as_ref() {
+                  self.alert_overlay.set_config(alerts.clone());
+                }
                 if let Some(mut source) = self.source.upgrade() {
-                  let bot_account: ObsString = data.1.bot_account().into();
                   let channel: ObsString = data.1.channel().into();
                   {
                     let mut settings = source.get_settings();
-                    settings.set_string(obs_string!("twitch_bot_account"), bot_account);
+                    // Anonymous configs have no bot account, and shouldn't blank the one typed in.
+                    if !data.1.bot_account().is_empty() {
+                      let bot_account: ObsString = data.1.bot_account().into();
+                      settings.set_string(obs_string!("twitch_bot_account"), bot_account);
+                    }
                     settings.set_string("twitch_channel", channel);
+                    if let Some(cooldowns) = wall.cooldowns.as_ref() {
+                      write_cooldown_settings(&mut settings, cooldowns);
+                    }
+                    if let Some(chat) = wall.chat.as_ref() {
+                      write_chat_settings(&mut settings, chat);
+                    }
+                    if let Some(style) = wall.text_style.as_ref() {
+                      write_text_style_settings(&mut settings, style);
+                    }
+                    if let Some(alerts) = wall.alerts.as_ref() {
+                      write_alert_settings(&mut settings, alerts);
+                    }
                   }
                   source.update_source_properties();
                 }
+                let channels = data.1.channels().iter().map(|channel| format!("#{}", channel) ).collect::<Vec<_>>().join(" ");
+                self.connection_info.channel = Some(match data.1.is_anonymous() {
+                  true => { format!("{} (anonymous)", channels) }
+                  false => { channels }
+                });
+                self.set_connection_status("Config loaded");
                 self.config_data = Some(data);
               }
               RcvrError(e) => {
                 log::error!("{}", e);
+                self.connection_info.last_error = Some(e.to_string());
+                self.set_connection_status("Config problem, see last error");
               }
             }}
           }
@@ -221,17 +351,22 @@ impl EmojiKanBan {
             }
             self.emote_rx.take();
             let (emote_tx, emote_rx) = tokio::sync::mpsc::unbounded_channel();
+            let (purge_tx, purge_rx) = tokio::sync::mpsc::unbounded_channel();
+            let chat_enabled = self.chat_overlay.shared_enabled();
             self.twitch_handle = Some(self.runtime.as_mut().unwrap().spawn(async move {
-              crate::start_twitch_monitor(ekb_config_dirs, conf, emote_tx).await;
+              crate::start_twitch_monitor(ekb_config_dirs, conf, chat_enabled, purge_rx, emote_tx).await;
             }));
             self.emote_rx = Some(emote_rx);
+            self.cache_purge_tx = Some(purge_tx);
             self.twitch_status = Connected;
+            self.set_connection_status("Starting Twitch monitor");
           }
         }
       }
-      Connected => {
+      Connected | Disconnected => {
         if need_oauth_update {
           self.twitch_status = AwaitingConfig;
+          self.set_connection_status("Waiting for Twitch authentication");
         }
       }
     }
@@ -270,6 +405,80 @@ impl EmojiKanBan {
       self.config_draft.channel = value;
     }
   }
+  fn set_connection_status(&mut self, status: &str) {
+    self.connection_info.status = status.to_owned();
+    self.refresh_status_display();
+  }
+  /// Puts `emote_data` on the wall with an effect picked for its channel and sender.
+  /// None if every effect is disabled, locked for the message, or with `cooldowns`, cooling down.
+  fn spawn_emote(&mut self, emote_data: EmoteEvent, cooldowns: bool) -> Option<EffectKind> {
+    let (weights, gates) = match self.channel_profiles.get(&emote_data.channel).and_then(|profile| profile.effects.as_ref() ) {
+      Some((weights, gates)) => { (weights, gates) }
+      None => { (&self.effect_weights, &self.effect_gates) }
+    };
+    let kind = gates.pick(
+      weights, &mut self.rng,
+      &emote_data.sender, &emote_data.trigger,
+      |kind| !cooldowns || self.cooldowns.effect_ready(kind),
+    )?;
+    let cache_key = emote_data.cache_key();
+    let frames = match self.texture_cache.get(&cache_key) {
+      Some(frames) => { frames }
+      None => { self.texture_cache.insert(cache_key, EmoteFrames::from(emote_data.image.as_ref())) }
+    };
+    let mut emote = EmoteOBS::new(emote_data.name, frames);
+    let (ew, eh) = (emote.current_frame().width() as f32, emote.current_frame().height() as f32);
+    emote.effect = Some(kind.init(self.screen_w as f32, self.screen_h as f32, ew, eh, &mut self.effect_assets, &mut self.rng));
+    self.emote_queue.push_back(emote);
+    Some(kind)
+  }
+  fn apply_monitor_status(&mut self, status: MonitorStatus) {
+    match status {
+      MonitorStatus::Connecting { attempt } => {
+        self.set_connection_status(&format!("Connecting (attempt {})", attempt));
+      }
+      MonitorStatus::Connected => {
+        self.connection_info.connected_since = Some(SystemTime::now());
+        self.set_connection_status("Connected");
+      }
+      MonitorStatus::Retrying { error, delay } => {
+        self.connection_info.connected_since = None;
+        self.connection_info.last_error = Some(error);
+        self.set_connection_status(&format!("Reconnecting in {:.0}s", delay));
+      }
+    }
+  }
+  /// Pushes `connection_info` into the read-only Properties fields and the on-screen badge.
+  fn refresh_status_display(&mut self) {
+    if let Some(source) = self.source.upgrade() {
+      let mut settings = source.get_settings();
+      let info = &self.connection_info;
+      settings.set_string(obs_string!("status_connection"), ObsString::from(info.status.to_owned()));
+      settings.set_string(obs_string!("status_channel"), ObsString::from(info.channel.to_owned().unwrap_or("-".to_owned())));
+      settings.set_string(obs_string!("status_since"), ObsString::from(info.since_text()));
+      settings.set_string(obs_string!("status_last_error"), ObsString::from(info.last_error.to_owned().unwrap_or("-".to_owned())));
+    }
+    let badge_text = self.show_status_badge.then(|| self.connection_info.badge_text() );
+    if badge_text == self.status_badge_text {
+      return; // Avoid re-rendering the same badge on every Properties refresh
+    }
+    match badge_text.as_deref() {
+      Some(txt) => {
+        let style = self.font_studio.style().sized(18.0);
+        let y = self.screen_h as i32 - 56 - style.effect_margin() as i32;
+        self.font_studio.set_text_block(STATUS_BADGE_KEY, 800, (10, y), &style, TextTransition::default(), None, txt);
+      }
+      None => { self.font_studio.remove_text_block(STATUS_BADGE_KEY); }
+    }
+    self.status_badge_text = badge_text;
+  }
+  /// The status badge is drawn again in the new style. Other blocks keep theirs until replaced.
+  fn set_text_style(&mut self, style: TextStyle) {
+    if &style == self.font_studio.style() { return; }
+    self.font_studio.set_style(style);
+    self.status_badge_text = None;
+    self.refresh_status_display();
+  }
   pub fn need_oauth_update(&self) -> bool {
     if let Ok(lock) = self.need_oauth_update.lock() {
       return lock.deref().clone();
@@ -290,10 +499,235 @@ impl EmojiKanBan {
       *update = false;
     }
   }
+  /// Hands a pending purge to the monitor, which owns the emote cache.
+  pub fn check_cache_purge(&mut self) {
+    let Ok(mut purge) = self.need_cache_purge.lock() else { return; };
+    if !*purge { return; }
+    *purge = false;
+    match self.cache_purge_tx.as_ref().map(|tx| tx.send(()) ) {
+      Some(Ok(())) => { log::info!("EmojiKanBan purging the emote cache."); }
+      _ => { log::warn!("The emote cache can only be purged while the Twitch monitor is running."); }
+    }
+  }
 } // impl EmojiKanBan
 
-const GRAVITY: f32 = 1800.;
-const BOUNCE: f32 = 0.6;
+fn cooldown_setting_name(kind: EffectKind) -> ObsString {
+  format!("cooldown_{}", kind.kdl_name()).into()
+}
+
+/// Settings missing from `settings` keep the value from `current`.
+fn read_cooldown_settings(settings: &DataObj, current: &CooldownConfig) -> CooldownConfig {
+  let mut config = current.clone();
+  let global: Option<f64> = settings.get(obs_string!("cooldown_global"));
+  if let Some(global) = global {
+    config.global_interval = global as f32;
+  }
+  if let Some(capacity) = settings.get(obs_string!("cooldown_burst_capacity")) {
+    config.burst_capacity = capacity;
+  }
+  let refill: Option<f64> = settings.get(obs_string!("cooldown_burst_refill"));
+  if let Some(refill) = refill {
+    config.burst_refill = refill as f32;
+  }
+  for kind in EffectKind::ALL {
+    let interval: Option<f64> = settings.get(cooldown_setting_name(kind));
+    if let Some(interval) = interval {
+      config.effect_intervals.insert(kind, interval as f32);
+    }
+  }
+  config
+}
+
+fn write_cooldown_settings(settings: &mut DataObj, config: &CooldownConfig) {
+  settings.set_double(obs_string!("cooldown_global"), config.global_interval as f64);
+  settings.set_int(obs_string!("cooldown_burst_capacity"), config.burst_capacity as i64);
+  settings.set_double(obs_string!("cooldown_burst_refill"), config.burst_refill as f64);
+  for kind in EffectKind::ALL {
+    settings.set_double(cooldown_setting_name(kind), config.effect_interval(kind) as f64);
+  }
+}
+
+/// Settings missing from `settings` keep the value from `current`.
+fn read_chat_settings(settings: &DataObj, current: &ChatOverlayConfig) -> ChatOverlayConfig {
+  let mut config = current.clone();
+  if let Some(enabled) = settings.get(obs_string!("chat_enabled")) {
+    config.enabled = enabled;
+  }
+  if let Some(x) = settings.get(obs_string!("chat_x")) {
+    config.x = x;
+  }
+  if let Some(y) = settings.get(obs_string!("chat_y")) {
+    config.y = y;
+  }
+  if let Some(width) = settings.get(obs_string!("chat_width")) {
+    config.width = width;
+  }
+  if let Some(height) = settings.get(obs_string!("chat_height")) {
+    config.height = height;
+  }
+  if let Some(max_lines) = settings.get(obs_string!("chat_max_lines")) {
+    config.max_lines = max_lines;
+  }
+  let lifetime: Option<f64> = settings.get(obs_string!("chat_lifetime"));
+  if let Some(lifetime) = lifetime {
+    config.lifetime = lifetime as f32;
+  }
+  let animation = |name: ObsString| -> Option<TextAnimation> {
+    let value: Option<Cow<'_, str>> = settings.get(name);
+    value.and_then(|value| TextAnimation::from_name(value.trim()) )
+  };
+  let seconds = |name: ObsString| -> Option<f32> {
+    let value: Option<f64> = settings.get(name);
+    value.map(|value| value as f32 )
+  };
+  if let Some(enter) = animation(obs_string!("chat_enter")) {
+    config.transition.enter = enter;
+  }
+  if let Some(enter_time) = seconds(obs_string!("chat_enter_time")) {
+    config.transition.enter_time = enter_time;
+  }
+  if let Some(exit) = animation(obs_string!("chat_exit")) {
+    config.transition.exit = exit;
+  }
+  if let Some(exit_time) = seconds(obs_string!("chat_exit_time")) {
+    config.transition.exit_time = exit_time;
+  }
+  // 0 follows the text size.
+  let font_size: Option<f64> = settings.get(obs_string!("chat_font_size"));
+  if let Some(font_size) = font_size {
+    config.font_size = (font_size > 0.).then_some(font_size as f32);
+  }
+  config
+}
+
+fn write_chat_settings(settings: &mut DataObj, config: &ChatOverlayConfig) {
+  settings.set_bool(obs_string!("chat_enabled"), config.enabled);
+  settings.set_int(obs_string!("chat_x"), config.x as i64);
+  settings.set_int(obs_string!("chat_y"), config.y as i64);
+  settings.set_int(obs_string!("chat_width"), config.width as i64);
+  settings.set_int(obs_string!("chat_height"), config.height as i64);
+  settings.set_int(obs_string!("chat_max_lines"), config.max_lines as i64);
+  settings.set_double(obs_string!("chat_lifetime"), config.lifetime as f64);
+  settings.set_string(obs_string!("chat_enter"), ObsString::from(config.transition.enter.name().to_owned()));
+  settings.set_double(obs_string!("chat_enter_time"), config.transition.enter_time as f64);
+  settings.set_string(obs_string!("chat_exit"), ObsString::from(config.transition.exit.name().to_owned()));
+  settings.set_double(obs_string!("chat_exit_time"), config.transition.exit_time as f64);
+  settings.set_double(obs_string!("chat_font_size"), config.font_size.unwrap_or(0.) as f64);
+}
+
+/// Settings missing from `settings` keep the value from `current`. Templates are only set in config.kdl.
+fn read_alert_settings(settings: &DataObj, current: &AlertConfig) -> AlertConfig {
+  let mut config = current.clone();
+  if let Some(enabled) = settings.get(obs_string!("alerts_enabled")) {
+    config.enabled = enabled;
+  }
+  let font_size: Option<f64> = settings.get(obs_string!("alerts_font_size"));
+  if let Some(font_size) = font_size {
+    config.font_size = (font_size > 0.).then_some(font_size as f32);
+  }
+  let duration: Option<f64> = settings.get(obs_string!("alerts_duration"));
+  if let Some(duration) = duration {
+    config.duration = duration as f32;
+  }
+  if let Some(burst) = settings.get(obs_string!("alerts_burst")) {
+    config.burst = burst;
+  }
+  if let Some(min_bits) = settings.get(obs_string!("alerts_min_bits")) {
+    config.min_bits = min_bits;
+  }
+  config
+}
+
+fn write_alert_settings(settings: &mut DataObj, config: &AlertConfig) {
+  settings.set_bool(obs_string!("alerts_enabled"), config.enabled);
+  settings.set_double(obs_string!("alerts_font_size"), config.font_size.unwrap_or(0.) as f64);
+  settings.set_double(obs_string!("alerts_duration"), config.duration as f64);
+  settings.set_int(obs_string!("alerts_burst"), config.burst as i64);
+  settings.set_int(obs_string!("alerts_min_bits"), config.min_bits as i64);
+}
+
+/// Settings missing from `settings`, or colors that don't parse, keep the value from `current`.
+fn read_text_style_settings(settings: &DataObj, current: &TextStyle) -> TextStyle {
+  let mut style = current.clone();
+  let color = |name: ObsString| -> Option<[u8; 4]> {
+    let value: Option<Cow<'_, str>> = settings.get(name);
+    value.and_then(|value| parse_hex_color(value.trim()) )
+  };
+  let float = |name: ObsString| -> Option<f32> {
+    let value: Option<f64> = settings.get(name);
+    value.map(|value| value as f32 )
+  };
+  let family: Option<Cow<'_, str>> = settings.get(obs_string!("text_font"));
+  if let Some(family) = family {
+    let family = family.trim();
+    style.family = (!family.is_empty()).then(|| family.to_owned() );
+  }
+  let weight: Option<u32> = settings.get(obs_string!("text_weight"));
+  if let Some(weight) = weight {
+    style.weight = weight.clamp(1, 1000) as u16;
+  }
+  if let Some(italic) = settings.get(obs_string!("text_italic")) {
+    style.italic = italic;
+  }
+  if let Some(size) = float(obs_string!("text_size")) {
+    style.size = size;
+  }
+  if let Some(line_height) = float(obs_string!("text_line_height")) {
+    style.line_height = line_height;
+  }
+  if let Some(fill) = color(obs_string!("text_color")) {
+    style.color = fill;
+  }
+  if let Some(width) = float(obs_string!("text_outline_width")) {
+    style.outline_width = width.clamp(0., MAX_TEXT_EFFECT);
+  }
+  if let Some(outline) = color(obs_string!("text_outline_color")) {
+    style.outline_color = outline;
+  }
+  if let Some(x) = float(obs_string!("text_shadow_x")) {
+    style.shadow_offset.0 = x;
+  }
+  if let Some(y) = float(obs_string!("text_shadow_y")) {
+    style.shadow_offset.1 = y;
+  }
+  if let Some(blur) = float(obs_string!("text_shadow_blur")) {
+    style.shadow_blur = blur.clamp(0., MAX_TEXT_EFFECT);
+  }
+  if let Some(shadow) = color(obs_string!("text_shadow_color")) {
+    style.shadow_color = shadow;
+  }
+  // Left empty for no background.
+  let background: Option<Cow<'_, str>> = settings.get(obs_string!("text_background_color"));
+  if let Some(background) = background {
+    let background = background.trim();
+    if background.is_empty() {
+      style.background = None;
+    } else if let Some(background) = parse_hex_color(background) {
+      style.background = Some(background);
+    }
+  }
+  if let Some(radius) = float(obs_string!("text_background_radius")) {
+    style.background_radius = radius;
+  }
+  style
+}
+
+fn write_text_style_settings(settings: &mut DataObj, style: &TextStyle) {
+  settings.set_string(obs_string!("text_font"), ObsString::from(style.family.to_owned().unwrap_or_default()));
+  settings.set_int(obs_string!("text_weight"), style.weight as i64);
+  settings.set_bool(obs_string!("text_italic"), style.italic);
+  settings.set_double(obs_string!("text_size"), style.size as f64);
+  settings.set_double(obs_string!("text_line_height"), style.line_height as f64);
+  settings.set_string(obs_string!("text_color"), ObsString::from(hex_color(style.color)));
+  settings.set_double(obs_string!("text_outline_width"), style.outline_width as f64);
+  settings.set_string(obs_string!("text_outline_color"), ObsString::from(hex_color(style.outline_color)));
+  settings.set_double(obs_string!("text_shadow_x"), style.shadow_offset.0 as f64);
+  settings.set_double(obs_string!("text_shadow_y"), style.shadow_offset.1 as f64);
+  settings.set_double(obs_string!("text_shadow_blur"), style.shadow_blur as f64);
+  settings.set_string(obs_string!("text_shadow_color"), ObsString::from(hex_color(style.shadow_color)));
+  settings.set_string(obs_string!("text_background_color"), ObsString::from(style.background.map(hex_color).unwrap_or_default()));
+  settings.set_double(obs_string!("text_background_radius"), style.background_radius as f64);
+}
 
 impl GetNameSource for EmojiKanBan {
   fn get_name() -> ObsString {
@@ -315,7 +749,34 @@ impl GetHeightSource for EmojiKanBan {
 
 impl GetPropertiesSource for EmojiKanBan {
   fn get_properties(&mut self) -> Properties {
+    self.refresh_status_display(); // Brings "connected since" up to date
     let mut props = Properties::new();
+    props
+      .add(
+        obs_string!("status_connection"),
+        obs_string!("Twitch status"),
+        TextProp::new(TextType::Info),
+      )
+      .add(
+        obs_string!("status_channel"),
+        obs_string!("Channel"),
+        TextProp::new(TextType::Info),
+      )
+      .add(
+        obs_string!("status_since"),
+        obs_string!("Connected since"),
+        TextProp::new(TextType::Info),
+      )
+      .add(
+        obs_string!("status_last_error"),
+        obs_string!("Last error"),
+        TextProp::new(TextType::Info),
+      )
+      .add(
+        obs_string!("status_badge"),
+        obs_string!("Show Twitch status on screen"),
+        BoolProp,
+      );
     if let Some(ref tx) = self.oauth_tx {
       let oauth_tx = tx.clone();
       let update_oauth = self.need_oauth_update.clone();
@@ -356,6 +817,19 @@ impl GetPropertiesSource for EmojiKanBan {
         },
       );
     };
+    if self.cache_purge_tx.is_some() {
+      let purge_cache = self.need_cache_purge.clone();
+      props.add_button_with_refresh(
+        "emote_cache_purge".into(),
+        "Purge Emote Cache".into(),
+        false,
+        move || {
+          if let Ok(mut purge) = purge_cache.lock() {
+            *purge = true;
+          }
+        },
+      );
+    }
     props
       .add(
         obs_string!("twitch_bot_account"),
@@ -393,6 +867,184 @@ impl GetPropertiesSource for EmojiKanBan {
         obs_string!("offset_y"),
         obs_string!("Offset relative to the top left screen corner. Y Offset:"),
         NumberProp::new_int().with_range(1u32..=3840 * 3),
+      )
+      .add(
+        obs_string!("cooldown_global"),
+        obs_string!("Minimum seconds between any two emotes"),
+        NumberProp::new_float(0.05).with_range(0.0..=60.0),
+      )
+      .add(
+        obs_string!("cooldown_burst_capacity"),
+        obs_string!("Burst allowance: emotes allowed back to back (0 disables)"),
+        NumberProp::new_int().with_range(0u32..=1000),
+      )
+      .add(
+        obs_string!("cooldown_burst_refill"),
+        obs_string!("Burst allowance regained per second"),
+        NumberProp::new_float(0.1).with_range(0.0..=100.0),
+      );
+    for kind in EffectKind::ALL {
+      props.add(
+        cooldown_setting_name(kind),
+        format!("Minimum seconds between {} effects", kind.kdl_name()).into(),
+        NumberProp::new_float(1.0).with_range(0.0..=3600.0),
+      );
+    }
+    props
+      .add(
+        obs_string!("chat_enabled"),
+        obs_string!("Show chat on screen"),
+        BoolProp,
+      )
+      .add(
+        obs_string!("chat_x"),
+        obs_string!("Chat X position"),
+        NumberProp::new_int().with_range(0u32..=3840 * 3),
+      )
+      .add(
+        obs_string!("chat_y"),
+        obs_string!("Chat Y position"),
+        NumberProp::new_int().with_range(0u32..=3840 * 3),
+      )
+      .add(
+        obs_string!("chat_width"),
+        obs_string!("Chat width"),
+        NumberProp::new_int().with_range(40u32..=3840 * 3),
+      )
+      .add(
+        obs_string!("chat_height"),
+        obs_string!("Chat height"),
+        NumberProp::new_int().with_range(1u32..=3840 * 3),
+      )
+      .add(
+        obs_string!("chat_max_lines"),
+        obs_string!("Most chat lines on screen (0 only limits by height)"),
+        NumberProp::new_int().with_range(0u32..=200),
+      )
+      .add(
+        obs_string!("chat_lifetime"),
+        obs_string!("Seconds a chat line stays up (0 keeps it until pushed out)"),
+        NumberProp::new_float(1.0).with_range(0.0..=3600.0),
+      )
+      .add(
+        obs_string!("chat_enter"),
+        obs_string!("Chat line enter animation (none, fade, slide-left/right/top/bottom, pop, typewriter)"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("chat_enter_time"),
+        obs_string!("Seconds a chat line animates in for"),
+        NumberProp::new_float(0.1).with_range(0.0..=60.0),
+      )
+      .add(
+        obs_string!("chat_exit"),
+        obs_string!("Chat line exit animation"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("chat_exit_time"),
+        obs_string!("Seconds a chat line animates out for"),
+        NumberProp::new_float(0.1).with_range(0.0..=60.0),
+      )
+      .add(
+        obs_string!("chat_font_size"),
+        obs_string!("Chat font size (0 uses the text size)"),
+        NumberProp::new_float(1.0).with_range(0.0..=200.0),
+      )
+      .add(
+        obs_string!("alerts_enabled"),
+        obs_string!("Show sub, raid and cheer alerts"),
+        BoolProp,
+      )
+      .add(
+        obs_string!("alerts_font_size"),
+        obs_string!("Alert font size (0 uses the text size)"),
+        NumberProp::new_float(1.0).with_range(0.0..=300.0),
+      )
+      .add(
+        obs_string!("alerts_duration"),
+        obs_string!("Seconds an alert stays up"),
+        NumberProp::new_float(0.5).with_range(1.0..=60.0),
+      )
+      .add(
+        obs_string!("alerts_burst"),
+        obs_string!("Emotes thrown onto the wall with each alert"),
+        NumberProp::new_int().with_range(0u32..=200),
+      )
+      .add(
+        obs_string!("alerts_min_bits"),
+        obs_string!("Fewest bits a cheer needs for an alert"),
+        NumberProp::new_int().with_range(1u32..=1_000_000),
+      )
+      .add(
+        obs_string!("text_font"),
+        obs_string!("Text font family (empty for the system's sans-serif)"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("text_weight"),
+        obs_string!("Text weight (400 regular, 700 bold)"),
+        NumberProp::new_int().with_range(100u32..=900),
+      )
+      .add(
+        obs_string!("text_italic"),
+        obs_string!("Italic text"),
+        BoolProp,
+      )
+      .add(
+        obs_string!("text_size"),
+        obs_string!("Text size"),
+        NumberProp::new_float(1.0).with_range(6.0..=200.0),
+      )
+      .add(
+        obs_string!("text_line_height"),
+        obs_string!("Line height, as a multiple of the text size"),
+        NumberProp::new_float(0.05).with_range(0.5..=3.0),
+      )
+      .add(
+        obs_string!("text_color"),
+        obs_string!("Text color (#RRGGBB or #RRGGBBAA)"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("text_outline_width"),
+        obs_string!("Text outline width (0 disables)"),
+        NumberProp::new_float(0.5).with_range(0.0..=MAX_TEXT_EFFECT as f64),
+      )
+      .add(
+        obs_string!("text_outline_color"),
+        obs_string!("Text outline color"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("text_shadow_x"),
+        obs_string!("Text shadow X offset"),
+        NumberProp::new_float(1.0).with_range(-50.0..=50.0),
+      )
+      .add(
+        obs_string!("text_shadow_y"),
+        obs_string!("Text shadow Y offset"),
+        NumberProp::new_float(1.0).with_range(-50.0..=50.0),
+      )
+      .add(
+        obs_string!("text_shadow_blur"),
+        obs_string!("Text shadow blur"),
+        NumberProp::new_float(0.5).with_range(0.0..=MAX_TEXT_EFFECT as f64),
+      )
+      .add(
+        obs_string!("text_shadow_color"),
+        obs_string!("Text shadow color (#00000000 disables)"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("text_background_color"),
+        obs_string!("Text background color (empty for none)"),
+        TextProp::new(TextType::Default),
+      )
+      .add(
+        obs_string!("text_background_radius"),
+        obs_string!("Text background corner radius"),
+        NumberProp::new_float(1.0).with_range(0.0..=100.0),
       );
     props
   }
@@ -413,69 +1065,91 @@ impl UpdateSource for EmojiKanBan {
     if let Some(screen_width) = settings.get(obs_string!("screen_width")) {
       data.screen_w = screen_width;
     }
-    if let Some(screen_height) = settings.get(obs_string!("screen_height")) {
+    if let Some(screen_height) = settings.get(obs_string!("screen_height"))
+      && screen_height != data.screen_h
+    {
       data.screen_h = screen_height;
+      data.status_badge_text = None; // Badge is anchored to the bottom edge
     }
+    data.font_studio.update_dimensions(data.screen_w as f32, data.screen_h as f32);
     if let Some(offset_x) = settings.get(obs_string!("offset_x")) {
       data.screen_offset_x = offset_x;
     }
     if let Some(offset_y) = settings.get(obs_string!("offset_y")) {
       data.screen_offset_y = offset_y;
     }
+    let cooldowns = read_cooldown_settings(settings, data.cooldowns.config());
+    data.cooldowns.set_config(cooldowns);
+    let chat = read_chat_settings(settings, data.chat_overlay.config());
+    data.chat_overlay.set_config(chat);
+    let alerts = read_alert_settings(settings, data.alert_overlay.config());
+    data.alert_overlay.set_config(alerts);
+    let style = read_text_style_settings(settings, data.font_studio.style());
+    data.set_text_style(style);
+    if let Some(show_status_badge) = settings.get(obs_string!("status_badge")) {
+      data.show_status_badge = show_status_badge;
+    }
+    data.refresh_status_display();
   }
 }
 
 impl VideoTickSource for EmojiKanBan {
   fn video_tick(&mut self, seconds: f32) {
     let data: &mut EmojiKanBan = self;
-    let w = data.screen_w as f32;
-    let h = data.screen_h as f32;
     data.check_twitch_connection();
-    if let Some(rx) = data.emote_rx.as_mut() {
+    data.check_cache_purge();
+    data.cooldowns.update(seconds);
+    // Taken out while draining, so handling a message can borrow all of `data`.
+    if let Some(mut rx) = data.emote_rx.take() {
       while let Ok(emote_data) = rx.try_recv() { match emote_data {
         EmoteComEnum::Data(emote_data) => {
-          if (data.emote_queue.len() as u32) < data.emote_queue_max_length {
-            let mut emote: EmoteOBS = emote_data.into();
-            if emote.tex_vec.is_empty() || emote.frame >= emote.tex_vec.len() {
-              log::error!("tex_vec empty or current frame out of bounds: len: {} frame: {}", emote.tex_vec.len(), emote.frame);
-              continue;
+          if (data.emote_queue.len() as u32) < data.emote_queue_max_length && data.cooldowns.can_spawn() {
+            let profile = data.channel_profiles.get(&emote_data.channel);
+            if let Some(profile) = profile
+              && !data.rng.random_bool(profile.weight.clamp(0., 1.) as f64)
+            {
+              continue; // This channel only gets a share of the wall
+            }
+            if let Some(kind) = data.spawn_emote(emote_data, true) {
+              data.cooldowns.record_spawn(kind);
             }
-            let (ew, eh) = (emote.tex_vec[emote.frame].width() as f32, emote.tex_vec[emote.frame].height() as f32);
-            let picker = data.rng.random_range(1..=100);
-            emote.effect = Some(match picker {
-              1..=10 => {
-                SlideUpEffect::init(
-                  w,h,ew,eh,
-                  &mut data.rng,
-                )
-              }
-              11..=30 => {
-                InchWormEffect::init(
-                  w, h, ew, eh,
-                  &mut data.rng
-                )
-              }
-              31..=100 => {
-                GravityEffect::init(
-                  w,h,ew,eh,
-                  GRAVITY, BOUNCE,
-                  &mut data.rng,
-                )
-              }
-              _ => { unreachable!() }
-            });
-            data.emote_queue.push_back(emote);
           }
         }
+        EmoteComEnum::Chat(line) => {
+          data.chat_overlay.push(&data.font_studio, &line);
+        }
+        EmoteComEnum::Alert(alert) => {
+          data.alert_overlay.push(alert);
+        }
+        EmoteComEnum::TwitchStatus(status) => {
+          data.apply_monitor_status(status);
+        }
         EmoteComEnum::TwitchConnectionFailure(e) => {
-          log::error!("Twitch Connection Failure: {}", e.as_ref().as_ref().unwrap_err());
-          data.twitch_status = InitConnection;
+          let e = e.as_ref().as_ref().unwrap_err();
+          log::error!("Twitch Connection Failure: {}", e);
+          data.twitch_status = Disconnected;
           data.twitch_handle.take();
+          data.cache_purge_tx.take();
+          data.connection_info.connected_since = None;
+          data.connection_info.last_error = Some(e.to_string());
+          data.set_connection_status("Disconnected. Reconnect from Properties");
+        }
+        EmoteComEnum::CachePurged => {
+          data.texture_cache.clear();
         }
         EmoteComEnum::SqliteConnectionFailure(e) => {
-          log::error!("Sqlite Connection Failure: {}", e.as_ref().as_ref().unwrap_err());
+          let e = e.as_ref().as_ref().unwrap_err();
+          log::error!("Sqlite Connection Failure: {}", e);
+          data.connection_info.last_error = Some(e.to_string());
+          data.set_connection_status("Emote cache unavailable");
         }
       }}
+      data.emote_rx = Some(rx);
+    }
+    // An alert's burst skips the cooldowns and channel shares, it's meant to fill the wall.
+    for emote_data in data.alert_overlay.update(&mut data.font_studio, seconds, data.screen_w) {
+      if (data.emote_queue.len() as u32) >= data.emote_queue_max_length { break; }
+      data.spawn_emote(emote_data, false);
     }
     // Animate emotes in queue
     for emote in data.emote_queue.iter_mut() {
@@ -483,10 +1157,12 @@ impl VideoTickSource for EmojiKanBan {
     }
     // Keep only the living
     data.emote_queue.retain(|emote| emote.is_alive() );
+    data.texture_cache.update(seconds);
     for tblk in data.font_studio.text_blocks.iter_mut() {
       tblk.update(seconds);
     }
     data.font_studio.text_blocks.retain(|tblk| tblk.is_alive() );
+    data.chat_overlay.update(&data.font_studio, seconds);
   }
 }
 
@@ -504,6 +1180,8 @@ impl VideoRenderSource for EmojiKanBan {
           effect.draw(emote.current_frame());
         }
       }
+      self.chat_overlay.draw();
+      self.alert_overlay.draw();
       self.font_studio.draw();
       obs_leave_graphics();
     }
@@ -512,14 +1190,22 @@ impl VideoRenderSource for EmojiKanBan {
 
 pub struct EmoteOBS {
   pub name: String,
-  tex_vec: Vec<GraphicsTexture>,
-  delay: Vec<f32>,
+  frames: Rc<EmoteFrames>,
   frame: usize,
   pub frame_time: f32,
   pub effect: Option<Box<dyn EmoteEffect>>,
 }
 
 impl EmoteOBS {
+  pub fn new(name: String, frames: Rc<EmoteFrames>) -> Self {
+    Self {
+      name,
+      frames,
+      frame: 0,
+      frame_time: 0.,
+      effect: None,
+    }
+  }
   pub fn is_alive(&self) -> bool {
     match self.effect.as_ref() {
       None => { false }
@@ -529,82 +1215,20 @@ impl EmoteOBS {
     }
   }
   pub fn current_frame(&self) -> &GraphicsTexture {
-    &self.tex_vec[self.frame]
+    self.frames.frame(self.frame)
   }
   pub fn current_delay(&self) -> f32 {
-    self.delay[self.frame]
+    self.frames.delay(self.frame)
   }
   pub fn update(&mut self, seconds: f32) {
     if let Some(effect) = self.effect.as_mut() {
       effect.update(seconds);
     }
-    if self.tex_vec.len() < 2 { return; }
+    if self.frames.len() < 2 { return; }
     self.frame_time += seconds;
-    if self.frame_time > self.delay[self.frame] {
+    if self.frame_time > self.current_delay() {
       self.frame_time = 0.;
-      self.frame = (self.frame + 1) % self.tex_vec.len();
-    }
-  }
-}
-
-impl From<EmoteData> for EmoteOBS { // This approach is fun but doesn't allow for error handling outside of log::error!()
-  fn from(value: EmoteData) -> Self {
-    let mut tex_vec: Vec<GraphicsTexture> = vec![];
-    let mut delay: Vec<f32> = vec![];
-    match image::guess_format(&value.img) {
-      Err(e) => { log::error!("Failed to guess_format of image data: {}", e) }
-      Ok(ImageFormat::Gif) => {
-        let cursor = std::io::Cursor::new(&value.img);
-        let gifdec_result = GifDecoder::new(cursor);
-        if let Ok(gif) = gifdec_result {
-          let frames = gif.into_frames();
-          for frame_result in frames.into_iter() {
-            let mut width = 0;
-            let mut height = 0;
-            let mut linesize = 0;
-            match frame_result {
-              Err(e) => { log::error!("Failed to decode GIF from image data: {}", e); }
-              Ok(frame) => {
-                let (ms,_) = frame.delay().numer_denom_ms();
-                let d = (ms as f32) / 1000.;
-                let img = DynamicImage::ImageRgba8(frame.into_buffer());
-                if width == 0 {
-                  (width, height) = (img.width(), img.height());
-                  linesize = width * 4;
-                }
-                let mut texture = GraphicsTexture::new(
-                  width, height, 
-                  GraphicsColorFormat::RGBA,
-                );
-                let pixels = img.into_rgba8().into_raw();
-                texture.set_image(&pixels, linesize, false);
-                tex_vec.push(texture);
-                delay.push(d);
-              }
-            }
-          }
-        }
-      }
-      Ok(_) => {
-        if let Ok(img) = image::load_from_memory(&value.img) {
-          let mut texture = GraphicsTexture::new(
-            img.width(), img.height(), 
-            GraphicsColorFormat::RGBA,
-          );
-          let linesize = img.width() * 4; // pixels wide * 4 bytes per pixel for RGBA
-          let pixels = img.into_rgba8().into_raw();
-          texture.set_image(&pixels, linesize, false);
-          tex_vec.push(texture);
-        };
-      }
-    }
-    Self{
-      name: value.name,
-      tex_vec,
-      delay,
-      frame: 0,
-      frame_time: 0.,
-      effect: None,
+      self.frame = (self.frame + 1) % self.frames.len();
     }
   }
 }
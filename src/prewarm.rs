@@ -0,0 +1,159 @@
+use {
+  crate::{
+    EmoteSource, download_emote, fetch_channel_twitch_emotes, fetch_room_id,
+    config_kdl::{
+      EkbConfigDirs, EkbTwitchConfig,
+    },
+    emote_db::EmoteMeta,
+    emote_image::decode_emote,
+    emote_store::EmoteStore,
+    providers::*,
+  },
+  anyhow::{
+    Result,
+    anyhow,
+  },
+  futures::StreamExt,
+  std::{
+    collections::HashSet,
+    path::PathBuf,
+  },
+};
+
+/// Downloads running at once while prewarming.
+const PREWARM_CONCURRENCY: usize = 8;
+
+/// What to download into `emotes.db3` ahead of stream.
+#[derive(Debug, Clone)]
+pub enum PrewarmTarget {
+  /// Global 7TV, BTTV and FFZ emotes plus the ones added to this channel, and the
+  /// channel's own Twitch emotes when authenticated.
+  Channel(String),
+  /// One emote per line as `<provider> <id> [name]`, or just `<id>` for Twitch emotes.
+  /// Blank lines and lines starting with `#` are skipped.
+  IdFile(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrewarmReport {
+  pub already_cached: usize,
+  pub downloaded: usize,
+  pub failed: usize,
+}
+
+/// Fills the emote cache so the wall has everything it needs even if the CDNs are down
+/// later, or with `offline` set. Emotes already cached are left alone, and prewarming
+/// doesn't count as using them.
+pub async fn prewarm(dirs: EkbConfigDirs, conf: &EkbTwitchConfig, targets: &[PrewarmTarget]) -> Result<PrewarmReport> {
+  let store = EmoteStore::open(dirs, conf.cache()).await?;
+  let mut providers = ProviderEmotes::new(conf.providers());
+  let mut sources = vec![];
+  for target in targets {
+    match target {
+      PrewarmTarget::Channel(channel) => {
+        let channel = channel.trim_start_matches('#').to_ascii_lowercase();
+        providers.load_global().await;
+        let room_id = fetch_room_id(conf, &channel).await?;
+        providers.load_channel(&channel, &room_id).await;
+        match fetch_channel_twitch_emotes(conf, &room_id).await {
+          Ok(emotes) => {
+            log::info!("Found {} Twitch emotes for #{}", emotes.len(), channel);
+            sources.extend(emotes);
+          }
+          Err(e) => { log::warn!("Skipping the Twitch emotes of #{}: {}", channel, e); }
+        }
+      }
+      PrewarmTarget::IdFile(path) => {
+        let list = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e) )?;
+        sources.extend(parse_id_list(&list, providers.config()).map_err(|e| anyhow!("{}: {}", path.display(), e) )?);
+      }
+    }
+  }
+  sources.extend(providers.emotes().map(|emote| EmoteSource {
+    provider: emote.provider,
+    id: emote.id.to_owned(),
+    name: emote.code.to_owned(),
+    urls: vec![emote.url.to_owned()],
+    image: None,
+  }));
+  let mut seen = HashSet::new();
+  sources.retain(|source| seen.insert((source.provider, source.id.to_owned())) );
+  log::info!("Prewarming {} emotes", sources.len());
+  let mut report = PrewarmReport::default();
+  let mut results = futures::stream::iter(sources)
+    .map(|source| prewarm_emote(&store, source) )
+    .buffer_unordered(PREWARM_CONCURRENCY);
+  while let Some(result) = results.next().await {
+    match result {
+      PrewarmResult::AlreadyCached => { report.already_cached += 1; }
+      PrewarmResult::Downloaded => { report.downloaded += 1; }
+      PrewarmResult::Failed => { report.failed += 1; }
+    }
+  }
+  Ok(report)
+}
+
+enum PrewarmResult {
+  AlreadyCached,
+  Downloaded,
+  Failed,
+}
+
+async fn prewarm_emote(store: &EmoteStore, source: EmoteSource) -> PrewarmResult {
+  if let Ok(Some(_)) = store.get(source.provider, &source.id).await {
+    return PrewarmResult::AlreadyCached;
+  }
+  let Some((emote_data, validators)) = download_emote(&source).await else {
+    return PrewarmResult::Failed;
+  };
+  let bytes = emote_data.img.clone();
+  let image = match tokio::task::spawn_blocking(move || decode_emote(&bytes) ).await {
+    Ok(Ok(image)) => { image }
+    Ok(Err(e)) => {
+      log::error!("Rejected image data for {} emote id {}: {}", source.provider.as_str(), source.id, e);
+      return PrewarmResult::Failed;
+    }
+    Err(e) => {
+      log::error!("Decoder task for {} emote id {} failed: {}", source.provider.as_str(), source.id, e);
+      return PrewarmResult::Failed;
+    }
+  };
+  let meta = EmoteMeta::new(&emote_data.img, &image);
+  match store.put(emote_data, meta, validators).await {
+    Ok(()) => { PrewarmResult::Downloaded }
+    Err(e) => {
+      log::error!("Failed to write emote to DB: {}", e);
+      PrewarmResult::Failed
+    }
+  }
+}
+
+fn parse_id_list(list: &str, config: &ProviderConfig) -> Result<Vec<EmoteSource>, String> {
+  let mut sources = vec![];
+  for (number, line) in list.lines().enumerate() {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') { continue; }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (provider, id, name) = match words.as_slice() {
+      [id] => { (EmoteProvider::Twitch, *id, *id) }
+      [provider, id] => { (parse_provider(provider, number)?, *id, *id) }
+      [provider, id, name, ..] => { (parse_provider(provider, number)?, *id, *name) }
+      [] => { continue; }
+    };
+    sources.push(EmoteSource {
+      provider,
+      id: id.to_owned(),
+      name: name.to_owned(),
+      urls: emote_urls(provider, id, config),
+      image: None,
+    });
+  }
+  Ok(sources)
+}
+
+fn parse_provider(name: &str, line: usize) -> Result<EmoteProvider, String> {
+  match EmoteProvider::from_name(name) {
+    Some(provider @ (EmoteProvider::Twitch | EmoteProvider::SevenTv | EmoteProvider::Bttv | EmoteProvider::Ffz)) => { Ok(provider) }
+    _ => { Err(format!("line {}: '{}' isn't a provider that can be prewarmed. Use twitch, seventv, bttv or ffz", line + 1, name)) }
+  }
+}
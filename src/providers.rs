@@ -0,0 +1,545 @@
+use {
+  anyhow::{
+    Result,
+    anyhow,
+  },
+  serde_json::Value,
+  std::{
+    collections::{
+      HashMap, HashSet,
+    },
+    time::Duration,
+  },
+  tokio::{
+    sync::mpsc::{
+      UnboundedReceiver, UnboundedSender,
+    },
+    task::JoinSet,
+  },
+};
+
+/// A provider that takes longer than this to answer is skipped until the monitor restarts.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where an emote comes from. Stored in the `provider` column of the emote cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmoteProvider {
+  Twitch,
+  SevenTv,
+  Bttv,
+  Ffz,
+  /// Unicode emoji, drawn locally rather than downloaded.
+  Emoji,
+  /// Image files in the custom emote folder.
+  Custom,
+  /// Chat badges, cached alongside emotes. Keyed `<set>/<version>`, or
+  /// `<channel>/<set>/<version>` for badges a channel can replace.
+  Badge,
+}
+
+impl EmoteProvider {
+  /// Third-party providers, lowest precedence first. When two providers use the same code
+  /// the later one wins, matching how 7TV's own extension layers them.
+  pub const THIRD_PARTY: [EmoteProvider; 3] = [
+    EmoteProvider::Ffz,
+    EmoteProvider::Bttv,
+    EmoteProvider::SevenTv,
+  ];
+  /// Name used in config.kdl and in sqlite.
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      EmoteProvider::Twitch => { "twitch" }
+      EmoteProvider::SevenTv => { "seventv" }
+      EmoteProvider::Bttv => { "bttv" }
+      EmoteProvider::Ffz => { "ffz" }
+      EmoteProvider::Emoji => { "emoji" }
+      EmoteProvider::Custom => { "custom" }
+      EmoteProvider::Badge => { "badge" }
+    }
+  }
+  pub fn from_name(name: &str) -> Option<Self> {
+    [EmoteProvider::Twitch, EmoteProvider::SevenTv, EmoteProvider::Bttv, EmoteProvider::Ffz, EmoteProvider::Emoji, EmoteProvider::Custom, EmoteProvider::Badge]
+      .into_iter().find(|provider| provider.as_str() == name )
+  }
+}
+
+/// API base URLs of the third-party providers. None disables a provider.
+/// Point these at a local server to test without reaching the real services.
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+  pub seventv: Option<String>,
+  pub bttv: Option<String>,
+  /// BTTV's API only returns ids, images are fetched from here.
+  pub bttv_cdn: String,
+  pub ffz: Option<String>,
+  pub emoji: bool,
+  pub custom: bool,
+}
+
+impl Default for ProviderConfig {
+  fn default() -> Self {
+    Self {
+      seventv: Some("https://7tv.io/v3".to_owned()),
+      bttv: Some("https://api.betterttv.net/3".to_owned()),
+      bttv_cdn: "https://cdn.betterttv.net".to_owned(),
+      ffz: Some("https://api.frankerfacez.com/v1".to_owned()),
+      emoji: true,
+      custom: true,
+    }
+  }
+}
+
+impl ProviderConfig {
+  /// Only providers listed afterwards with `enable` are used.
+  pub fn none() -> Self {
+    Self { seventv: None, bttv: None, ffz: None, emoji: false, custom: false, ..Default::default() }
+  }
+  pub fn base_url(&self, provider: EmoteProvider) -> Option<&str> {
+    match provider {
+      EmoteProvider::Twitch | EmoteProvider::Emoji | EmoteProvider::Custom | EmoteProvider::Badge => { None }
+      EmoteProvider::SevenTv => { self.seventv.as_deref() }
+      EmoteProvider::Bttv => { self.bttv.as_deref() }
+      EmoteProvider::Ffz => { self.ffz.as_deref() }
+    }
+  }
+  /// Enables `provider` at `url`, or at its default URL when None.
+  pub fn enable(&mut self, provider: EmoteProvider, url: Option<String>) {
+    let defaults = ProviderConfig::default();
+    let url = url.or(defaults.base_url(provider).map(str::to_owned));
+    match provider {
+      EmoteProvider::Twitch | EmoteProvider::Badge => {}
+      EmoteProvider::SevenTv => { self.seventv = url; }
+      EmoteProvider::Bttv => { self.bttv = url; }
+      EmoteProvider::Ffz => { self.ffz = url; }
+      EmoteProvider::Emoji => { self.emoji = true; }
+      EmoteProvider::Custom => { self.custom = true; }
+    }
+  }
+}
+
+/// Image URLs for an emote known only by its id, tried in order. Twitch emotes always come
+/// this way, third-party ones when prewarming from a list of ids instead of a provider's API.
+pub fn emote_urls(provider: EmoteProvider, id: &str, config: &ProviderConfig) -> Vec<String> {
+  match provider {
+    EmoteProvider::Twitch => {
+      vec![
+        format!("https://static-cdn.jtvnw.net/emoticons/v2/{}/default/light/3.0", id),
+        format!("https://static-cdn.jtvnw.net/emoticons/v1/{}/3.0", id),
+      ]
+    }
+    EmoteProvider::SevenTv => { vec![format!("https://cdn.7tv.app/emote/{}/4x.webp", id)] }
+    EmoteProvider::Bttv => { vec![format!("{}/emote/{}/3x", config.bttv_cdn, id)] }
+    EmoteProvider::Ffz => {
+      ["4", "2", "1"].iter().map(|scale| format!("https://cdn.frankerfacez.com/emote/{}/{}", id, scale) ).collect()
+    }
+    EmoteProvider::Emoji | EmoteProvider::Custom | EmoteProvider::Badge => { vec![] }
+  }
+}
+
+/// An emote found by its code in message text rather than in the `emotes` tag.
+#[derive(Debug, Clone)]
+pub struct ThirdPartyEmote {
+  pub provider: EmoteProvider,
+  pub id: String,
+  pub code: String,
+  pub url: String,
+}
+
+type EmoteSet = HashMap<String, ThirdPartyEmote>;
+/// A set fetched in the background, with the channel it's for. None is the global set.
+type LoadedSet = (Option<String>, EmoteSet);
+
+/// Global and per-channel emote sets of every enabled provider, fetched once per monitor.
+/// Codes are case sensitive, as they are on every provider.
+pub struct ProviderEmotes {
+  config: ProviderConfig,
+  client: reqwest::Client,
+  global: Option<EmoteSet>,
+  channels: HashMap<String, EmoteSet>,
+  /// True once `load_cached` stood in for the providers.
+  offline: bool,
+  /// Channels with a spawned load that hasn't arrived yet.
+  loading: HashSet<String>,
+  loaded_tx: UnboundedSender<LoadedSet>,
+  loaded_rx: UnboundedReceiver<LoadedSet>,
+}
+
+impl ProviderEmotes {
+  pub fn new(config: ProviderConfig) -> Self {
+    let client = reqwest::Client::builder().timeout(PROVIDER_TIMEOUT).build().unwrap_or_else(|e| {
+      log::error!("Failed to set up the emote provider client, continuing without a timeout: {}", e);
+      reqwest::Client::new()
+    });
+    let (loaded_tx, loaded_rx) = tokio::sync::mpsc::unbounded_channel();
+    Self { config, client, global: None, channels: HashMap::new(), offline: false, loading: HashSet::new(), loaded_tx, loaded_rx }
+  }
+  pub fn config(&self) -> &ProviderConfig {
+    &self.config
+  }
+  /// True once a load for `channel` was started, even if it hasn't arrived yet.
+  pub fn has_channel(&self, channel: &str) -> bool {
+    self.channels.contains_key(channel) || self.loading.contains(channel)
+  }
+  /// Every emote loaded so far, global ones first.
+  pub fn emotes(&self) -> impl Iterator<Item = &ThirdPartyEmote> {
+    self.global.iter().chain(self.channels.values()).flat_map(|set| set.values() )
+  }
+  /// Only the emotes added to `channel` itself, not the global ones. Offline every cached
+  /// emote stands in, since the cache doesn't know which channel an emote came from.
+  pub fn channel_emotes(&self, channel: &str) -> impl Iterator<Item = &ThirdPartyEmote> {
+    let set = match self.offline {
+      true => { self.global.as_ref() }
+      false => { self.channels.get(channel) }
+    };
+    set.into_iter().flat_map(|set| set.values() )
+  }
+  /// For offline mode: emotes already in the cache stand in for every provider's sets.
+  /// The cache doesn't know which channel an emote came from, so they all count as global.
+  pub fn load_cached(&mut self, emotes: Vec<ThirdPartyEmote>) {
+    let mut set = EmoteSet::new();
+    for provider in EmoteProvider::THIRD_PARTY {
+      if self.config.base_url(provider).is_none() { continue; }
+      set.extend(emotes.iter().filter(|emote| emote.provider == provider ).map(|emote| (emote.code.to_owned(), emote.clone()) ));
+    }
+    log::info!("Offline: using {} cached third-party emotes", set.len());
+    self.global = Some(set);
+    self.offline = true;
+  }
+  /// A provider that fails is logged and skipped. It's tried again when the monitor restarts.
+  pub async fn load_global(&mut self) {
+    if self.global.is_some() { return; }
+    self.global = Some(fetch_global_set(self.client.clone(), self.config.clone()).await);
+  }
+  /// `room_id` is the channel's Twitch user id from the `room-id` tag, which every provider keys on.
+  pub async fn load_channel(&mut self, channel: &str, room_id: &str) {
+    let set = fetch_channel_set(self.client.clone(), self.config.clone(), channel.to_owned(), room_id.to_owned()).await;
+    self.channels.insert(channel.to_owned(), set);
+  }
+  /// Like [`ProviderEmotes::load_global`], but fetched on `tasks` so chat isn't held up.
+  /// The set is used once [`ProviderEmotes::receive_loaded`] picks it up.
+  pub fn spawn_global_load(&mut self, tasks: &mut JoinSet<()>) {
+    if self.global.is_some() { return; }
+    let (client, config, loaded_tx) = (self.client.clone(), self.config.clone(), self.loaded_tx.clone());
+    tasks.spawn(async move {
+      _ = loaded_tx.send((None, fetch_global_set(client, config).await));
+    });
+  }
+  /// Like [`ProviderEmotes::load_channel`], but fetched on `tasks` so chat isn't held up.
+  pub fn spawn_channel_load(&mut self, tasks: &mut JoinSet<()>, channel: &str, room_id: &str) {
+    if self.has_channel(channel) { return; }
+    self.loading.insert(channel.to_owned());
+    let (client, config, loaded_tx) = (self.client.clone(), self.config.clone(), self.loaded_tx.clone());
+    let (channel, room_id) = (channel.to_owned(), room_id.to_owned());
+    tasks.spawn(async move {
+      let set = fetch_channel_set(client, config, channel.clone(), room_id).await;
+      _ = loaded_tx.send((Some(channel), set));
+    });
+  }
+  /// Puts sets from spawned loads that finished since the last call to use.
+  pub fn receive_loaded(&mut self) {
+    while let Ok((channel, set)) = self.loaded_rx.try_recv() {
+      match channel {
+        Some(channel) => {
+          self.loading.remove(&channel);
+          self.channels.insert(channel, set);
+        }
+        None => { self.global = Some(set); }
+      }
+    }
+  }
+  /// Channel emotes take precedence over global ones with the same code.
+  pub fn lookup(&self, channel: &str, code: &str) -> Option<&ThirdPartyEmote> {
+    self.channels.get(channel).and_then(|set| set.get(code) )
+      .or_else(|| self.global.as_ref().and_then(|set| set.get(code) ) )
+  }
+  /// Third-party emotes in the order they appear in `text`. Words in `native` were already
+  /// found in the `emotes` tag and are skipped.
+  pub fn find_in_message(&self, channel: &str, text: &str, native: &[String]) -> Vec<ThirdPartyEmote> {
+    let text = text.strip_prefix("\u{1}ACTION ").map(|text| text.trim_end_matches('\u{1}') ).unwrap_or(text);
+    text.split_whitespace()
+      .filter(|word| !native.iter().any(|name| name == word) )
+      .filter_map(|word| self.lookup(channel, word) )
+      .cloned()
+      .collect()
+  }
+}
+
+async fn fetch_global_set(client: reqwest::Client, config: ProviderConfig) -> EmoteSet {
+  let mut set = EmoteSet::new();
+  for provider in EmoteProvider::THIRD_PARTY {
+    let Some(base) = config.base_url(provider) else { continue; };
+    match fetch_global(&client, provider, base, &config).await {
+      Ok(emotes) => {
+        log::info!("Loaded {} global {} emotes", emotes.len(), provider.as_str());
+        set.extend(emotes.into_iter().map(|emote| (emote.code.to_owned(), emote) ));
+      }
+      Err(e) => { log::warn!("Failed to load global {} emotes: {}", provider.as_str(), e); }
+    }
+  }
+  set
+}
+
+async fn fetch_channel_set(client: reqwest::Client, config: ProviderConfig, channel: String, room_id: String) -> EmoteSet {
+  let mut set = EmoteSet::new();
+  for provider in EmoteProvider::THIRD_PARTY {
+    let Some(base) = config.base_url(provider) else { continue; };
+    match fetch_channel(&client, provider, base, &room_id, &config).await {
+      Ok(emotes) => {
+        log::info!("Loaded {} {} emotes for #{}", emotes.len(), provider.as_str(), channel);
+        set.extend(emotes.into_iter().map(|emote| (emote.code.to_owned(), emote) ));
+      }
+      // Most channels don't use every provider, and they answer 404 for those.
+      Err(e) => { log::info!("No {} emotes for #{}: {}", provider.as_str(), channel, e); }
+    }
+  }
+  set
+}
+
+async fn fetch_json(client: &reqwest::Client, url: &str) -> Result<Value> {
+  let response = client.get(url).send().await?.error_for_status()?;
+  let bytes = response.bytes().await?;
+  Ok(serde_json::from_slice(&bytes)?)
+}
+
+async fn fetch_global(client: &reqwest::Client, provider: EmoteProvider, base: &str, config: &ProviderConfig) -> Result<Vec<ThirdPartyEmote>> {
+  match provider {
+    EmoteProvider::SevenTv => {
+      let set = fetch_json(client, &format!("{}/emote-sets/global", base)).await?;
+      Ok(parse_seventv(&set["emotes"]))
+    }
+    EmoteProvider::Bttv => {
+      let emotes = fetch_json(client, &format!("{}/cached/emotes/global", base)).await?;
+      Ok(parse_bttv(&emotes, &config.bttv_cdn))
+    }
+    EmoteProvider::Ffz => {
+      let global = fetch_json(client, &format!("{}/set/global", base)).await?;
+      let default_sets: Vec<String> = global["default_sets"].as_array().into_iter().flatten()
+        .filter_map(json_id)
+        .collect();
+      let emotes = global["sets"].as_object().into_iter().flatten()
+        .filter(|(id, _)| default_sets.contains(*id) )
+        .flat_map(|(_, set)| parse_ffz(&set["emoticons"]) )
+        .collect();
+      Ok(emotes)
+    }
+    EmoteProvider::Twitch | EmoteProvider::Emoji | EmoteProvider::Custom | EmoteProvider::Badge => { Err(anyhow!("{} emotes aren't fetched from an API", provider.as_str())) }
+  }
+}
+
+async fn fetch_channel(client: &reqwest::Client, provider: EmoteProvider, base: &str, room_id: &str, config: &ProviderConfig) -> Result<Vec<ThirdPartyEmote>> {
+  match provider {
+    EmoteProvider::SevenTv => {
+      let user = fetch_json(client, &format!("{}/users/twitch/{}", base, room_id)).await?;
+      Ok(parse_seventv(&user["emote_set"]["emotes"]))
+    }
+    EmoteProvider::Bttv => {
+      let user = fetch_json(client, &format!("{}/cached/users/twitch/{}", base, room_id)).await?;
+      let mut emotes = parse_bttv(&user["channelEmotes"], &config.bttv_cdn);
+      emotes.extend(parse_bttv(&user["sharedEmotes"], &config.bttv_cdn));
+      Ok(emotes)
+    }
+    EmoteProvider::Ffz => {
+      let room = fetch_json(client, &format!("{}/room/id/{}", base, room_id)).await?;
+      let emotes = room["sets"].as_object().into_iter().flatten()
+        .flat_map(|(_, set)| parse_ffz(&set["emoticons"]) )
+        .collect();
+      Ok(emotes)
+    }
+    EmoteProvider::Twitch | EmoteProvider::Emoji | EmoteProvider::Custom | EmoteProvider::Badge => { Err(anyhow!("{} emotes aren't fetched from an API", provider.as_str())) }
+  }
+}
+
+/// Ids are strings on 7TV and BTTV but numbers on FFZ.
+fn json_id(value: &Value) -> Option<String> {
+  match value {
+    Value::String(id) => { Some(id.to_owned()) }
+    Value::Number(id) => { Some(id.to_string()) }
+    _ => { None }
+  }
+}
+
+/// Provider CDNs sometimes hand out protocol relative URLs.
+fn absolute_url(url: &str) -> String {
+  match url.strip_prefix("//") {
+    Some(rest) => { format!("https://{}", rest) }
+    None => { url.to_owned() }
+  }
+}
+
+fn parse_seventv(emotes: &Value) -> Vec<ThirdPartyEmote> {
+  emotes.as_array().into_iter().flatten().filter_map(|emote| {
+    let host = &emote["data"]["host"];
+    // Files are listed smallest first. WebP keeps animation and `image` can decode it.
+    let file = host["files"].as_array()?.iter()
+      .filter_map(|file| file["name"].as_str() )
+      .rfind(|name| name.ends_with(".webp") )?;
+    Some(ThirdPartyEmote {
+      provider: EmoteProvider::SevenTv,
+      id: json_id(&emote["id"])?,
+      code: emote["name"].as_str()?.to_owned(),
+      url: format!("{}/{}", absolute_url(host["url"].as_str()?), file),
+    })
+  }).collect()
+}
+
+fn parse_bttv(emotes: &Value, cdn: &str) -> Vec<ThirdPartyEmote> {
+  emotes.as_array().into_iter().flatten().filter_map(|emote| {
+    let id = json_id(&emote["id"])?;
+    Some(ThirdPartyEmote {
+      provider: EmoteProvider::Bttv,
+      url: format!("{}/emote/{}/3x", cdn, id),
+      code: emote["code"].as_str()?.to_owned(),
+      id,
+    })
+  }).collect()
+}
+
+fn parse_ffz(emotes: &Value) -> Vec<ThirdPartyEmote> {
+  emotes.as_array().into_iter().flatten().filter_map(|emote| {
+    // `urls` maps scale to URL. Animated emotes also have `animated` with the same keys.
+    let urls = match emote["animated"].as_object() {
+      Some(animated) if !animated.is_empty() => { animated }
+      _ => { emote["urls"].as_object()? }
+    };
+    let url = ["4", "2", "1"].iter().find_map(|scale| urls.get(*scale).and_then(Value::as_str) )?;
+    Some(ThirdPartyEmote {
+      provider: EmoteProvider::Ffz,
+      id: json_id(&emote["id"])?,
+      code: emote["name"].as_str()?.to_owned(),
+      url: absolute_url(url),
+    })
+  }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use {
+    super::*,
+    std::{
+      io::{BufRead, BufReader, Write},
+      net::TcpListener,
+    },
+  };
+
+  const SEVENTV_GLOBAL: &str = r#"{"emotes": [
+    {"id": "7g1", "name": "EZ", "data": {"host": {"url": "//cdn.7tv.app/emote/7g1", "files": [
+      {"name": "1x.avif"}, {"name": "1x.webp"}, {"name": "4x.avif"}, {"name": "4x.webp"}
+    ]}}},
+    {"id": "7g2", "name": "NoWebp", "data": {"host": {"url": "//cdn.7tv.app/emote/7g2", "files": [{"name": "1x.avif"}]}}}
+  ]}"#;
+  const SEVENTV_USER: &str = r#"{"emote_set": {"emotes": [
+    {"id": "7c1", "name": "Shared", "data": {"host": {"url": "https://cdn.7tv.app/emote/7c1", "files": [{"name": "2x.webp"}]}}}
+  ]}}"#;
+  const BTTV_GLOBAL: &str = r#"[{"id": "bg1", "code": "Shared"}, {"id": "bg2", "code": "catJAM"}]"#;
+  const BTTV_USER: &str = r#"{"channelEmotes": [{"id": "bc1", "code": "ChannelOnly"}], "sharedEmotes": [{"id": "bc2", "code": "EZ"}]}"#;
+  const FFZ_GLOBAL: &str = r#"{"default_sets": [3], "sets": {
+    "3": {"emoticons": [
+      {"id": 1, "name": "Shared", "urls": {"1": "//cdn.frankerfacez.com/emote/1/1", "4": "//cdn.frankerfacez.com/emote/1/4"}},
+      {"id": 2, "name": "ZrehplaR", "urls": {"1": "https://cdn.frankerfacez.com/emote/2/1"}, "animated": {"1": "https://cdn.frankerfacez.com/emote/2/animated/1"}}
+    ]},
+    "4": {"emoticons": [{"id": 5, "name": "NotDefault", "urls": {"1": "https://cdn.frankerfacez.com/emote/5/1"}}]}
+  }}"#;
+
+  /// A fake HTTP server for every provider on a free local port. Paths it doesn't know get a 404,
+  /// as providers answer for channels that don't use them.
+  fn fake_provider_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+      for stream in listener.incoming() {
+        let Ok(mut stream) = stream else { break; };
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request = String::new();
+        if reader.read_line(&mut request).is_err() { continue; }
+        let mut header = String::new();
+        while reader.read_line(&mut header).is_ok_and(|read| read > 2 ) {
+          header.clear();
+        }
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let body = match path {
+          "/7tv/emote-sets/global" => { Some(SEVENTV_GLOBAL) }
+          "/7tv/users/twitch/1234" => { Some(SEVENTV_USER) }
+          "/bttv/cached/emotes/global" => { Some(BTTV_GLOBAL) }
+          "/bttv/cached/users/twitch/1234" => { Some(BTTV_USER) }
+          "/ffz/set/global" => { Some(FFZ_GLOBAL) }
+          _ => { None }
+        };
+        let response = match body {
+          Some(body) => { format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body) }
+          None => { "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned() }
+        };
+        _ = stream.write_all(response.as_bytes());
+      }
+    });
+    base
+  }
+
+  fn test_config(base: &str) -> ProviderConfig {
+    let mut config = ProviderConfig::none();
+    config.enable(EmoteProvider::SevenTv, Some(format!("{}/7tv", base)));
+    config.enable(EmoteProvider::Bttv, Some(format!("{}/bttv", base)));
+    config.enable(EmoteProvider::Ffz, Some(format!("{}/ffz", base)));
+    config
+  }
+
+  #[tokio::test]
+  async fn loads_and_layers_provider_sets() {
+    let base = fake_provider_server();
+    let mut providers = ProviderEmotes::new(test_config(&base));
+    providers.load_global().await;
+    providers.load_channel("test", "1234").await;
+
+    let ez = providers.lookup("other", "EZ").unwrap();
+    assert_eq!((ez.provider, ez.id.as_str()), (EmoteProvider::SevenTv, "7g1"));
+    assert_eq!(ez.url, "https://cdn.7tv.app/emote/7g1/4x.webp");
+    assert!(providers.lookup("other", "NoWebp").is_none());
+    // FFZ uses the biggest animated image, and only its default sets are global.
+    let zreh = providers.lookup("other", "ZrehplaR").unwrap();
+    assert_eq!((zreh.id.as_str(), zreh.url.as_str()), ("2", "https://cdn.frankerfacez.com/emote/2/animated/1"));
+    assert!(providers.lookup("other", "NotDefault").is_none());
+    // 7TV beats BTTV beats FFZ.
+    assert_eq!(providers.lookup("other", "Shared").unwrap().provider, EmoteProvider::Bttv);
+    let catjam = providers.lookup("other", "catJAM").unwrap();
+    assert_eq!(catjam.url, "https://cdn.betterttv.net/emote/bg2/3x");
+
+    // Channel emotes beat global ones, and FFZ's 404 for the room just leaves it out.
+    assert_eq!(providers.lookup("test", "Shared").unwrap().id, "7c1");
+    assert_eq!(providers.lookup("test", "EZ").unwrap().id, "bc2");
+    assert_eq!(providers.lookup("test", "ChannelOnly").unwrap().provider, EmoteProvider::Bttv);
+    assert!(providers.lookup("other", "ChannelOnly").is_none());
+    assert_eq!(providers.channel_emotes("test").count(), 3);
+
+    let found: Vec<String> = providers.find_in_message("test", "\u{1}ACTION EZ catJAM Kappa ChannelOnly\u{1}", &["catJAM".to_owned()])
+      .into_iter().map(|emote| emote.code ).collect();
+    assert_eq!(found, ["EZ", "ChannelOnly"]);
+  }
+
+  #[tokio::test]
+  async fn spawned_loads_arrive_on_receive() {
+    let base = fake_provider_server();
+    let mut providers = ProviderEmotes::new(test_config(&base));
+    let mut tasks = JoinSet::new();
+    providers.spawn_global_load(&mut tasks);
+    providers.spawn_channel_load(&mut tasks, "test", "1234");
+    assert!(providers.has_channel("test"));
+    assert!(providers.lookup("test", "ChannelOnly").is_none());
+    while tasks.join_next().await.is_some() {}
+    providers.receive_loaded();
+    assert!(providers.lookup("test", "ChannelOnly").is_some());
+    assert!(providers.lookup("test", "catJAM").is_some());
+  }
+
+  #[test]
+  fn offline_channel_emotes_are_the_cached_ones() {
+    let emote = |provider, code: &str| ThirdPartyEmote { provider, id: code.to_lowercase(), code: code.to_owned(), url: String::new() };
+    let mut config = ProviderConfig::none();
+    config.enable(EmoteProvider::SevenTv, None);
+    let mut providers = ProviderEmotes::new(config);
+    providers.load_cached(vec![emote(EmoteProvider::SevenTv, "EZ"), emote(EmoteProvider::Bttv, "catJAM")]);
+
+    // BTTV is off, and the cache can't tell channels apart.
+    let codes: Vec<&str> = providers.channel_emotes("test").map(|emote| emote.code.as_str() ).collect();
+    assert_eq!(codes, ["EZ"]);
+    assert_eq!(providers.channel_emotes("other").count(), 1);
+  }
+}
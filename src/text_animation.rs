@@ -0,0 +1,128 @@
+use {
+  enterpolation::easing::smootherstep,
+};
+
+/// How far past full size a scale-pop grows before settling.
+const POP_OVERSHOOT: f32 = 0.1;
+/// Share of a scale-pop spent growing, the rest settles back to full size.
+const POP_GROW: f32 = 0.7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+  Left,
+  Right,
+  Top,
+  Bottom,
+}
+
+/// How a text block appears or disappears. Exits run their animation backwards, so a
+/// block leaving with `slide-left` slides out past the left edge.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TextAnimation {
+  #[default]
+  None,
+  Fade,
+  /// From off screen past the edge to where the block sits.
+  Slide(Edge),
+  /// Grows from its center, a little past full size, then settles.
+  ScalePop,
+  /// Uncovers the text one glyph at a time, line by line.
+  Typewriter,
+}
+
+impl TextAnimation {
+  pub const NAMES: [&str; 8] = ["none", "fade", "slide-left", "slide-right", "slide-top", "slide-bottom", "pop", "typewriter"];
+  /// Name used in config.kdl and Properties.
+  pub fn name(&self) -> &'static str {
+    match self {
+      TextAnimation::None => { "none" }
+      TextAnimation::Fade => { "fade" }
+      TextAnimation::Slide(Edge::Left) => { "slide-left" }
+      TextAnimation::Slide(Edge::Right) => { "slide-right" }
+      TextAnimation::Slide(Edge::Top) => { "slide-top" }
+      TextAnimation::Slide(Edge::Bottom) => { "slide-bottom" }
+      TextAnimation::ScalePop => { "pop" }
+      TextAnimation::Typewriter => { "typewriter" }
+    }
+  }
+  pub fn from_name(name: &str) -> Option<Self> {
+    [
+      TextAnimation::None, TextAnimation::Fade,
+      TextAnimation::Slide(Edge::Left), TextAnimation::Slide(Edge::Right),
+      TextAnimation::Slide(Edge::Top), TextAnimation::Slide(Edge::Bottom),
+      TextAnimation::ScalePop, TextAnimation::Typewriter,
+    ].into_iter().find(|animation| animation.name() == name )
+  }
+}
+
+/// A block's enter and exit animations. Exits play out over the end of the block's life,
+/// so blocks without a life only ever animate in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextTransition {
+  pub enter: TextAnimation,
+  /// Seconds.
+  pub enter_time: f32,
+  pub exit: TextAnimation,
+  /// Seconds.
+  pub exit_time: f32,
+}
+
+impl Default for TextTransition {
+  fn default() -> Self {
+    Self { enter: TextAnimation::None, enter_time: 0.3, exit: TextAnimation::None, exit_time: 0.3 }
+  }
+}
+
+impl TextTransition {
+  pub fn new(enter: TextAnimation, exit: TextAnimation) -> Self {
+    Self { enter, exit, ..Default::default() }
+  }
+  /// Where the block is in its transition, `age` seconds after it appeared with `life` left.
+  pub fn frame(&self, age: f32, life: Option<f32>) -> TransitionFrame {
+    if let Some(life) = life
+      && self.exit != TextAnimation::None
+      && life < self.exit_time
+    {
+      return TransitionFrame::of(self.exit, (life / self.exit_time).max(0.));
+    }
+    if self.enter != TextAnimation::None && age < self.enter_time {
+      return TransitionFrame::of(self.enter, age / self.enter_time);
+    }
+    TransitionFrame::SHOWN
+  }
+}
+
+/// How to draw a block partway through an animation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransitionFrame {
+  /// Share of the distance still to travel from the edge, 1 is fully off screen.
+  pub slide: Option<(Edge, f32)>,
+  /// Size relative to the rendered block, around its center.
+  pub scale: f32,
+  pub opacity: f32,
+  /// Share of the glyphs uncovered.
+  pub reveal: f32,
+}
+
+impl TransitionFrame {
+  pub const SHOWN: Self = Self { slide: None, scale: 1., opacity: 1., reveal: 1. };
+  /// `pct` runs from 0, not shown yet, to 1, fully shown.
+  fn of(animation: TextAnimation, pct: f32) -> Self {
+    let pct = pct.clamp(0., 1.);
+    let step = smootherstep(pct);
+    match animation {
+      TextAnimation::None => { Self::SHOWN }
+      TextAnimation::Fade => { Self { opacity: step, ..Self::SHOWN } }
+      TextAnimation::Slide(edge) => { Self { slide: Some((edge, 1. - step)), ..Self::SHOWN } }
+      TextAnimation::ScalePop => {
+        let scale = match pct < POP_GROW {
+          true => { (1. + POP_OVERSHOOT) * smootherstep(pct / POP_GROW) }
+          false => { 1. + POP_OVERSHOOT * (1. - smootherstep((pct - POP_GROW) / (1. - POP_GROW))) }
+        };
+        Self { scale, ..Self::SHOWN }
+      }
+      // Glyphs appear at an even pace, easing would make typing speed up and slow down.
+      TextAnimation::Typewriter => { Self { reveal: pct, ..Self::SHOWN } }
+    }
+  }
+}
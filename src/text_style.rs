@@ -0,0 +1,162 @@
+use {
+  image::{
+    GrayImage, Luma, Pixel, Rgba, RgbaImage,
+  },
+};
+
+/// Largest outline width and shadow blur, in pixels. Both get slow to draw and pad every
+/// text texture well past this.
+pub const MAX_TEXT_EFFECT: f32 = 20.;
+
+/// How `FontStudio` rasterizes text. Spans can still override the fill color and boldness.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+  /// Font family name. None uses the system's sans-serif font.
+  pub family: Option<String>,
+  /// 100 to 900, 400 is regular and 700 bold.
+  pub weight: u16,
+  pub italic: bool,
+  /// Pixels. Chat and alerts use it unless they set a font size of their own.
+  pub size: f32,
+  /// Multiple of `size`.
+  pub line_height: f32,
+  pub color: [u8; 4],
+  /// Pixels, up to `MAX_TEXT_EFFECT`. 0 disables the outline.
+  pub outline_width: f32,
+  pub outline_color: [u8; 4],
+  pub shadow_offset: (f32, f32),
+  /// Gaussian blur sigma in pixels, up to `MAX_TEXT_EFFECT`.
+  pub shadow_blur: f32,
+  /// A transparent color disables the shadow.
+  pub shadow_color: [u8; 4],
+  /// Rounded rectangle behind the text. None draws no background.
+  pub background: Option<[u8; 4]>,
+  pub background_radius: f32,
+  /// Centers each line in the block's width. Set by whatever renders the block, such as
+  /// alerts, rather than from config.kdl or Properties.
+  pub centered: bool,
+}
+
+impl Default for TextStyle {
+  fn default() -> Self {
+    Self {
+      family: None,
+      weight: 400,
+      italic: false,
+      size: 24.,
+      line_height: 1.2,
+      color: [0xFF, 0xFF, 0xFF, 0xFF],
+      outline_width: 0.,
+      outline_color: [0x00, 0x00, 0x00, 0xFF],
+      shadow_offset: (2., 2.),
+      shadow_blur: 2.,
+      shadow_color: [0x00, 0x00, 0x00, 0x00],
+      background: None,
+      background_radius: 8.,
+      centered: false,
+    }
+  }
+}
+
+impl TextStyle {
+  /// The same style at another font size, for text that has a size of its own.
+  pub fn sized(&self, size: f32) -> Self {
+    Self { size, ..self.clone() }
+  }
+  /// Like `sized`, but None keeps this style's own size.
+  pub fn resized(&self, size: Option<f32>) -> Self {
+    match size {
+      Some(size) => { self.sized(size) }
+      None => { self.clone() }
+    }
+  }
+  pub fn centered(&self) -> Self {
+    Self { centered: true, ..self.clone() }
+  }
+  /// Font size and line height in pixels, as `cosmic_text::Metrics` wants them.
+  pub fn metrics(&self) -> (f32, f32) {
+    (self.size, (self.size * self.line_height).max(1.))
+  }
+  pub fn has_outline(&self) -> bool {
+    self.outline_width > 0. && self.outline_color[3] > 0
+  }
+  pub fn has_shadow(&self) -> bool {
+    self.shadow_color[3] > 0
+  }
+  /// How far past the glyphs the outline and shadow can reach.
+  pub fn effect_margin(&self) -> u32 {
+    let outline = if self.has_outline() { self.outline_width } else { 0. };
+    let shadow = match self.has_shadow() {
+      true => { self.shadow_offset.0.abs().max(self.shadow_offset.1.abs()) + 3. * self.shadow_blur }
+      false => { 0. }
+    };
+    (outline + shadow).ceil() as u32
+  }
+}
+
+/// `#RRGGBB` or `#RRGGBBAA`.
+pub fn parse_hex_color(value: &str) -> Option<[u8; 4]> {
+  let hex = value.strip_prefix('#')?;
+  if hex.len() != 6 && hex.len() != 8 { return None; }
+  let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+  let alpha = if hex.len() == 8 { channel(6)? } else { 0xFF };
+  Some([channel(0)?, channel(2)?, channel(4)?, alpha])
+}
+
+pub fn hex_color(color: [u8; 4]) -> String {
+  let [r, g, b, a] = color;
+  format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a)
+}
+
+/// Grows the coverage mask by `radius` pixels, anti-aliased at the edge. Used for outlines.
+pub fn dilate(mask: &GrayImage, radius: f32) -> GrayImage {
+  let reach = radius.ceil() as i32;
+  let kernel: Vec<(i32, i32, f32)> = (-reach..=reach)
+    .flat_map(|dy| (-reach..=reach).map(move |dx| (dx, dy) ) )
+    .filter_map(|(dx, dy)| {
+      let weight = (radius + 0.5 - ((dx * dx + dy * dy) as f32).sqrt()).clamp(0., 1.);
+      (weight > 0.).then_some((dx, dy, weight))
+    })
+    .collect();
+  let (w, h) = mask.dimensions();
+  GrayImage::from_fn(w, h, |x, y| {
+    let mut value = 0f32;
+    for &(dx, dy, weight) in kernel.iter() {
+      let (src_x, src_y) = (x as i32 + dx, y as i32 + dy);
+      if src_x < 0 || src_y < 0 || src_x >= w as i32 || src_y >= h as i32 { continue; }
+      value = value.max(mask.get_pixel(src_x as u32, src_y as u32)[0] as f32 * weight);
+    }
+    Luma([value.round() as u8])
+  })
+}
+
+/// Blends `color` onto `img` wherever `mask` has coverage, shifted by `offset`.
+pub fn paint_mask(img: &mut RgbaImage, mask: &GrayImage, color: [u8; 4], offset: (i64, i64)) {
+  let [r, g, b, a] = color;
+  let (w, h) = (img.width() as i64, img.height() as i64);
+  for (x, y, coverage) in mask.enumerate_pixels() {
+    if coverage[0] == 0 { continue; }
+    let (dst_x, dst_y) = (x as i64 + offset.0, y as i64 + offset.1);
+    if dst_x < 0 || dst_y < 0 || dst_x >= w || dst_y >= h { continue; }
+    let alpha = (coverage[0] as u16 * a as u16 / 255) as u8;
+    img.get_pixel_mut(dst_x as u32, dst_y as u32).blend(&Rgba([r, g, b, alpha]));
+  }
+}
+
+/// Blends an anti-aliased rounded rectangle of `(left, top, width, height)` onto `img`.
+pub fn fill_rounded_rect(img: &mut RgbaImage, rect: (f32, f32, f32, f32), radius: f32, color: [u8; 4]) {
+  let [r, g, b, a] = color;
+  let (left, top, w, h) = rect;
+  let radius = radius.clamp(0., w.min(h) / 2.);
+  let (center_x, center_y) = (left + w / 2., top + h / 2.);
+  for (x, y, pxl) in img.enumerate_pixels_mut() {
+    // Signed distance to the edge, negative inside.
+    let qx = (x as f32 + 0.5 - center_x).abs() - (w / 2. - radius);
+    let qy = (y as f32 + 0.5 - center_y).abs() - (h / 2. - radius);
+    let outside = (qx.max(0.).powi(2) + qy.max(0.).powi(2)).sqrt();
+    let dist = outside + qx.max(qy).min(0.) - radius;
+    let coverage = (0.5 - dist).clamp(0., 1.);
+    if coverage <= 0. { continue; }
+    pxl.blend(&Rgba([r, g, b, (a as f32 * coverage).round() as u8]));
+  }
+}
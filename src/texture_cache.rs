@@ -0,0 +1,96 @@
+use {
+  crate::emote_image::DecodedEmote,
+  obs_wrapper::graphics::*,
+  std::{
+    collections::HashMap,
+    rc::Rc,
+  },
+};
+
+/// Uploaded frames of one emote, shared by every `EmoteOBS` showing it.
+pub struct EmoteFrames {
+  tex_vec: Vec<GraphicsTexture>,
+  delay: Vec<f32>,
+}
+
+impl EmoteFrames {
+  pub fn len(&self) -> usize {
+    self.tex_vec.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.tex_vec.is_empty()
+  }
+  pub fn frame(&self, index: usize) -> &GraphicsTexture {
+    &self.tex_vec[index]
+  }
+  pub fn delay(&self, index: usize) -> f32 {
+    self.delay.get(index).copied().unwrap_or(0.)
+  }
+}
+
+impl From<&DecodedEmote> for EmoteFrames {
+  /// GPU upload only. Decoding has already happened on the tokio runtime.
+  fn from(decoded: &DecodedEmote) -> Self {
+    let tex_vec = decoded.frames.iter().map(|frame| {
+      let mut texture = GraphicsTexture::new(
+        frame.width, frame.height,
+        GraphicsColorFormat::RGBA,
+      );
+      texture.set_image(&frame.rgba, frame.linesize(), false);
+      texture
+    }).collect();
+    Self { tex_vec, delay: decoded.delays.clone() }
+  }
+}
+
+struct CachedFrames {
+  frames: Rc<EmoteFrames>,
+  /// Seconds since the last `EmoteOBS` using these frames went away.
+  idle: f32,
+}
+
+/// Emote textures keyed by emote id. Entries are dropped once nothing on screen
+/// has used them for `idle_ttl` seconds.
+pub struct TextureCache {
+  entries: HashMap<String, CachedFrames>,
+  idle_ttl: f32,
+}
+
+impl TextureCache {
+  pub fn new(idle_ttl: f32) -> Self {
+    Self { entries: HashMap::new(), idle_ttl }
+  }
+  pub fn get(&mut self, id: &str) -> Option<Rc<EmoteFrames>> {
+    self.entries.get_mut(id).map(|entry| {
+      entry.idle = 0.;
+      entry.frames.clone()
+    })
+  }
+  pub fn insert(&mut self, id: String, frames: EmoteFrames) -> Rc<EmoteFrames> {
+    let frames = Rc::new(frames);
+    self.entries.insert(id, CachedFrames { frames: frames.clone(), idle: 0. });
+    frames
+  }
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+  /// Emotes already on screen keep their frames until they're gone.
+  pub fn clear(&mut self) {
+    self.entries.clear();
+  }
+  /// Ages entries that only the cache still holds and evicts the ones idle for too long.
+  pub fn update(&mut self, seconds: f32) {
+    for entry in self.entries.values_mut() {
+      if Rc::strong_count(&entry.frames) > 1 {
+        entry.idle = 0.;
+      } else {
+        entry.idle += seconds;
+      }
+    }
+    let idle_ttl = self.idle_ttl;
+    self.entries.retain(|_, entry| entry.idle < idle_ttl );
+  }
+}
@@ -0,0 +1,107 @@
+use {
+  crate::{
+    EmoteSource, fetch_channel_twitch_emotes,
+    config_kdl::EkbTwitchConfig,
+    emote_store::EmoteStore,
+    providers::*,
+  },
+  std::collections::{
+    HashMap, HashSet,
+  },
+  tokio::{
+    sync::mpsc::{
+      UnboundedReceiver, UnboundedSender,
+    },
+    task::JoinSet,
+  },
+};
+
+/// Cached Twitch emotes standing in for a channel's own when Helix can't list them.
+const CACHED_CHANNEL_EMOTES: usize = 32;
+
+/// A channel's emotes fetched in the background, with the channel they're for.
+type LoadedEmotes = (String, Vec<EmoteSource>);
+
+/// Each channel's own Twitch emotes, like its subscriber and bits emotes, for alert bursts.
+/// Helix lists them when authenticated. Anonymous, offline or when Helix fails, the most used
+/// Twitch emotes in the cache stand in, since the cache doesn't know which channel an emote
+/// belongs to.
+pub struct ChannelTwitchEmotes {
+  conf: EkbTwitchConfig,
+  store: EmoteStore,
+  /// Keyed by lowercase channel name.
+  channels: HashMap<String, Vec<EmoteSource>>,
+  /// Channels with a spawned load that hasn't arrived yet.
+  loading: HashSet<String>,
+  loaded_tx: UnboundedSender<LoadedEmotes>,
+  loaded_rx: UnboundedReceiver<LoadedEmotes>,
+}
+
+impl ChannelTwitchEmotes {
+  pub fn new(conf: EkbTwitchConfig, store: EmoteStore) -> Self {
+    let (loaded_tx, loaded_rx) = tokio::sync::mpsc::unbounded_channel();
+    Self { conf, store, channels: HashMap::new(), loading: HashSet::new(), loaded_tx, loaded_rx }
+  }
+  /// True once a load for `channel` was started, even if it hasn't arrived yet.
+  pub fn has_channel(&self, channel: &str) -> bool {
+    self.channels.contains_key(channel) || self.loading.contains(channel)
+  }
+  /// Fetched on `tasks` so chat isn't held up. Used once `receive_loaded` picks it up.
+  pub fn spawn_channel_load(&mut self, tasks: &mut JoinSet<()>, channel: &str, room_id: &str) {
+    if self.has_channel(channel) { return; }
+    self.loading.insert(channel.to_owned());
+    let (conf, store, loaded_tx) = (self.conf.clone(), self.store.clone(), self.loaded_tx.clone());
+    let (channel, room_id) = (channel.to_owned(), room_id.to_owned());
+    tasks.spawn(async move {
+      let listed = match conf.offline() || conf.is_anonymous() {
+        true => { None }
+        false => {
+          match fetch_channel_twitch_emotes(&conf, &room_id).await {
+            Ok(emotes) => { Some(emotes) }
+            Err(e) => {
+              log::warn!("Failed to list #{}'s Twitch emotes, alerts will use cached ones: {}", channel, e);
+              None
+            }
+          }
+        }
+      };
+      let emotes = match listed {
+        Some(emotes) => {
+          log::info!("Loaded {} Twitch emotes for #{}", emotes.len(), channel);
+          emotes
+        }
+        None => { cached_emotes(&store, &conf.providers()).await }
+      };
+      _ = loaded_tx.send((channel, emotes));
+    });
+  }
+  /// Puts emotes from spawned loads that finished since the last call to use.
+  pub fn receive_loaded(&mut self) {
+    while let Ok((channel, emotes)) = self.loaded_rx.try_recv() {
+      self.loading.remove(&channel);
+      self.channels.insert(channel, emotes);
+    }
+  }
+  /// Empty until the channel's load arrives.
+  pub(crate) fn emotes(&self, channel: &str) -> &[EmoteSource] {
+    self.channels.get(channel).map(Vec::as_slice).unwrap_or_default()
+  }
+}
+
+async fn cached_emotes(store: &EmoteStore, config: &ProviderConfig) -> Vec<EmoteSource> {
+  match store.popular_twitch_emotes(CACHED_CHANNEL_EMOTES).await {
+    Ok(emotes) => {
+      emotes.into_iter().map(|(id, name)| EmoteSource {
+        provider: EmoteProvider::Twitch,
+        urls: emote_urls(EmoteProvider::Twitch, &id, config),
+        id,
+        name,
+        image: None,
+      }).collect()
+    }
+    Err(e) => {
+      log::error!("Failed to read cached Twitch emotes: {}", e);
+      vec![]
+    }
+  }
+}